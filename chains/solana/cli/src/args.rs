@@ -0,0 +1,311 @@
+use clap::{Args, Subcommand};
+use solana_sdk::pubkey::Pubkey;
+use std::path::PathBuf;
+use train_solana_client::HashAlgorithm;
+
+/// The instructions of either program. Ids, hashlocks and secrets are 32-byte
+/// hex strings, amounts are in lamports or token base units and timelocks are
+/// unix timestamps in seconds.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Commit funds to src_receiver. The Id is derived from the sender's nonce.
+    Commit {
+        #[arg(long)]
+        src_receiver: Pubkey,
+        #[arg(long)]
+        amount: u64,
+        #[arg(long)]
+        timelock: u64,
+        /// Hash the hashlock will be taken with, sha256 or keccak256.
+        #[arg(long, value_parser = parse_hash_algorithm, default_value = "sha256")]
+        hash_algorithm: HashAlgorithm,
+        /// Lamports for whoever refunds the HTLC once it expires.
+        #[arg(long, default_value_t = 0)]
+        keeper_bounty: u64,
+        /// Mint of the committed tokens (anchor-htlc only).
+        #[arg(long)]
+        mint: Option<Pubkey>,
+        #[command(flatten)]
+        destination: Destination,
+        #[command(flatten)]
+        hops: Hops,
+    },
+    /// Lock funds to src_receiver under a hashlock.
+    Lock {
+        #[arg(long, value_parser = parse_bytes32)]
+        id: [u8; 32],
+        #[arg(long, value_parser = parse_bytes32)]
+        hashlock: [u8; 32],
+        /// Hash the hashlock is taken with, sha256 or keccak256.
+        #[arg(long, value_parser = parse_hash_algorithm, default_value = "sha256")]
+        hash_algorithm: HashAlgorithm,
+        #[arg(long)]
+        src_receiver: Pubkey,
+        #[arg(long)]
+        amount: u64,
+        #[arg(long)]
+        timelock: u64,
+        /// Paid to the redeemer once reward_timelock has passed.
+        #[arg(long, default_value_t = 0)]
+        reward: u64,
+        #[arg(long, default_value_t = 0)]
+        reward_timelock: u64,
+        /// Lamports for whoever refunds the HTLC once it expires.
+        #[arg(long, default_value_t = 0)]
+        keeper_bounty: u64,
+        /// Mint of the locked tokens (anchor-htlc only).
+        #[arg(long)]
+        mint: Option<Pubkey>,
+        #[command(flatten)]
+        destination: Destination,
+    },
+    /// Add the hashlock to a committed HTLC, signed by its sender.
+    AddLock {
+        #[arg(long, value_parser = parse_bytes32)]
+        id: [u8; 32],
+        #[arg(long, value_parser = parse_bytes32)]
+        hashlock: [u8; 32],
+        #[arg(long)]
+        timelock: u64,
+        /// Hash the hashlock is taken with, sha256 or keccak256.
+        #[arg(long, value_parser = parse_hash_algorithm, default_value = "sha256")]
+        hash_algorithm: HashAlgorithm,
+    },
+    /// Add the hashlock on behalf of the sender, with the sender's signature.
+    AddLockSig {
+        #[arg(long)]
+        sender: Pubkey,
+        #[arg(long, value_parser = parse_bytes32)]
+        id: [u8; 32],
+        #[arg(long, value_parser = parse_bytes32)]
+        hashlock: [u8; 32],
+        #[arg(long)]
+        timelock: u64,
+        /// Hash the hashlock is taken with, sha256 or keccak256.
+        #[arg(long, value_parser = parse_hash_algorithm, default_value = "sha256")]
+        hash_algorithm: HashAlgorithm,
+        /// The sender's 64-byte hex ed25519 signature over the addLockSig message.
+        #[arg(long, value_parser = parse_signature, required_unless_present = "sender_keypair")]
+        signature: Option<[u8; 64]>,
+        /// Sign the message with this keypair file instead of passing --signature.
+        #[arg(long, conflicts_with = "signature")]
+        sender_keypair: Option<PathBuf>,
+    },
+    /// Move the timelock of an open HTLC later, signed by its sender.
+    ExtendTimelock {
+        #[arg(long, value_parser = parse_bytes32)]
+        id: [u8; 32],
+        /// The new timelock, after the current one.
+        #[arg(long)]
+        timelock: u64,
+    },
+    /// Redeem an HTLC with its secret.
+    Redeem {
+        #[arg(long)]
+        sender: Pubkey,
+        #[arg(long, value_parser = parse_bytes32)]
+        id: [u8; 32],
+        #[arg(long, value_parser = parse_bytes32)]
+        secret: [u8; 32],
+    },
+    /// Redeem several HTLCs in one transaction, one --sender, --id and --secret
+    /// per HTLC. HTLCs that fail their checks are skipped and reported.
+    RedeemBatch {
+        #[arg(long = "sender", required = true)]
+        senders: Vec<Pubkey>,
+        #[arg(long = "id", value_parser = parse_bytes32, required = true)]
+        ids: Vec<[u8; 32]>,
+        #[arg(long = "secret", value_parser = parse_bytes32, required = true)]
+        secrets: Vec<[u8; 32]>,
+    },
+    /// Refund an HTLC whose timelock has passed, to its sender. Anyone can
+    /// refund; the --keypair signer is paid the keeper bounty.
+    Refund {
+        #[arg(long)]
+        sender: Pubkey,
+        #[arg(long, value_parser = parse_bytes32)]
+        id: [u8; 32],
+    },
+    /// Refund several expired HTLCs of one sender in one transaction, one --id
+    /// per HTLC. HTLCs that are not refundable yet are skipped and reported.
+    RefundBatch {
+        #[arg(long)]
+        sender: Pubkey,
+        #[arg(long = "id", value_parser = parse_bytes32, required = true)]
+        ids: Vec<[u8; 32]>,
+    },
+    /// Redeem an HTLC created at the former [Id] address.
+    RedeemLegacy {
+        #[arg(long, value_parser = parse_bytes32)]
+        id: [u8; 32],
+        #[arg(long, value_parser = parse_bytes32)]
+        secret: [u8; 32],
+    },
+    /// Refund an HTLC created at the former [Id] address.
+    RefundLegacy {
+        #[arg(long, value_parser = parse_bytes32)]
+        id: [u8; 32],
+    },
+    /// Print an HTLC.
+    GetDetails {
+        #[arg(long)]
+        sender: Pubkey,
+        #[arg(long, value_parser = parse_bytes32)]
+        id: [u8; 32],
+    },
+    /// Print one page of the Ids of a sender's HTLCs.
+    GetContracts {
+        #[arg(long)]
+        sender: Pubkey,
+        #[arg(long, default_value_t = 0)]
+        page: u32,
+    },
+    /// Print the Id the sender's next commit has to use.
+    GetCommitId {
+        /// Defaults to the --keypair pubkey.
+        #[arg(long)]
+        sender: Option<Pubkey>,
+        #[arg(long)]
+        src_receiver: Pubkey,
+        #[arg(long)]
+        amount: u64,
+        #[arg(long)]
+        timelock: u64,
+    },
+    /// Create the program's Config with the --keypair signer, the program's
+    /// upgrade authority, as its admin.
+    InitializeConfig,
+    /// Propose a new admin, who takes over with accept-admin. Admin only.
+    TransferAdmin {
+        #[arg(long)]
+        new_admin: Pubkey,
+    },
+    /// Take over the admin role as the --keypair signer, once proposed.
+    AcceptAdmin,
+    /// Block commit and lock. Redeem and refund keep working. Admin only.
+    Pause,
+    /// Allow commit and lock again. Admin only.
+    Unpause,
+    /// Set the protocol fee redeem takes from the amount. Admin only.
+    SetFee {
+        /// The fee in basis points of the amount.
+        #[arg(long)]
+        fee_bps: u16,
+    },
+    /// Set how far ahead of the clock commit, lock and add-lock accept a
+    /// timelock. Admin only.
+    SetTimelockWindow {
+        /// The shortest timelock window, in seconds.
+        #[arg(long)]
+        min_window: u64,
+        /// The longest timelock window, in seconds.
+        #[arg(long)]
+        max_window: u64,
+    },
+    /// Withdraw the collected protocol fees. Admin only.
+    WithdrawFees {
+        /// Receives the fees, or its associated token account for anchor-htlc.
+        /// Defaults to the --keypair pubkey.
+        #[arg(long)]
+        recipient: Option<Pubkey>,
+        /// Mint whose fee vault to empty (anchor-htlc only).
+        #[arg(long)]
+        mint: Option<Pubkey>,
+    },
+    /// Print the program's Config.
+    GetConfig,
+    /// Accept a mint in commit and lock, with the src_asset they have to use.
+    /// Admin only, anchor-htlc only.
+    AllowMint {
+        #[arg(long)]
+        mint: Pubkey,
+        /// The mint's canonical symbol, e.g. USDC.
+        #[arg(long)]
+        src_asset: String,
+    },
+    /// Stop accepting a mint in commit and lock. Admin only, anchor-htlc only.
+    RemoveMint {
+        #[arg(long)]
+        mint: Pubkey,
+    },
+    /// Print the src_asset a mint is accepted with. anchor-htlc only.
+    GetAllowedMint {
+        #[arg(long)]
+        mint: Pubkey,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct Destination {
+    #[arg(long)]
+    pub dst_chain: String,
+    #[arg(long)]
+    pub dst_asset: String,
+    #[arg(long)]
+    pub dst_address: String,
+    #[arg(long)]
+    pub src_asset: String,
+}
+
+/// The hop route, one --hop-chain, --hop-asset and --hop-address per hop.
+#[derive(Args, Debug)]
+pub struct Hops {
+    #[arg(long = "hop-chain")]
+    pub chains: Vec<String>,
+    #[arg(long = "hop-asset")]
+    pub assets: Vec<String>,
+    #[arg(long = "hop-address")]
+    pub addresses: Vec<String>,
+}
+
+fn parse_hex<const N: usize>(value: &str) -> Result<[u8; N], String> {
+    let value = value.strip_prefix("0x").unwrap_or(value);
+    let bytes = hex::decode(value).map_err(|err| err.to_string())?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| format!("expected {N} bytes, got {}", bytes.len()))
+}
+
+pub fn parse_bytes32(value: &str) -> Result<[u8; 32], String> {
+    parse_hex(value)
+}
+
+pub fn parse_signature(value: &str) -> Result<[u8; 64], String> {
+    parse_hex(value)
+}
+
+pub fn parse_hash_algorithm(value: &str) -> Result<HashAlgorithm, String> {
+    match value {
+        "sha256" => Ok(HashAlgorithm::Sha256),
+        "keccak256" => Ok(HashAlgorithm::Keccak256),
+        _ => Err(format!("expected sha256 or keccak256, got {value}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_with_or_without_prefix() {
+        let hex = "2a".repeat(32);
+        assert_eq!(parse_bytes32(&hex), Ok([42u8; 32]));
+        assert_eq!(parse_bytes32(&format!("0x{hex}")), Ok([42u8; 32]));
+        assert_eq!(
+            parse_bytes32("2a2a"),
+            Err("expected 32 bytes, got 2".to_string())
+        );
+        assert!(parse_bytes32("zz").is_err());
+        assert_eq!(parse_signature(&"01".repeat(64)), Ok([1u8; 64]));
+    }
+
+    #[test]
+    fn parses_hash_algorithms() {
+        assert_eq!(parse_hash_algorithm("sha256"), Ok(HashAlgorithm::Sha256));
+        assert_eq!(
+            parse_hash_algorithm("keccak256"),
+            Ok(HashAlgorithm::Keccak256)
+        );
+        assert!(parse_hash_algorithm("blake3").is_err());
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// The cluster to talk to, by moniker as in the solana CLI or by RPC URL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cluster {
+    Localnet,
+    Devnet,
+    Testnet,
+    MainnetBeta,
+    Url(String),
+}
+
+impl Cluster {
+    pub fn url(&self) -> &str {
+        match self {
+            Cluster::Localnet => "http://127.0.0.1:8899",
+            Cluster::Devnet => "https://api.devnet.solana.com",
+            Cluster::Testnet => "https://api.testnet.solana.com",
+            Cluster::MainnetBeta => "https://api.mainnet-beta.solana.com",
+            Cluster::Url(url) => url,
+        }
+    }
+}
+
+impl FromStr for Cluster {
+    type Err = String;
+
+    fn from_str(cluster: &str) -> Result<Self, Self::Err> {
+        match cluster {
+            "localnet" | "localhost" | "l" => Ok(Cluster::Localnet),
+            "devnet" | "d" => Ok(Cluster::Devnet),
+            "testnet" | "t" => Ok(Cluster::Testnet),
+            "mainnet-beta" | "m" => Ok(Cluster::MainnetBeta),
+            url if url.starts_with("http://") || url.starts_with("https://") => {
+                Ok(Cluster::Url(url.to_string()))
+            }
+            _ => Err(format!(
+                "expected localnet, devnet, testnet, mainnet-beta or an http(s) URL, got {cluster}"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Cluster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.url())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_monikers_and_urls() {
+        assert_eq!("localnet".parse(), Ok(Cluster::Localnet));
+        assert_eq!("l".parse(), Ok(Cluster::Localnet));
+        assert_eq!("mainnet-beta".parse(), Ok(Cluster::MainnetBeta));
+        assert_eq!(
+            "http://10.0.0.1:8899".parse(),
+            Ok(Cluster::Url("http://10.0.0.1:8899".to_string()))
+        );
+        assert_eq!(Cluster::Devnet.url(), "https://api.devnet.solana.com");
+        assert!("mainnet".parse::<Cluster>().is_err());
+    }
+}
@@ -0,0 +1,710 @@
+use anyhow::{anyhow, bail, Result};
+use serde_json::{json, Value};
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use train_solana_client::{
+    add_lock_sig_message, anchor_htlc, commit_id, decode_batch_results, decode_config,
+    decode_contract_ids, decode_current_page, decode_nonce, native_htlc, pda, CommitParams,
+    HashAlgorithm, LockParams,
+};
+
+use crate::args::{Command, Destination, Hops};
+use crate::context::{read_keypair, Context};
+
+/// The compute unit limit batches are simulated with, the most a transaction
+/// may request.
+const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// The program a command is sent to.
+#[derive(Clone, Copy, Debug)]
+pub enum Program {
+    NativeHtlc,
+    AnchorHtlc,
+}
+
+impl Program {
+    fn id(self) -> Pubkey {
+        match self {
+            Program::NativeHtlc => native_htlc::PROGRAM_ID,
+            Program::AnchorHtlc => anchor_htlc::PROGRAM_ID,
+        }
+    }
+
+    /// Fails for native_htlc, which only locks lamports and so has no mint
+    /// allowlist.
+    fn require_anchor_htlc(self) -> Result<()> {
+        match self {
+            Program::NativeHtlc => bail!("native-htlc locks lamports and has no mint allowlist"),
+            Program::AnchorHtlc => Ok(()),
+        }
+    }
+
+    /// The mint for anchor_htlc, which requires one, and none for native_htlc.
+    fn mint(self, mint: Option<Pubkey>) -> Result<Option<Pubkey>> {
+        match (self, mint) {
+            (Program::NativeHtlc, None) => Ok(None),
+            (Program::NativeHtlc, Some(_)) => bail!("native-htlc locks lamports, drop --mint"),
+            (Program::AnchorHtlc, None) => bail!("anchor-htlc requires --mint"),
+            (Program::AnchorHtlc, mint) => Ok(mint),
+        }
+    }
+}
+
+/// Runs the command and returns what to print.
+pub fn run(ctx: &Context, program: Program, command: Command) -> Result<Value> {
+    match command {
+        Command::Commit {
+            src_receiver,
+            amount,
+            timelock,
+            hash_algorithm,
+            keeper_bounty,
+            mint,
+            destination,
+            hops,
+        } => {
+            let mint = program.mint(mint)?;
+            let sender = ctx.signer()?;
+            let nonce = nonce(ctx, program, &sender.pubkey())?;
+            let Id = commit_id(
+                &program.id(),
+                &sender.pubkey(),
+                &src_receiver,
+                amount,
+                timelock,
+                nonce,
+            );
+            let Destination {
+                dst_chain,
+                dst_asset,
+                dst_address,
+                src_asset,
+            } = destination;
+            let Hops {
+                chains,
+                assets,
+                addresses,
+            } = hops;
+            let params = CommitParams {
+                Id,
+                hop_chains: chains,
+                hop_assets: assets,
+                hop_addresses: addresses,
+                dst_chain,
+                dst_asset,
+                dst_address,
+                src_asset,
+                src_receiver,
+                timelock,
+                amount,
+                hash_algorithm,
+                keeper_bounty,
+            };
+            let page = current_page(ctx, program, &sender.pubkey())?;
+            let instruction = match mint {
+                None => native_htlc::commit(&sender.pubkey(), page, &params),
+                Some(mint) => {
+                    let token_program = ctx.account_owner(&mint)?;
+                    anchor_htlc::commit(&sender.pubkey(), page, &mint, &token_program, &params)
+                }
+            };
+            sent(ctx.send(&[instruction], &[&sender])?, Id)
+        }
+        Command::Lock {
+            id,
+            hashlock,
+            hash_algorithm,
+            src_receiver,
+            amount,
+            timelock,
+            reward,
+            reward_timelock,
+            keeper_bounty,
+            mint,
+            destination,
+        } => {
+            let mint = program.mint(mint)?;
+            let sender = ctx.signer()?;
+            let params = LockParams {
+                Id: id,
+                hashlock,
+                timelock,
+                amount,
+                reward,
+                reward_timelock,
+                dst_chain: destination.dst_chain,
+                dst_address: destination.dst_address,
+                dst_asset: destination.dst_asset,
+                src_asset: destination.src_asset,
+                src_receiver,
+                hash_algorithm,
+                keeper_bounty,
+            };
+            let page = current_page(ctx, program, &sender.pubkey())?;
+            let instruction = match mint {
+                None => native_htlc::lock(&sender.pubkey(), page, &params),
+                Some(mint) => {
+                    let token_program = ctx.account_owner(&mint)?;
+                    anchor_htlc::lock(&sender.pubkey(), page, &mint, &token_program, &params)
+                }
+            };
+            sent(ctx.send(&[instruction], &[&sender])?, id)
+        }
+        Command::AddLock {
+            id,
+            hashlock,
+            timelock,
+            hash_algorithm,
+        } => {
+            let sender = ctx.signer()?;
+            let instruction = match program {
+                Program::NativeHtlc => native_htlc::add_lock(
+                    &sender.pubkey(),
+                    &sender.pubkey(),
+                    id,
+                    hashlock,
+                    timelock,
+                    hash_algorithm,
+                ),
+                Program::AnchorHtlc => {
+                    anchor_htlc::add_lock(&sender.pubkey(), id, hashlock, timelock, hash_algorithm)
+                }
+            };
+            sent(ctx.send(&[instruction], &[&sender])?, id)
+        }
+        Command::AddLockSig {
+            sender,
+            id,
+            hashlock,
+            timelock,
+            hash_algorithm,
+            signature,
+            sender_keypair,
+        } => {
+            let signature = match (signature, sender_keypair) {
+                (Some(signature), _) => signature,
+                (None, Some(path)) => {
+                    let keypair = read_keypair(&path)?;
+                    if keypair.pubkey() != sender {
+                        bail!("{} is not the keypair of {sender}", path.display());
+                    }
+                    sign_add_lock(&keypair, program, id, hashlock, timelock, hash_algorithm)
+                }
+                (None, None) => bail!("pass --signature or --sender-keypair"),
+            };
+            let payer = ctx.signer()?;
+            let instructions = match program {
+                Program::NativeHtlc => native_htlc::add_lock_sig(
+                    &payer.pubkey(),
+                    &sender,
+                    id,
+                    hashlock,
+                    timelock,
+                    hash_algorithm,
+                    signature,
+                ),
+                Program::AnchorHtlc => anchor_htlc::add_lock_sig(
+                    &payer.pubkey(),
+                    &sender,
+                    id,
+                    hashlock,
+                    timelock,
+                    hash_algorithm,
+                    signature,
+                ),
+            };
+            sent(ctx.send(&instructions, &[&payer])?, id)
+        }
+        Command::ExtendTimelock { id, timelock } => {
+            let sender = ctx.signer()?;
+            let instruction = match program {
+                Program::NativeHtlc => native_htlc::extend_timelock(&sender.pubkey(), id, timelock),
+                Program::AnchorHtlc => anchor_htlc::extend_timelock(&sender.pubkey(), id, timelock),
+            };
+            sent(ctx.send(&[instruction], &[&sender])?, id)
+        }
+        Command::Redeem { sender, id, secret } => {
+            let signer = ctx.signer()?;
+            let data = htlc_data(ctx, pda::htlc(&program.id(), &sender, &id).0)?;
+            let instruction = match program {
+                Program::NativeHtlc => {
+                    let htlc = native_htlc::decode_htlc(&data)?;
+                    native_htlc::redeem(&signer.pubkey(), id, &htlc, secret)
+                }
+                Program::AnchorHtlc => {
+                    let htlc = anchor_htlc::decode_htlc(&data)?;
+                    let token_program = ctx.account_owner(&htlc.token_contract)?;
+                    anchor_htlc::redeem(&signer.pubkey(), id, &htlc, secret, &token_program)
+                }
+            };
+            sent(ctx.send(&[instruction], &[&signer])?, id)
+        }
+        Command::RedeemBatch {
+            senders,
+            ids,
+            secrets,
+        } => {
+            if senders.len() != ids.len() || ids.len() != secrets.len() {
+                bail!("pass one --sender, --id and --secret per HTLC");
+            }
+            let signer = ctx.signer()?;
+            let htlcs = senders
+                .iter()
+                .zip(&ids)
+                .map(|(sender, id)| htlc_data(ctx, pda::htlc(&program.id(), sender, id).0))
+                .collect::<Result<Vec<_>>>()?;
+            let instruction = match program {
+                Program::NativeHtlc => {
+                    let htlcs = htlcs
+                        .iter()
+                        .map(|data| native_htlc::decode_htlc(data))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let items: Vec<_> = ids
+                        .iter()
+                        .zip(&htlcs)
+                        .zip(&secrets)
+                        .map(|((id, htlc), secret)| (*id, htlc, *secret))
+                        .collect();
+                    native_htlc::redeem_batch(&signer.pubkey(), &items)
+                }
+                Program::AnchorHtlc => {
+                    let htlcs = htlcs
+                        .iter()
+                        .map(|data| anchor_htlc::decode_htlc(data))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let token_program = batch_token_program(ctx, &htlcs)?;
+                    let items: Vec<_> = ids
+                        .iter()
+                        .zip(&htlcs)
+                        .zip(&secrets)
+                        .map(|((id, htlc), secret)| (*id, htlc, *secret))
+                        .collect();
+                    anchor_htlc::redeem_batch(&signer.pubkey(), &items, &token_program)
+                }
+            };
+            send_batch(ctx, &signer, instruction, &ids, "redeemed")
+        }
+        Command::Refund { sender, id } => {
+            let signer = ctx.signer()?;
+            let data = htlc_data(ctx, pda::htlc(&program.id(), &sender, &id).0)?;
+            let instruction = match program {
+                Program::NativeHtlc => {
+                    let htlc = native_htlc::decode_htlc(&data)?;
+                    native_htlc::refund(&signer.pubkey(), id, &htlc)
+                }
+                Program::AnchorHtlc => {
+                    let htlc = anchor_htlc::decode_htlc(&data)?;
+                    let token_program = ctx.account_owner(&htlc.token_contract)?;
+                    anchor_htlc::refund(&signer.pubkey(), id, &htlc, &token_program)
+                }
+            };
+            sent(ctx.send(&[instruction], &[&signer])?, id)
+        }
+        Command::RefundBatch { sender, ids } => {
+            let signer = ctx.signer()?;
+            let htlcs = ids
+                .iter()
+                .map(|id| htlc_data(ctx, pda::htlc(&program.id(), &sender, id).0))
+                .collect::<Result<Vec<_>>>()?;
+            let instruction = match program {
+                Program::NativeHtlc => {
+                    let htlcs = htlcs
+                        .iter()
+                        .map(|data| native_htlc::decode_htlc(data))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let items: Vec<_> = ids.iter().copied().zip(&htlcs).collect();
+                    native_htlc::refund_batch(&signer.pubkey(), &sender, &items)
+                }
+                Program::AnchorHtlc => {
+                    let htlcs = htlcs
+                        .iter()
+                        .map(|data| anchor_htlc::decode_htlc(data))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let token_program = batch_token_program(ctx, &htlcs)?;
+                    let items: Vec<_> = ids.iter().copied().zip(&htlcs).collect();
+                    anchor_htlc::refund_batch(&signer.pubkey(), &sender, &items, &token_program)
+                }
+            };
+            send_batch(ctx, &signer, instruction, &ids, "refunded")
+        }
+        Command::RedeemLegacy { id, secret } => {
+            let signer = ctx.signer()?;
+            let data = htlc_data(ctx, pda::legacy_htlc(&program.id(), &id))?;
+            let instruction = match program {
+                Program::NativeHtlc => {
+                    let htlc = native_htlc::decode_legacy_htlc(&data)?;
+                    native_htlc::redeem_legacy(&signer.pubkey(), id, &htlc, secret)
+                }
+                Program::AnchorHtlc => {
+                    let htlc = anchor_htlc::decode_legacy_htlc(&data)?;
+                    let token_program = ctx.account_owner(&htlc.token_contract)?;
+                    anchor_htlc::redeem_legacy(&signer.pubkey(), id, &htlc, secret, &token_program)
+                }
+            };
+            sent(ctx.send(&[instruction], &[&signer])?, id)
+        }
+        Command::RefundLegacy { id } => {
+            let signer = ctx.signer()?;
+            let data = htlc_data(ctx, pda::legacy_htlc(&program.id(), &id))?;
+            let instruction = match program {
+                Program::NativeHtlc => {
+                    let htlc = native_htlc::decode_legacy_htlc(&data)?;
+                    native_htlc::refund_legacy(&signer.pubkey(), id, &htlc)
+                }
+                Program::AnchorHtlc => {
+                    let htlc = anchor_htlc::decode_legacy_htlc(&data)?;
+                    let token_program = ctx.account_owner(&htlc.token_contract)?;
+                    anchor_htlc::refund_legacy(&signer.pubkey(), id, &htlc, &token_program)
+                }
+            };
+            sent(ctx.send(&[instruction], &[&signer])?, id)
+        }
+        Command::GetDetails { sender, id } => {
+            let data = htlc_data(ctx, pda::htlc(&program.id(), &sender, &id).0)?;
+            match program {
+                Program::NativeHtlc => Ok(native_details(&native_htlc::decode_htlc(&data)?)),
+                Program::AnchorHtlc => Ok(anchor_details(&anchor_htlc::decode_htlc(&data)?)),
+            }
+        }
+        Command::GetContracts { sender, page } => {
+            let ids = match ctx.account_data(&pda::contracts_page(&program.id(), &sender, page))? {
+                Some(data) => decode_contract_ids(&data)?,
+                None => Vec::new(),
+            };
+            Ok(json!({
+                "page": page,
+                "ids": ids.iter().map(hex32).collect::<Vec<_>>(),
+            }))
+        }
+        Command::GetCommitId {
+            sender,
+            src_receiver,
+            amount,
+            timelock,
+        } => {
+            let sender = match sender {
+                Some(sender) => sender,
+                None => ctx.signer()?.pubkey(),
+            };
+            let nonce = nonce(ctx, program, &sender)?;
+            let Id = commit_id(
+                &program.id(),
+                &sender,
+                &src_receiver,
+                amount,
+                timelock,
+                nonce,
+            );
+            Ok(json!({ "Id": hex32(&Id), "nonce": nonce }))
+        }
+        Command::InitializeConfig => {
+            let admin = ctx.signer()?;
+            let instruction = match program {
+                Program::NativeHtlc => native_htlc::initialize_config(&admin.pubkey()),
+                Program::AnchorHtlc => anchor_htlc::initialize_config(&admin.pubkey()),
+            };
+            signed(ctx.send(&[instruction], &[&admin])?)
+        }
+        Command::TransferAdmin { new_admin } => {
+            let admin = ctx.signer()?;
+            let instruction = match program {
+                Program::NativeHtlc => native_htlc::transfer_admin(&admin.pubkey(), &new_admin),
+                Program::AnchorHtlc => anchor_htlc::transfer_admin(&admin.pubkey(), &new_admin),
+            };
+            signed(ctx.send(&[instruction], &[&admin])?)
+        }
+        Command::AcceptAdmin => {
+            let pending_admin = ctx.signer()?;
+            let instruction = match program {
+                Program::NativeHtlc => native_htlc::accept_admin(&pending_admin.pubkey()),
+                Program::AnchorHtlc => anchor_htlc::accept_admin(&pending_admin.pubkey()),
+            };
+            signed(ctx.send(&[instruction], &[&pending_admin])?)
+        }
+        Command::Pause => set_paused(ctx, program, true),
+        Command::Unpause => set_paused(ctx, program, false),
+        Command::SetFee { fee_bps } => {
+            let admin = ctx.signer()?;
+            let instruction = match program {
+                Program::NativeHtlc => native_htlc::set_fee(&admin.pubkey(), fee_bps),
+                Program::AnchorHtlc => anchor_htlc::set_fee(&admin.pubkey(), fee_bps),
+            };
+            signed(ctx.send(&[instruction], &[&admin])?)
+        }
+        Command::SetTimelockWindow {
+            min_window,
+            max_window,
+        } => {
+            let admin = ctx.signer()?;
+            let instruction = match program {
+                Program::NativeHtlc => {
+                    native_htlc::set_timelock_window(&admin.pubkey(), min_window, max_window)
+                }
+                Program::AnchorHtlc => {
+                    anchor_htlc::set_timelock_window(&admin.pubkey(), min_window, max_window)
+                }
+            };
+            signed(ctx.send(&[instruction], &[&admin])?)
+        }
+        Command::WithdrawFees { recipient, mint } => {
+            let mint = program.mint(mint)?;
+            let admin = ctx.signer()?;
+            let recipient = recipient.unwrap_or_else(|| admin.pubkey());
+            let instruction = match mint {
+                None => native_htlc::withdraw_fees(&admin.pubkey(), &recipient),
+                Some(mint) => {
+                    let token_program = ctx.account_owner(&mint)?;
+                    let recipient_token_account =
+                        pda::associated_token_account(&recipient, &mint, &token_program);
+                    anchor_htlc::withdraw_fees(
+                        &admin.pubkey(),
+                        &mint,
+                        &recipient_token_account,
+                        &token_program,
+                    )
+                }
+            };
+            signed(ctx.send(&[instruction], &[&admin])?)
+        }
+        Command::GetConfig => {
+            let address = pda::config(&program.id());
+            let data = ctx
+                .account_data(&address)?
+                .ok_or_else(|| anyhow!("no Config at {address}, run initialize-config"))?;
+            let config = decode_config(&data)?;
+            Ok(json!({
+                "admin": config.admin.to_string(),
+                "pending_admin": config.pending_admin.map(|admin| admin.to_string()),
+                "paused": config.paused,
+                "fee_bps": config.fee_bps,
+                "min_timelock_window": config.min_timelock_window,
+                "max_timelock_window": config.max_timelock_window,
+            }))
+        }
+        Command::AllowMint { mint, src_asset } => {
+            program.require_anchor_htlc()?;
+            let admin = ctx.signer()?;
+            let instruction = anchor_htlc::allow_mint(&admin.pubkey(), &mint, &src_asset);
+            signed(ctx.send(&[instruction], &[&admin])?)
+        }
+        Command::RemoveMint { mint } => {
+            program.require_anchor_htlc()?;
+            let admin = ctx.signer()?;
+            let instruction = anchor_htlc::remove_mint(&admin.pubkey(), &mint);
+            signed(ctx.send(&[instruction], &[&admin])?)
+        }
+        Command::GetAllowedMint { mint } => {
+            program.require_anchor_htlc()?;
+            let address = pda::allowed_mint(&program.id(), &mint);
+            let data = ctx
+                .account_data(&address)?
+                .ok_or_else(|| anyhow!("{mint} is not allowed, run allow-mint"))?;
+            let allowed_mint = anchor_htlc::decode_allowed_mint(&data)?;
+            Ok(json!({ "src_asset": allowed_mint.src_asset }))
+        }
+    }
+}
+
+/// Pauses or resumes commit and lock, signed by the admin.
+fn set_paused(ctx: &Context, program: Program, paused: bool) -> Result<Value> {
+    let admin = ctx.signer()?;
+    let instruction = match program {
+        Program::NativeHtlc => native_htlc::set_paused(&admin.pubkey(), paused),
+        Program::AnchorHtlc => anchor_htlc::set_paused(&admin.pubkey(), paused),
+    };
+    signed(ctx.send(&[instruction], &[&admin])?)
+}
+
+/// The page the sender's next HTLC is indexed in.
+fn current_page(ctx: &Context, program: Program, sender: &Pubkey) -> Result<u32> {
+    match ctx.account_data(&pda::contracts_index(&program.id(), sender))? {
+        Some(data) => Ok(decode_current_page(&data)?),
+        None => Ok(0),
+    }
+}
+
+/// The sender's commit nonce, zero before their first commit.
+fn nonce(ctx: &Context, program: Program, sender: &Pubkey) -> Result<u64> {
+    match ctx.account_data(&pda::sender_nonce(&program.id(), sender))? {
+        Some(data) => Ok(decode_nonce(&data)?),
+        None => Ok(0),
+    }
+}
+
+/// The token program of the mints of a batch, which must all share one.
+fn batch_token_program(ctx: &Context, htlcs: &[anchor_htlc::HTLC]) -> Result<Pubkey> {
+    let token_program = ctx.account_owner(&htlcs[0].token_contract)?;
+    for htlc in &htlcs[1..] {
+        if ctx.account_owner(&htlc.token_contract)? != token_program {
+            bail!("all mints of a batch must be of the same token program");
+        }
+    }
+    Ok(token_program)
+}
+
+/// Simulates a batch instruction at the maximum compute unit limit, sends it
+/// with the limit the simulation used and reports each item as `done` or with
+/// the error it was skipped with.
+fn send_batch(
+    ctx: &Context,
+    signer: &Keypair,
+    instruction: Instruction,
+    ids: &[[u8; 32]],
+    done: &str,
+) -> Result<Value> {
+    let (units, return_data) = ctx.simulate(
+        &[
+            ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT),
+            instruction.clone(),
+        ],
+        &[signer],
+    )?;
+    let results = decode_batch_results(&return_data)
+        .ok_or_else(|| anyhow!("the batch returned no results"))?;
+    let limit = (units + units / 10).min(MAX_COMPUTE_UNIT_LIMIT.into()) as u32;
+    let signature = ctx.send(
+        &[
+            ComputeBudgetInstruction::set_compute_unit_limit(limit),
+            instruction,
+        ],
+        &[signer],
+    )?;
+    Ok(json!({
+        "signature": signature.to_string(),
+        "results": ids
+            .iter()
+            .zip(results)
+            .map(|(id, result)| match result {
+                None => json!({ "Id": hex32(id), done: true }),
+                Some(error) => json!({
+                    "Id": hex32(id),
+                    done: false,
+                    "error": error.to_string(),
+                }),
+            })
+            .collect::<Vec<_>>(),
+    }))
+}
+
+fn htlc_data(ctx: &Context, address: Pubkey) -> Result<Vec<u8>> {
+    ctx.account_data(&address)?
+        .ok_or_else(|| anyhow!("no HTLC at {address}"))
+}
+
+/// The sender's signature over the `add_lock_sig` message.
+pub fn sign_add_lock(
+    sender: &Keypair,
+    program: Program,
+    Id: [u8; 32],
+    hashlock: [u8; 32],
+    timelock: u64,
+    hash_algorithm: HashAlgorithm,
+) -> [u8; 64] {
+    let message = add_lock_sig_message(
+        &program.id().to_bytes(),
+        &Id,
+        &hashlock,
+        timelock,
+        hash_algorithm,
+    );
+    sender.sign_message(&message).into()
+}
+
+fn sent(signature: solana_sdk::signature::Signature, Id: [u8; 32]) -> Result<Value> {
+    Ok(json!({ "signature": signature.to_string(), "Id": hex32(&Id) }))
+}
+
+fn signed(signature: solana_sdk::signature::Signature) -> Result<Value> {
+    Ok(json!({ "signature": signature.to_string() }))
+}
+
+fn hex32(bytes: &[u8; 32]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+fn native_details(htlc: &native_htlc::HTLC) -> Value {
+    json!({
+        "dst_address": htlc.dst_address,
+        "dst_chain": htlc.dst_chain,
+        "dst_asset": htlc.dst_asset,
+        "src_asset": htlc.src_asset,
+        "sender": htlc.sender.to_string(),
+        "src_receiver": htlc.src_receiver.to_string(),
+        "payer": htlc.payer.to_string(),
+        "hop_chains": htlc.hop_chains,
+        "hop_assets": htlc.hop_assets,
+        "hop_addresses": htlc.hop_addresses,
+        "hashlock": hex32(&htlc.hashlock),
+        "hash_algorithm": format!("{:?}", htlc.hash_algorithm),
+        "secret": hex32(&htlc.secret),
+        "amount": htlc.amount,
+        "timelock": htlc.timelock,
+        "reward": htlc.reward,
+        "reward_timelock": htlc.reward_timelock,
+        "keeper_bounty": htlc.keeper_bounty,
+        "fee_bps": htlc.fee_bps,
+        "index_page": htlc.index_page,
+        "status": format!("{:?}", htlc.status),
+    })
+}
+
+fn anchor_details(htlc: &anchor_htlc::HTLC) -> Value {
+    json!({
+        "dst_address": htlc.dst_address,
+        "dst_chain": htlc.dst_chain,
+        "dst_asset": htlc.dst_asset,
+        "src_asset": htlc.src_asset,
+        "sender": htlc.sender.to_string(),
+        "src_receiver": htlc.src_receiver.to_string(),
+        "payer": htlc.payer.to_string(),
+        "hop_chains": htlc.hop_chains,
+        "hop_assets": htlc.hop_assets,
+        "hop_addresses": htlc.hop_addresses,
+        "hashlock": hex32(&htlc.hashlock),
+        "hash_algorithm": format!("{:?}", htlc.hash_algorithm),
+        "secret": hex32(&htlc.secret),
+        "amount": htlc.amount,
+        "timelock": htlc.timelock,
+        "reward": htlc.reward,
+        "reward_timelock": htlc.reward_timelock,
+        "keeper_bounty": htlc.keeper_bounty,
+        "fee_bps": htlc.fee_bps,
+        "token_contract": htlc.token_contract.to_string(),
+        "token_wallet": htlc.token_wallet.to_string(),
+        "index_page": htlc.index_page,
+        "status": format!("{:?}", htlc.status),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Signature;
+
+    #[test]
+    fn signs_the_add_lock_sig_message() {
+        let sender = Keypair::new();
+        let (Id, hashlock) = ([1u8; 32], [2u8; 32]);
+        let signature = sign_add_lock(
+            &sender,
+            Program::AnchorHtlc,
+            Id,
+            hashlock,
+            99,
+            HashAlgorithm::Keccak256,
+        );
+        let verifies = |program: &Pubkey, hash_algorithm| {
+            let message =
+                add_lock_sig_message(&program.to_bytes(), &Id, &hashlock, 99, hash_algorithm);
+            Signature::from(signature).verify(&sender.pubkey().to_bytes(), &message)
+        };
+        assert!(verifies(&anchor_htlc::PROGRAM_ID, HashAlgorithm::Keccak256));
+        assert!(!verifies(&anchor_htlc::PROGRAM_ID, HashAlgorithm::Sha256));
+        assert!(!verifies(
+            &native_htlc::PROGRAM_ID,
+            HashAlgorithm::Keccak256
+        ));
+    }
+}
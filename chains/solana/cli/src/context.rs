@@ -0,0 +1,122 @@
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signature, Signer};
+use solana_sdk::transaction::{Transaction, TransactionError};
+use std::path::{Path, PathBuf};
+
+use crate::cluster::Cluster;
+
+/// The RPC connection and the keypair transactions are signed with.
+pub struct Context {
+    rpc: RpcClient,
+    keypair: PathBuf,
+}
+
+impl Context {
+    pub fn new(cluster: &Cluster, keypair: PathBuf) -> Self {
+        Context {
+            rpc: RpcClient::new_with_commitment(cluster.url(), CommitmentConfig::confirmed()),
+            keypair,
+        }
+    }
+
+    /// The `--keypair` signer. Read only by the commands that need it, so the
+    /// queries work without a keypair file.
+    pub fn signer(&self) -> Result<Keypair> {
+        read_keypair(&self.keypair)
+    }
+
+    /// The data of an account, or `None` if it does not exist.
+    pub fn account_data(&self, address: &Pubkey) -> Result<Option<Vec<u8>>> {
+        Ok(self.account(address)?.map(|account| account.data))
+    }
+
+    /// The program owning an account, which for a mint is its token program.
+    pub fn account_owner(&self, address: &Pubkey) -> Result<Pubkey> {
+        self.account(address)?
+            .map(|account| account.owner)
+            .ok_or_else(|| anyhow!("account {address} not found"))
+    }
+
+    fn account(&self, address: &Pubkey) -> Result<Option<solana_sdk::account::Account>> {
+        Ok(self
+            .rpc
+            .get_account_with_commitment(address, self.rpc.commitment())
+            .map_err(|err| anyhow!("failed to fetch account {address}: {err}"))?
+            .value)
+    }
+
+    /// Sends the instructions in one transaction paid by the first signer.
+    /// A program error is reported by its `HTLCError` name.
+    pub fn send(&self, instructions: &[Instruction], signers: &[&Keypair]) -> Result<Signature> {
+        let transaction = self.transaction(instructions, signers)?;
+        self.rpc
+            .send_and_confirm_transaction(&transaction)
+            .map_err(|err| match err.get_transaction_error() {
+                Some(error) => transaction_error(error).unwrap_or_else(|| anyhow!("{err}")),
+                None => anyhow!("{err}"),
+            })
+    }
+
+    /// Simulates the transaction `send` would send and returns the compute
+    /// units it consumed and the return data of its last instruction.
+    pub fn simulate(
+        &self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<(u64, Vec<u8>)> {
+        let transaction = self.transaction(instructions, signers)?;
+        let simulation = self
+            .rpc
+            .simulate_transaction(&transaction)
+            .map_err(|err| anyhow!("failed to simulate the transaction: {err}"))?
+            .value;
+        if let Some(error) = simulation.err {
+            return Err(transaction_error(error.clone()).unwrap_or_else(|| anyhow!("{error}")));
+        }
+        let return_data = match simulation.return_data {
+            Some(return_data) => base64::engine::general_purpose::STANDARD
+                .decode(&return_data.data.0)
+                .map_err(|err| anyhow!("invalid return data: {err}"))?,
+            None => Vec::new(),
+        };
+        Ok((simulation.units_consumed.unwrap_or_default(), return_data))
+    }
+
+    fn transaction(
+        &self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<Transaction> {
+        let blockhash = self
+            .rpc
+            .get_latest_blockhash()
+            .map_err(|err| anyhow!("failed to fetch a blockhash: {err}"))?;
+        Ok(Transaction::new_signed_with_payer(
+            instructions,
+            Some(&signers[0].pubkey()),
+            signers,
+            blockhash,
+        ))
+    }
+}
+
+/// A failed instruction named by its `HTLCError`, if it returned a custom code.
+fn transaction_error(error: TransactionError) -> Option<anyhow::Error> {
+    match error {
+        TransactionError::InstructionError(index, error) => {
+            train_solana_client::Error::from_instruction_error(&error)
+                .map(|error| anyhow!("instruction {index} failed: {error}"))
+        }
+        _ => None,
+    }
+}
+
+pub fn read_keypair(path: &Path) -> Result<Keypair> {
+    read_keypair_file(path)
+        .map_err(|err| anyhow!("failed to read keypair {}: {err}", path.display()))
+}
@@ -0,0 +1,140 @@
+//! `train-solana`: sends the instructions of the Train Solana HTLC programs and
+//! reads their accounts, printing the result as JSON.
+//!
+//! ```text
+//! train-solana --cluster devnet native-htlc lock --id 0x.. --hashlock 0x.. ...
+//! train-solana anchor-htlc get-details --sender <PUBKEY> --id 0x..
+//! ```
+#![allow(non_snake_case)]
+
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+mod args;
+mod cluster;
+mod commands;
+mod context;
+
+use args::Command;
+use cluster::Cluster;
+use commands::Program;
+use context::Context;
+
+#[derive(Parser, Debug)]
+#[command(name = "train-solana", version, about)]
+struct Cli {
+    /// localnet, devnet, testnet, mainnet-beta or an RPC URL.
+    #[arg(short = 'u', long, global = true, default_value = "localnet")]
+    cluster: Cluster,
+    /// Keypair file that signs and pays. Defaults to the solana CLI keypair.
+    #[arg(short, long, global = true)]
+    keypair: Option<PathBuf>,
+    #[command(subcommand)]
+    program: ProgramCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum ProgramCommand {
+    /// native_htlc (`sol`), which locks lamports.
+    #[command(subcommand, alias = "sol")]
+    NativeHtlc(Command),
+    /// anchor_htlc (`native`), which locks SPL tokens.
+    #[command(subcommand, alias = "token")]
+    AnchorHtlc(Command),
+}
+
+fn default_keypair() -> PathBuf {
+    let home = std::env::var_os("HOME").unwrap_or_else(|| ".".into());
+    PathBuf::from(home).join(".config/solana/id.json")
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let ctx = Context::new(&cli.cluster, cli.keypair.unwrap_or_else(default_keypair));
+    let (program, command) = match cli.program {
+        ProgramCommand::NativeHtlc(command) => (Program::NativeHtlc, command),
+        ProgramCommand::AnchorHtlc(command) => (Program::AnchorHtlc, command),
+    };
+    match commands::run(&ctx, program, command) {
+        Ok(output) => {
+            println!("{output:#}");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            println!("{:#}", serde_json::json!({ "error": format!("{err:#}") }));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn parses_the_command_line() {
+        Cli::command().debug_assert();
+
+        let id = format!("0x{}", "11".repeat(32));
+        let cli = Cli::try_parse_from([
+            "train-solana",
+            "sol",
+            "redeem",
+            "--sender",
+            "11111111111111111111111111111111",
+            "--id",
+            &id,
+            "--secret",
+            &id,
+            "-u",
+            "devnet",
+        ])
+        .unwrap();
+        assert_eq!(cli.cluster, Cluster::Devnet);
+        assert!(matches!(
+            cli.program,
+            ProgramCommand::NativeHtlc(Command::Redeem { id, .. }) if id == [0x11; 32]
+        ));
+
+        let sender = "11111111111111111111111111111111";
+        let cli = Cli::try_parse_from([
+            "train-solana",
+            "token",
+            "redeem-batch",
+            "--sender",
+            sender,
+            "--id",
+            &id,
+            "--secret",
+            &id,
+            "--sender",
+            sender,
+            "--id",
+            &id,
+            "--secret",
+            &id,
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.program,
+            ProgramCommand::AnchorHtlc(Command::RedeemBatch { ids, .. }) if ids.len() == 2
+        ));
+
+        let missing = Cli::try_parse_from([
+            "train-solana",
+            "token",
+            "add-lock-sig",
+            "--sender",
+            "11111111111111111111111111111111",
+            "--id",
+            &id,
+            "--hashlock",
+            &id,
+            "--timelock",
+            "1",
+        ]);
+        assert!(missing.is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! Decoders for the Config and the per-sender accounts, which have the same
+//! layout in both programs.
+use anchor_lang::AccountDeserialize;
+use sol::{Config, ContractsIndex, ContractsPage, SenderNonce};
+
+use crate::Error;
+
+/// Deserializes an Anchor account, checking its discriminator.
+pub(crate) fn decode<T: AccountDeserialize>(
+    mut data: &[u8],
+    account: &'static str,
+) -> Result<T, Error> {
+    T::try_deserialize(&mut data).map_err(|_| Error::InvalidAccount(account))
+}
+
+/// The page new HTLCs are indexed in, from a `contracts_index` account. A sender
+/// without one has not created an HTLC yet and indexes into page 0.
+pub fn decode_current_page(data: &[u8]) -> Result<u32, Error> {
+    decode::<ContractsIndex>(data, "ContractsIndex").map(|index| index.current_page)
+}
+
+/// The Ids of the HTLCs listed in a `contracts_page` account.
+pub fn decode_contract_ids(data: &[u8]) -> Result<Vec<[u8; 32]>, Error> {
+    decode::<ContractsPage>(data, "ContractsPage").map(|page| page.ids)
+}
+
+/// The nonce of a `nonce` account, to derive the sender's next commit Id from.
+pub fn decode_nonce(data: &[u8]) -> Result<u64, Error> {
+    decode::<SenderNonce>(data, "SenderNonce").map(|nonce| nonce.nonce)
+}
+
+/// The program's Config: its admin, pending admin, whether deposits are paused,
+/// the protocol fee and the timelock window.
+pub fn decode_config(data: &[u8]) -> Result<Config, Error> {
+    decode::<Config>(data, "Config")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::pubkey::Pubkey;
+    use anchor_lang::AccountSerialize;
+
+    fn encode<T: AccountSerialize>(account: &T) -> Vec<u8> {
+        let mut data = Vec::new();
+        account.try_serialize(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn decodes_the_sender_accounts_of_both_programs() {
+        let index = native::ContractsIndex { current_page: 3 };
+        let page = native::ContractsPage {
+            ids: vec![[1u8; 32], [2u8; 32]],
+        };
+        let nonce = native::SenderNonce { nonce: 7 };
+
+        assert_eq!(decode_current_page(&encode(&index)), Ok(3));
+        assert_eq!(
+            decode_contract_ids(&encode(&page)),
+            Ok(vec![[1u8; 32], [2u8; 32]])
+        );
+        assert_eq!(decode_nonce(&encode(&nonce)), Ok(7));
+    }
+
+    #[test]
+    fn decodes_the_config_of_both_programs() {
+        let config = native::Config {
+            admin: Pubkey::new_unique(),
+            pending_admin: Some(Pubkey::new_unique()),
+            paused: true,
+            fee_bps: 25,
+            min_timelock_window: 1_800,
+            max_timelock_window: 86_400,
+        };
+
+        let decoded = decode_config(&encode(&config)).unwrap();
+        assert_eq!(decoded.admin, config.admin);
+        assert_eq!(decoded.pending_admin, config.pending_admin);
+        assert!(decoded.paused);
+        assert_eq!(decoded.fee_bps, 25);
+        assert_eq!(
+            (decoded.min_timelock_window, decoded.max_timelock_window),
+            (1_800, 86_400)
+        );
+    }
+
+    #[test]
+    fn rejects_other_accounts() {
+        let nonce = encode(&SenderNonce { nonce: 7 });
+        assert_eq!(
+            decode_current_page(&nonce),
+            Err(Error::InvalidAccount("ContractsIndex"))
+        );
+        assert_eq!(decode_nonce(&[]), Err(Error::InvalidAccount("SenderNonce")));
+    }
+}
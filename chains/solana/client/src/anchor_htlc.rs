@@ -0,0 +1,841 @@
+//! Instructions and accounts of anchor_htlc (the `native` program), which locks
+//! SPL tokens of either the SPL Token or the Token-2022 program.
+//!
+//! Token accounts are the owners' associated token accounts under
+//! `token_program`. As in [`crate::native_htlc`], each builder notes which
+//! accounts have to sign.
+use anchor_lang::solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use anchor_spl::{associated_token, token_2022};
+use native::{accounts, instruction};
+
+pub use native::{
+    AllowedMint, Config, ContractsIndex, ContractsPage, LegacyHTLC, SenderNonce, HTLC,
+    ID as PROGRAM_ID,
+};
+
+use crate::accounts::decode;
+use crate::{
+    add_lock_sig_message, anchor_instruction, ed25519_verify_instruction, pda, CommitParams, Error,
+    HashAlgorithm, LockParams,
+};
+
+/// `initialize_config`, signed by `admin`, who pays for the Config and becomes
+/// its admin. `admin` has to be the program's upgrade authority, and only the
+/// first call succeeds.
+pub fn initialize_config(admin: &Pubkey) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::InitializeConfig {
+            admin: *admin,
+            program_data: pda::program_data(&PROGRAM_ID),
+            config: pda::config(&PROGRAM_ID),
+            system_program: system_program::ID,
+        },
+        instruction::InitializeConfig {},
+    )
+}
+
+/// `transfer_admin` to `new_admin`, signed by the current admin.
+pub fn transfer_admin(admin: &Pubkey, new_admin: &Pubkey) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::ConfigAdmin {
+            admin: *admin,
+            config: pda::config(&PROGRAM_ID),
+        },
+        instruction::TransferAdmin {
+            new_admin: *new_admin,
+        },
+    )
+}
+
+/// `accept_admin`, signed by the pending admin.
+pub fn accept_admin(pending_admin: &Pubkey) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::AcceptAdmin {
+            pending_admin: *pending_admin,
+            config: pda::config(&PROGRAM_ID),
+        },
+        instruction::AcceptAdmin {},
+    )
+}
+
+/// `set_paused`, signed by the admin.
+pub fn set_paused(admin: &Pubkey, paused: bool) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::ConfigAdmin {
+            admin: *admin,
+            config: pda::config(&PROGRAM_ID),
+        },
+        instruction::SetPaused { paused },
+    )
+}
+
+/// `set_fee` to `fee_bps` basis points, signed by the admin.
+pub fn set_fee(admin: &Pubkey, fee_bps: u16) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::ConfigAdmin {
+            admin: *admin,
+            config: pda::config(&PROGRAM_ID),
+        },
+        instruction::SetFee { fee_bps },
+    )
+}
+
+/// `set_timelock_window` to `min_window`..=`max_window` seconds ahead of the
+/// clock, signed by the admin.
+pub fn set_timelock_window(admin: &Pubkey, min_window: u64, max_window: u64) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::ConfigAdmin {
+            admin: *admin,
+            config: pda::config(&PROGRAM_ID),
+        },
+        instruction::SetTimelockWindow {
+            min_window,
+            max_window,
+        },
+    )
+}
+
+/// `withdraw_fees` of the `mint` fee vault to `recipient_token_account`, signed
+/// by the admin.
+pub fn withdraw_fees(
+    admin: &Pubkey,
+    mint: &Pubkey,
+    recipient_token_account: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::WithdrawFees {
+            admin: *admin,
+            config: pda::config(&PROGRAM_ID),
+            fee_vault: pda::token_fee_vault(&PROGRAM_ID, mint),
+            recipient_token_account: *recipient_token_account,
+            token_contract: *mint,
+            token_program: *token_program,
+        },
+        instruction::WithdrawFees {},
+    )
+}
+
+/// `allow_mint`, signed by the admin, who pays for the AllowedMint the first
+/// time `mint` is allowed.
+pub fn allow_mint(admin: &Pubkey, mint: &Pubkey, src_asset: &str) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::AllowMint {
+            admin: *admin,
+            config: pda::config(&PROGRAM_ID),
+            allowed_mint: pda::allowed_mint(&PROGRAM_ID, mint),
+            token_contract: *mint,
+            system_program: system_program::ID,
+        },
+        instruction::AllowMint {
+            src_asset: src_asset.to_string(),
+        },
+    )
+}
+
+/// `remove_mint`, signed by the admin, who gets the AllowedMint rent back.
+pub fn remove_mint(admin: &Pubkey, mint: &Pubkey) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::RemoveMint {
+            admin: *admin,
+            config: pda::config(&PROGRAM_ID),
+            allowed_mint: pda::allowed_mint(&PROGRAM_ID, mint),
+            token_contract: *mint,
+        },
+        instruction::RemoveMint {},
+    )
+}
+
+/// `get_commit_id`: returns the Id of the sender's next commit. Nothing signs.
+pub fn get_commit_id(
+    sender: &Pubkey,
+    src_receiver: &Pubkey,
+    amount: u64,
+    timelock: u64,
+) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::GetCommitId {
+            sender: *sender,
+            receiver: *src_receiver,
+            sender_nonce: pda::sender_nonce(&PROGRAM_ID, sender),
+        },
+        instruction::GetCommitId { amount, timelock },
+    )
+}
+
+/// `commit` of `mint` tokens, signed by the sender. `page` is the sender's
+/// current contracts page, see [`crate::decode_current_page`].
+pub fn commit(
+    sender: &Pubkey,
+    page: u32,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+    params: &CommitParams,
+) -> Instruction {
+    let (htlc, commit_bump) = pda::htlc(&PROGRAM_ID, sender, &params.Id);
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::Commit {
+            sender: *sender,
+            config: pda::config(&PROGRAM_ID),
+            htlc,
+            contracts_index: pda::contracts_index(&PROGRAM_ID, sender),
+            contracts_page: pda::contracts_page(&PROGRAM_ID, sender, page),
+            sender_nonce: pda::sender_nonce(&PROGRAM_ID, sender),
+            htlc_token_account: pda::htlc_token_account(&PROGRAM_ID, sender, &params.Id),
+            fee_vault: pda::token_fee_vault(&PROGRAM_ID, mint),
+            token_contract: *mint,
+            allowed_mint: pda::allowed_mint(&PROGRAM_ID, mint),
+            sender_token_account: pda::associated_token_account(sender, mint, token_program),
+            token_program: *token_program,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::Commit {
+            Id: params.Id,
+            hopChains: params.hop_chains.clone(),
+            hopAssets: params.hop_assets.clone(),
+            hopAddress: params.hop_addresses.clone(),
+            dst_chain: params.dst_chain.clone(),
+            dst_asset: params.dst_asset.clone(),
+            dst_address: params.dst_address.clone(),
+            src_asset: params.src_asset.clone(),
+            src_receiver: params.src_receiver,
+            timelock: params.timelock,
+            amount: params.amount,
+            hash_algorithm: params.hash_algorithm.into(),
+            keeper_bounty: params.keeper_bounty,
+            commit_bump,
+        },
+    )
+}
+
+/// `lock` of `mint` tokens, signed by the sender. `page` is the sender's
+/// current contracts page.
+pub fn lock(
+    sender: &Pubkey,
+    page: u32,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+    params: &LockParams,
+) -> Instruction {
+    let (htlc, lock_bump) = pda::htlc(&PROGRAM_ID, sender, &params.Id);
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::Lock {
+            sender: *sender,
+            config: pda::config(&PROGRAM_ID),
+            htlc,
+            contracts_index: pda::contracts_index(&PROGRAM_ID, sender),
+            contracts_page: pda::contracts_page(&PROGRAM_ID, sender, page),
+            htlc_token_account: pda::htlc_token_account(&PROGRAM_ID, sender, &params.Id),
+            fee_vault: pda::token_fee_vault(&PROGRAM_ID, mint),
+            token_contract: *mint,
+            allowed_mint: pda::allowed_mint(&PROGRAM_ID, mint),
+            sender_token_account: pda::associated_token_account(sender, mint, token_program),
+            token_program: *token_program,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::Lock {
+            Id: params.Id,
+            hashlock: params.hashlock,
+            timelock: params.timelock,
+            dst_chain: params.dst_chain.clone(),
+            dst_address: params.dst_address.clone(),
+            dst_asset: params.dst_asset.clone(),
+            src_asset: params.src_asset.clone(),
+            src_receiver: params.src_receiver,
+            amount: params.amount,
+            reward: params.reward,
+            reward_timelock: params.reward_timelock,
+            hash_algorithm: params.hash_algorithm.into(),
+            keeper_bounty: params.keeper_bounty,
+            lock_bump,
+        },
+    )
+}
+
+/// `add_lock`, signed by the sender, who also pays.
+pub fn add_lock(
+    sender: &Pubkey,
+    Id: [u8; 32],
+    hashlock: [u8; 32],
+    timelock: u64,
+    hash_algorithm: HashAlgorithm,
+) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::AddLock {
+            sender: *sender,
+            htlc: pda::htlc(&PROGRAM_ID, sender, &Id).0,
+            config: pda::config(&PROGRAM_ID),
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::AddLock {
+            Id,
+            hashlock,
+            timelock,
+            hash_algorithm: hash_algorithm.into(),
+        },
+    )
+}
+
+/// The Ed25519 verification and the `add_lock_sig` instruction that has to
+/// follow it, signed by the payer only. `signature` is the sender's signature
+/// over [`crate::add_lock_sig_message`].
+pub fn add_lock_sig(
+    payer: &Pubkey,
+    sender: &Pubkey,
+    Id: [u8; 32],
+    hashlock: [u8; 32],
+    timelock: u64,
+    hash_algorithm: HashAlgorithm,
+    signature: [u8; 64],
+) -> [Instruction; 2] {
+    let message = add_lock_sig_message(
+        &PROGRAM_ID.to_bytes(),
+        &Id,
+        &hashlock,
+        timelock,
+        hash_algorithm,
+    );
+    [
+        ed25519_verify_instruction(sender, &signature, &message),
+        anchor_instruction(
+            PROGRAM_ID,
+            accounts::AddLockSig {
+                payer: *payer,
+                htlc: pda::htlc(&PROGRAM_ID, sender, &Id).0,
+                config: pda::config(&PROGRAM_ID),
+                ix_sysvar: sysvar::instructions::ID,
+            },
+            instruction::AddLockSig {
+                Id,
+                hashlock,
+                timelock,
+                hash_algorithm: hash_algorithm.into(),
+                signature,
+            },
+        ),
+    ]
+}
+
+/// `extend_timelock`, signed by the sender.
+pub fn extend_timelock(sender: &Pubkey, Id: [u8; 32], timelock: u64) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::ExtendTimelock {
+            sender: *sender,
+            htlc: pda::htlc(&PROGRAM_ID, sender, &Id).0,
+            config: pda::config(&PROGRAM_ID),
+        },
+        instruction::ExtendTimelock { Id, timelock },
+    )
+}
+
+/// The fee vault of the HTLC's mint, if the HTLC charges a fee.
+fn fee_vault(htlc: &HTLC) -> Option<Pubkey> {
+    (htlc.fee_bps != 0).then(|| pda::token_fee_vault(&PROGRAM_ID, &htlc.token_contract))
+}
+
+/// The meta of the mint of an HTLC. A Token-2022 mint is writable, as the
+/// transfer fees withheld in the HTLC token account are harvested to it before
+/// that account is closed.
+fn mint_meta(mint: &Pubkey, token_program: &Pubkey) -> AccountMeta {
+    if *token_program == token_2022::ID {
+        AccountMeta::new(*mint, false)
+    } else {
+        AccountMeta::new_readonly(*mint, false)
+    }
+}
+
+/// Makes the mint of a redeem or refund instruction writable for a Token-2022
+/// mint, as in [`mint_meta`].
+fn with_mint_meta(mut ix: Instruction, mint: &Pubkey, token_program: &Pubkey) -> Instruction {
+    for meta in ix.accounts.iter_mut().filter(|meta| meta.pubkey == *mint) {
+        *meta = mint_meta(mint, token_program);
+    }
+    ix
+}
+
+/// The token account a reward earned by `user_signing` goes to, if the HTLC has
+/// a reward the redeemer can earn.
+fn reward_token_account(
+    user_signing: &Pubkey,
+    htlc: &HTLC,
+    token_program: &Pubkey,
+) -> Option<Pubkey> {
+    (htlc.reward != 0 && *user_signing != htlc.src_receiver)
+        .then(|| pda::associated_token_account(user_signing, &htlc.token_contract, token_program))
+}
+
+/// `redeem` of the decoded `htlc`, signed by `user_signing`, who pays for the
+/// src_receiver's token account if it does not exist yet. A reward earned by
+/// the redeemer goes to `user_signing`'s token account, which has to exist
+/// when the HTLC has a reward and is only passed then. The fee vault of the
+/// mint is only passed when the HTLC charges a fee.
+pub fn redeem(
+    user_signing: &Pubkey,
+    Id: [u8; 32],
+    htlc: &HTLC,
+    secret: [u8; 32],
+    token_program: &Pubkey,
+) -> Instruction {
+    let (htlc_address, htlc_bump) = pda::htlc(&PROGRAM_ID, &htlc.sender, &Id);
+    let mint = &htlc.token_contract;
+    let ix = anchor_instruction(
+        PROGRAM_ID,
+        accounts::Redeem {
+            user_signing: *user_signing,
+            htlc: htlc_address,
+            contracts_page: pda::contracts_page(&PROGRAM_ID, &htlc.sender, htlc.index_page),
+            htlc_token_account: pda::htlc_token_account(&PROGRAM_ID, &htlc.sender, &Id),
+            fee_vault: fee_vault(htlc),
+            src_receiver_token_account: pda::associated_token_account(
+                &htlc.src_receiver,
+                mint,
+                token_program,
+            ),
+            sender_token_account: pda::associated_token_account(&htlc.sender, mint, token_program),
+            reward_token_account: reward_token_account(user_signing, htlc, token_program),
+            sender: htlc.sender,
+            src_receiver: htlc.src_receiver,
+            payer: htlc.payer,
+            token_contract: *mint,
+            system_program: system_program::ID,
+            token_program: *token_program,
+            associated_token_program: associated_token::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::Redeem {
+            Id,
+            secret,
+            htlc_bump,
+        },
+    );
+    with_mint_meta(ix, mint, token_program)
+}
+
+/// `redeem_batch` of the decoded HTLCs, each with its Id and secret, signed by
+/// `user_signing`. All mints have to be of `token_program`, and unlike in
+/// [`redeem`] the src_receivers' token accounts have to exist. A reward earned
+/// by the redeemer goes to `user_signing`'s token account, passed as the
+/// program id for an HTLC without a reward, like the fee vault for an HTLC
+/// without a fee. At most [`native::MAX_BATCH_LEN`] items.
+pub fn redeem_batch(
+    user_signing: &Pubkey,
+    items: &[([u8; 32], &HTLC, [u8; 32])],
+    token_program: &Pubkey,
+) -> Instruction {
+    let mut ix = anchor_instruction(
+        PROGRAM_ID,
+        accounts::RedeemBatch {
+            user_signing: *user_signing,
+            token_program: *token_program,
+        },
+        instruction::RedeemBatch {
+            items: items
+                .iter()
+                .map(|&(Id, _, secret)| native::RedeemBatchItem { Id, secret })
+                .collect(),
+        },
+    );
+    for (Id, htlc, _) in items {
+        let mint = &htlc.token_contract;
+        ix.accounts.extend([
+            AccountMeta::new(pda::htlc(&PROGRAM_ID, &htlc.sender, Id).0, false),
+            AccountMeta::new(
+                pda::contracts_page(&PROGRAM_ID, &htlc.sender, htlc.index_page),
+                false,
+            ),
+            AccountMeta::new(
+                pda::htlc_token_account(&PROGRAM_ID, &htlc.sender, Id),
+                false,
+            ),
+            mint_meta(mint, token_program),
+            AccountMeta::new(fee_vault(htlc).unwrap_or(PROGRAM_ID), false),
+            AccountMeta::new(
+                pda::associated_token_account(&htlc.src_receiver, mint, token_program),
+                false,
+            ),
+            AccountMeta::new(
+                pda::associated_token_account(&htlc.sender, mint, token_program),
+                false,
+            ),
+            AccountMeta::new(
+                reward_token_account(user_signing, htlc, token_program).unwrap_or(PROGRAM_ID),
+                false,
+            ),
+            AccountMeta::new(htlc.sender, false),
+            AccountMeta::new(htlc.payer, false),
+        ]);
+    }
+    ix
+}
+
+/// `refund` of the decoded `htlc`, signed by `user_signing`: anyone once the
+/// HTLC has expired. The keeper bounty, if any, goes to `user_signing`.
+pub fn refund(
+    user_signing: &Pubkey,
+    Id: [u8; 32],
+    htlc: &HTLC,
+    token_program: &Pubkey,
+) -> Instruction {
+    let (htlc_address, htlc_bump) = pda::htlc(&PROGRAM_ID, &htlc.sender, &Id);
+    let ix = anchor_instruction(
+        PROGRAM_ID,
+        accounts::Refund {
+            user_signing: *user_signing,
+            htlc: htlc_address,
+            contracts_page: pda::contracts_page(&PROGRAM_ID, &htlc.sender, htlc.index_page),
+            htlc_token_account: pda::htlc_token_account(&PROGRAM_ID, &htlc.sender, &Id),
+            sender: htlc.sender,
+            payer: htlc.payer,
+            token_contract: htlc.token_contract,
+            sender_token_account: pda::associated_token_account(
+                &htlc.sender,
+                &htlc.token_contract,
+                token_program,
+            ),
+            system_program: system_program::ID,
+            token_program: *token_program,
+            rent: sysvar::rent::ID,
+        },
+        instruction::Refund { Id, htlc_bump },
+    );
+    with_mint_meta(ix, &htlc.token_contract, token_program)
+}
+
+/// `refund_batch` of the decoded HTLCs of `sender`, each with its Id, signed by
+/// `user_signing`. All mints have to be of `token_program`. At most
+/// [`native::MAX_BATCH_LEN`] items.
+pub fn refund_batch(
+    user_signing: &Pubkey,
+    sender: &Pubkey,
+    items: &[([u8; 32], &HTLC)],
+    token_program: &Pubkey,
+) -> Instruction {
+    let mut ix = anchor_instruction(
+        PROGRAM_ID,
+        accounts::RefundBatch {
+            user_signing: *user_signing,
+            sender: *sender,
+            token_program: *token_program,
+        },
+        instruction::RefundBatch {
+            Ids: items.iter().map(|&(Id, _)| Id).collect(),
+        },
+    );
+    for (Id, htlc) in items {
+        let mint = &htlc.token_contract;
+        ix.accounts.extend([
+            AccountMeta::new(pda::htlc(&PROGRAM_ID, sender, Id).0, false),
+            AccountMeta::new(
+                pda::contracts_page(&PROGRAM_ID, sender, htlc.index_page),
+                false,
+            ),
+            AccountMeta::new(pda::htlc_token_account(&PROGRAM_ID, sender, Id), false),
+            mint_meta(mint, token_program),
+            AccountMeta::new(
+                pda::associated_token_account(sender, mint, token_program),
+                false,
+            ),
+            AccountMeta::new(htlc.payer, false),
+        ]);
+    }
+    ix
+}
+
+/// `redeem_legacy` of an HTLC at the `[Id]` address, signed by `user_signing`.
+pub fn redeem_legacy(
+    user_signing: &Pubkey,
+    Id: [u8; 32],
+    htlc: &LegacyHTLC,
+    secret: [u8; 32],
+    token_program: &Pubkey,
+) -> Instruction {
+    let ix = anchor_instruction(
+        PROGRAM_ID,
+        accounts::RedeemLegacy {
+            user_signing: *user_signing,
+            htlc: pda::legacy_htlc(&PROGRAM_ID, &Id),
+            htlc_token_account: pda::legacy_htlc_token_account(&PROGRAM_ID, &Id),
+            src_receiver_token_account: pda::associated_token_account(
+                &htlc.src_receiver,
+                &htlc.token_contract,
+                token_program,
+            ),
+            sender: htlc.sender,
+            src_receiver: htlc.src_receiver,
+            token_contract: htlc.token_contract,
+            system_program: system_program::ID,
+            token_program: *token_program,
+            associated_token_program: associated_token::ID,
+        },
+        instruction::RedeemLegacy { Id, secret },
+    );
+    with_mint_meta(ix, &htlc.token_contract, token_program)
+}
+
+/// `refund_legacy` of an HTLC at the `[Id]` address, signed by `user_signing`.
+pub fn refund_legacy(
+    user_signing: &Pubkey,
+    Id: [u8; 32],
+    htlc: &LegacyHTLC,
+    token_program: &Pubkey,
+) -> Instruction {
+    let ix = anchor_instruction(
+        PROGRAM_ID,
+        accounts::RefundLegacy {
+            user_signing: *user_signing,
+            htlc: pda::legacy_htlc(&PROGRAM_ID, &Id),
+            htlc_token_account: pda::legacy_htlc_token_account(&PROGRAM_ID, &Id),
+            sender: htlc.sender,
+            token_contract: htlc.token_contract,
+            sender_token_account: pda::associated_token_account(
+                &htlc.sender,
+                &htlc.token_contract,
+                token_program,
+            ),
+            system_program: system_program::ID,
+            token_program: *token_program,
+        },
+        instruction::RefundLegacy { Id },
+    );
+    with_mint_meta(ix, &htlc.token_contract, token_program)
+}
+
+/// `getDetails`: returns the HTLC. Nothing signs.
+pub fn get_details(sender: &Pubkey, Id: [u8; 32]) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::GetDetails {
+            htlc: pda::htlc(&PROGRAM_ID, sender, &Id).0,
+        },
+        instruction::GetDetails { Id },
+    )
+}
+
+/// `get_contracts`: returns one page of the sender's HTLC Ids. Nothing signs.
+pub fn get_contracts(sender: &Pubkey, page: u32) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::GetContracts {
+            contracts_page: pda::contracts_page(&PROGRAM_ID, sender, page),
+        },
+        instruction::GetContracts {
+            _sender: *sender,
+            _page: page,
+        },
+    )
+}
+
+/// Decodes the data of an HTLC account.
+pub fn decode_htlc(data: &[u8]) -> Result<HTLC, Error> {
+    decode(data, "HTLC")
+}
+
+/// Decodes the data of an AllowedMint account: the symbol a mint accepted by
+/// `allow_mint` has to be committed and locked with.
+pub fn decode_allowed_mint(data: &[u8]) -> Result<AllowedMint, Error> {
+    decode(data, "AllowedMint")
+}
+
+/// Decodes the data of an HTLC account at a legacy `[Id]` address.
+pub fn decode_legacy_htlc(data: &[u8]) -> Result<LegacyHTLC, Error> {
+    if data.len() < 8 || data[..8] != HTLC::DISCRIMINATOR {
+        return Err(Error::InvalidAccount("LegacyHTLC"));
+    }
+    LegacyHTLC::deserialize(&mut &data[8..]).map_err(|_| Error::InvalidAccount("LegacyHTLC"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::AnchorSerialize;
+    use anchor_spl::token::spl_token;
+
+    #[test]
+    fn redeem_passes_the_fee_vault_and_reward_account_only_when_used() {
+        let Id = [9u8; 32];
+        let mut htlc = HTLC {
+            sender: Pubkey::new_unique(),
+            src_receiver: Pubkey::new_unique(),
+            payer: Pubkey::new_unique(),
+            token_contract: Pubkey::new_unique(),
+            reward: 10,
+            fee_bps: 25,
+            ..HTLC::default()
+        };
+        let user_signing = Pubkey::new_unique();
+        let ix = redeem(&user_signing, Id, &htlc, [1u8; 32], &spl_token::ID);
+
+        let keys: Vec<Pubkey> = ix.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(keys[0], user_signing);
+        assert_eq!(
+            keys[3..5],
+            [
+                pda::htlc_token_account(&PROGRAM_ID, &htlc.sender, &Id),
+                pda::token_fee_vault(&PROGRAM_ID, &htlc.token_contract),
+            ]
+        );
+        assert_eq!(
+            keys[7],
+            pda::associated_token_account(&user_signing, &htlc.token_contract, &spl_token::ID)
+        );
+        assert!(keys.contains(&spl_token::ID));
+
+        htlc.reward = 0;
+        htlc.fee_bps = 0;
+        let ix = redeem(&user_signing, Id, &htlc, [1u8; 32], &spl_token::ID);
+        assert_eq!(ix.accounts[4].pubkey, PROGRAM_ID);
+        assert_eq!(ix.accounts[7].pubkey, PROGRAM_ID);
+    }
+
+    #[test]
+    fn redeem_batch_appends_the_token_accounts_of_each_item() {
+        let Id = [9u8; 32];
+        let htlc = HTLC {
+            sender: Pubkey::new_unique(),
+            src_receiver: Pubkey::new_unique(),
+            payer: Pubkey::new_unique(),
+            token_contract: Pubkey::new_unique(),
+            ..HTLC::default()
+        };
+        let user_signing = Pubkey::new_unique();
+        let ix = redeem_batch(&user_signing, &[(Id, &htlc, [1u8; 32])], &spl_token::ID);
+
+        let keys: Vec<Pubkey> = ix.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(keys.len(), 2 + native::REDEEM_BATCH_ACCOUNTS);
+        assert_eq!(keys[..2], [user_signing, spl_token::ID]);
+        assert_eq!(
+            keys[4],
+            pda::htlc_token_account(&PROGRAM_ID, &htlc.sender, &Id)
+        );
+        assert_eq!(
+            keys[6..8],
+            [
+                PROGRAM_ID,
+                pda::associated_token_account(
+                    &htlc.src_receiver,
+                    &htlc.token_contract,
+                    &spl_token::ID
+                ),
+            ]
+        );
+        assert!(!ix.accounts[5].is_writable);
+        assert_eq!(keys[9], PROGRAM_ID);
+    }
+
+    #[test]
+    fn redeem_and_refund_pass_a_token_2022_mint_writable() {
+        let Id = [9u8; 32];
+        let htlc = HTLC {
+            sender: Pubkey::new_unique(),
+            src_receiver: Pubkey::new_unique(),
+            payer: Pubkey::new_unique(),
+            token_contract: Pubkey::new_unique(),
+            ..HTLC::default()
+        };
+        let user_signing = Pubkey::new_unique();
+        let mint_writable = |ix: &Instruction| {
+            ix.accounts
+                .iter()
+                .find(|meta| meta.pubkey == htlc.token_contract)
+                .unwrap()
+                .is_writable
+        };
+
+        for (token_program, writable) in [(spl_token::ID, false), (token_2022::ID, true)] {
+            let redeem = redeem(&user_signing, Id, &htlc, [1u8; 32], &token_program);
+            let redeem_batch =
+                redeem_batch(&user_signing, &[(Id, &htlc, [1u8; 32])], &token_program);
+            let refund = refund(&user_signing, Id, &htlc, &token_program);
+            let refund_batch =
+                refund_batch(&user_signing, &htlc.sender, &[(Id, &htlc)], &token_program);
+            for ix in [redeem, redeem_batch, refund, refund_batch] {
+                assert_eq!(mint_writable(&ix), writable);
+            }
+        }
+    }
+
+    #[test]
+    fn commit_and_lock_pass_the_allowed_mint_of_their_mint() {
+        let sender = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let allowed_mint = pda::allowed_mint(&PROGRAM_ID, &mint);
+        let commit = commit(&sender, 0, &mint, &spl_token::ID, &CommitParams::default());
+        let lock = lock(&sender, 0, &mint, &spl_token::ID, &LockParams::default());
+
+        assert_eq!(commit.accounts[9].pubkey, allowed_mint);
+        assert_eq!(lock.accounts[8].pubkey, allowed_mint);
+        assert!(!commit.accounts[9].is_writable);
+    }
+
+    #[test]
+    fn decodes_allowed_mint_accounts() {
+        let mut data = AllowedMint::DISCRIMINATOR.to_vec();
+        data.extend(
+            AllowedMint {
+                src_asset: "USDC".to_string(),
+            }
+            .try_to_vec()
+            .unwrap(),
+        );
+
+        assert_eq!(decode_allowed_mint(&data).unwrap().src_asset, "USDC");
+        assert_eq!(
+            decode_allowed_mint(&data[..8]).err(),
+            Some(Error::InvalidAccount("AllowedMint"))
+        );
+    }
+
+    #[test]
+    fn decodes_legacy_htlc_accounts() {
+        let legacy = LegacyHTLC {
+            dst_address: "0x1234".to_string(),
+            dst_chain: "ETHEREUM".to_string(),
+            dst_asset: "USDC".to_string(),
+            src_asset: "USDC".to_string(),
+            sender: Pubkey::new_unique(),
+            src_receiver: Pubkey::new_unique(),
+            hashlock: [1u8; 32],
+            secret: [0u8; 32],
+            amount: 42,
+            timelock: 7,
+            token_contract: Pubkey::new_unique(),
+            token_wallet: Pubkey::new_unique(),
+            redeemed: false,
+            refunded: true,
+        };
+        let mut data = HTLC::DISCRIMINATOR.to_vec();
+        data.extend(legacy.try_to_vec().unwrap());
+
+        let decoded = decode_legacy_htlc(&data).unwrap();
+        assert_eq!(
+            (decoded.sender, decoded.amount, decoded.refunded),
+            (legacy.sender, 42, true)
+        );
+        assert_eq!(
+            decode_legacy_htlc(&data[1..]).err(),
+            Some(Error::InvalidAccount("LegacyHTLC"))
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use anchor_lang::solana_program::instruction::InstructionError;
+use std::fmt;
+use train_htlc_core::{HtlcError, ERROR_CODE_OFFSET};
+
+/// Why an instruction failed, or why an account could not be decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// One of the `HTLCError`s both programs declare from train-htlc-core.
+    Htlc(HtlcError),
+    /// An Anchor framework error, e.g. 2006 for a `ConstraintSeeds` failure.
+    Anchor(u32),
+    /// A custom code neither the programs nor Anchor define.
+    Custom(u32),
+    /// The data is not the named program account.
+    InvalidAccount(&'static str),
+}
+
+impl Error {
+    /// The error for a custom program error code.
+    pub fn from_code(code: u32) -> Self {
+        match HtlcError::from_code(code) {
+            Some(error) => Error::Htlc(error),
+            None if code < ERROR_CODE_OFFSET => Error::Anchor(code),
+            None => Error::Custom(code),
+        }
+    }
+
+    /// The error an instruction failed with, if it returned a custom code.
+    pub fn from_instruction_error(error: &InstructionError) -> Option<Self> {
+        match error {
+            InstructionError::Custom(code) => Some(Self::from_code(*code)),
+            _ => None,
+        }
+    }
+
+    /// The custom program error code, if the error came from a program.
+    pub fn code(&self) -> Option<u32> {
+        match self {
+            Error::Htlc(error) => Some(error.code()),
+            Error::Anchor(code) | Error::Custom(code) => Some(*code),
+            Error::InvalidAccount(_) => None,
+        }
+    }
+}
+
+impl From<HtlcError> for Error {
+    fn from(error: HtlcError) -> Self {
+        Error::Htlc(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Htlc(error) => write!(f, "{} ({}): {}", error.name(), error.code(), error.msg()),
+            Error::Anchor(code) => write!(f, "Anchor error {code}"),
+            Error::Custom(code) => write!(f, "Custom program error {code}"),
+            Error::InvalidAccount(account) => write!(f, "Account data is not a {account}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_codes_to_errors() {
+        assert_eq!(
+            Error::from_code(6000),
+            Error::Htlc(HtlcError::NotFutureTimeLock)
+        );
+        assert_eq!(
+            Error::from_code(HtlcError::InvalidCommitId.code()),
+            Error::Htlc(HtlcError::InvalidCommitId)
+        );
+        assert_eq!(Error::from_code(2006), Error::Anchor(2006));
+        let unknown = ERROR_CODE_OFFSET + HtlcError::ALL.len() as u32;
+        assert_eq!(Error::from_code(unknown), Error::Custom(unknown));
+        for error in HtlcError::ALL {
+            assert_eq!(Error::from_code(error.code()).code(), Some(error.code()));
+        }
+    }
+
+    #[test]
+    fn reads_custom_instruction_errors() {
+        assert_eq!(
+            Error::from_instruction_error(&InstructionError::Custom(6005)),
+            Some(Error::Htlc(HtlcError::AlreadyRedeemed))
+        );
+        assert_eq!(
+            Error::from_instruction_error(&InstructionError::InsufficientFunds),
+            None
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Client for the Train Solana HTLC programs: native_htlc (`sol`, lamports) and
+//! anchor_htlc (`native`, SPL tokens).
+//!
+//! [`native_htlc`] and [`anchor_htlc`] build every instruction of their program
+//! with its accounts in the order the program expects and decode the program's
+//! HTLC accounts. [`pda`] derives the program addresses, [`decode_current_page`]
+//! and friends read the per-sender index and nonce accounts both programs
+//! share, and [`Error`] names the code a failed instruction returned.
+#![allow(non_snake_case)]
+
+use anchor_lang::solana_program::{ed25519_program, instruction::Instruction, pubkey::Pubkey};
+use anchor_lang::{AnchorDeserialize, InstructionData, ToAccountMetas};
+
+mod accounts;
+pub mod anchor_htlc;
+mod error;
+pub mod native_htlc;
+pub mod pda;
+
+pub use accounts::{decode_config, decode_contract_ids, decode_current_page, decode_nonce};
+pub use error::Error;
+pub use train_htlc_core::{add_lock_sig_message, keccak256, sha256, HashAlgorithm, HtlcError};
+
+/// Arguments of `commit`, the same for both programs.
+#[derive(Clone, Debug, Default)]
+pub struct CommitParams {
+    /// Must be [`commit_id`] for the sender's current nonce.
+    pub Id: [u8; 32],
+    pub hop_chains: Vec<String>,
+    pub hop_assets: Vec<String>,
+    pub hop_addresses: Vec<String>,
+    pub dst_chain: String,
+    pub dst_asset: String,
+    pub dst_address: String,
+    pub src_asset: String,
+    pub src_receiver: Pubkey,
+    pub timelock: u64,
+    pub amount: u64,
+    /// The hash the hashlock added later is taken with.
+    pub hash_algorithm: HashAlgorithm,
+    /// Lamports for whoever refunds the HTLC once it expires. Zero for none.
+    pub keeper_bounty: u64,
+}
+
+/// Arguments of `lock`, the same for both programs.
+#[derive(Clone, Debug, Default)]
+pub struct LockParams {
+    pub Id: [u8; 32],
+    pub hashlock: [u8; 32],
+    pub timelock: u64,
+    pub amount: u64,
+    /// Zero for no reward.
+    pub reward: u64,
+    pub reward_timelock: u64,
+    pub dst_chain: String,
+    pub dst_address: String,
+    pub dst_asset: String,
+    pub src_asset: String,
+    pub src_receiver: Pubkey,
+    /// The hash `hashlock` is taken with.
+    pub hash_algorithm: HashAlgorithm,
+    /// Lamports for whoever refunds the HTLC once it expires. Zero for none.
+    pub keeper_bounty: u64,
+}
+
+/// The Id the sender's next `commit` to `program_id` has to use, given the
+/// nonce from the sender's nonce account (zero before its first commit).
+pub fn commit_id(
+    program_id: &Pubkey,
+    sender: &Pubkey,
+    src_receiver: &Pubkey,
+    amount: u64,
+    timelock: u64,
+    nonce: u64,
+) -> [u8; 32] {
+    train_htlc_core::derive_commit_id(
+        &program_id.to_bytes(),
+        &sender.to_bytes(),
+        &src_receiver.to_bytes(),
+        amount,
+        timelock,
+        nonce,
+    )
+}
+
+/// An instruction of an Anchor program from its generated accounts and args.
+pub(crate) fn anchor_instruction(
+    program_id: Pubkey,
+    accounts: impl ToAccountMetas,
+    args: impl InstructionData,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: accounts.to_account_metas(None),
+        data: args.data(),
+    }
+}
+
+/// Reads the return data of a batch instruction: for each item, `None` if it
+/// went through or the error it was skipped with. `None` if the data is not a
+/// list of results.
+pub fn decode_batch_results(return_data: &[u8]) -> Option<Vec<Option<Error>>> {
+    let codes = Vec::<u32>::try_from_slice(return_data).ok()?;
+    Some(
+        codes
+            .into_iter()
+            .map(|code| (code != 0).then(|| Error::from_code(code)))
+            .collect(),
+    )
+}
+
+/// Reads the return data of `redeem`: the protocol fee it took from the amount.
+pub fn decode_redeem_fee(return_data: &[u8]) -> Option<u64> {
+    u64::try_from_slice(return_data).ok()
+}
+
+/// An Ed25519 program instruction verifying `signature` by `signer` over
+/// `message`, with the public key, signature and message stored inside the
+/// instruction, as `add_lock_sig` expects right before it.
+pub fn ed25519_verify_instruction(
+    signer: &Pubkey,
+    signature: &[u8; 64],
+    message: &[u8],
+) -> Instruction {
+    const HEADER_LEN: u16 = 16;
+    let public_key_offset = HEADER_LEN;
+    let signature_offset = public_key_offset + 32;
+    let message_offset = signature_offset + 64;
+
+    let mut data = vec![1u8, 0];
+    for field in [
+        signature_offset,
+        u16::MAX,
+        public_key_offset,
+        u16::MAX,
+        message_offset,
+        message.len() as u16,
+        u16::MAX,
+    ] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(signer.as_ref());
+    data.extend_from_slice(signature);
+    data.extend_from_slice(message);
+
+    Instruction {
+        program_id: ed25519_program::ID,
+        accounts: Vec::new(),
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ed25519_instruction_points_inside_itself() {
+        let signer = Pubkey::new_unique();
+        let message =
+            add_lock_sig_message(&[1u8; 32], &[2u8; 32], &[3u8; 32], 4, HashAlgorithm::Sha256);
+        let ix = ed25519_verify_instruction(&signer, &[5u8; 64], &message);
+        let read_u16 = |at: usize| u16::from_le_bytes([ix.data[at], ix.data[at + 1]]) as usize;
+
+        assert_eq!(ix.data[0], 1);
+        assert_eq!(&ix.data[read_u16(6)..read_u16(6) + 32], signer.as_ref());
+        assert_eq!(&ix.data[read_u16(2)..read_u16(2) + 64], &[5u8; 64]);
+        assert_eq!(
+            &ix.data[read_u16(10)..read_u16(10) + read_u16(12)],
+            &message
+        );
+        for index in [4, 8, 14] {
+            assert_eq!(read_u16(index), u16::MAX as usize);
+        }
+    }
+
+    #[test]
+    fn decodes_batch_results() {
+        let mut data = 3u32.to_le_bytes().to_vec();
+        for code in [0, HtlcError::HashlockNoMatch.code(), 3012] {
+            data.extend(code.to_le_bytes());
+        }
+        assert_eq!(
+            decode_batch_results(&data),
+            Some(vec![
+                None,
+                Some(Error::Htlc(HtlcError::HashlockNoMatch)),
+                Some(Error::Anchor(3012)),
+            ])
+        );
+        assert_eq!(decode_batch_results(&data[..6]), None);
+    }
+
+    #[test]
+    fn decodes_the_redeem_fee() {
+        assert_eq!(decode_redeem_fee(&25u64.to_le_bytes()), Some(25));
+        assert_eq!(decode_redeem_fee(&[1]), None);
+    }
+}
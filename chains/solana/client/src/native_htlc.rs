@@ -0,0 +1,577 @@
+//! Instructions and accounts of native_htlc (the `sol` program), which locks lamports.
+//!
+//! The builders only derive addresses; signers are the caller's business. Each
+//! one notes which accounts have to sign.
+use anchor_lang::solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use sol::{accounts, instruction};
+
+pub use sol::{
+    Config, ContractsIndex, ContractsPage, LegacyHTLC, SenderNonce, HTLC, ID as PROGRAM_ID,
+};
+
+use crate::accounts::decode;
+use crate::{
+    add_lock_sig_message, anchor_instruction, ed25519_verify_instruction, pda, CommitParams, Error,
+    HashAlgorithm, LockParams,
+};
+
+/// `initialize_config`, signed by `admin`, who pays for the Config and becomes
+/// its admin. `admin` has to be the program's upgrade authority, and only the
+/// first call succeeds.
+pub fn initialize_config(admin: &Pubkey) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::InitializeConfig {
+            admin: *admin,
+            program_data: pda::program_data(&PROGRAM_ID),
+            config: pda::config(&PROGRAM_ID),
+            fee_vault: pda::fee_vault(&PROGRAM_ID),
+            system_program: system_program::ID,
+        },
+        instruction::InitializeConfig {},
+    )
+}
+
+/// `transfer_admin` to `new_admin`, signed by the current admin.
+pub fn transfer_admin(admin: &Pubkey, new_admin: &Pubkey) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::ConfigAdmin {
+            admin: *admin,
+            config: pda::config(&PROGRAM_ID),
+        },
+        instruction::TransferAdmin {
+            new_admin: *new_admin,
+        },
+    )
+}
+
+/// `accept_admin`, signed by the pending admin.
+pub fn accept_admin(pending_admin: &Pubkey) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::AcceptAdmin {
+            pending_admin: *pending_admin,
+            config: pda::config(&PROGRAM_ID),
+        },
+        instruction::AcceptAdmin {},
+    )
+}
+
+/// `set_paused`, signed by the admin.
+pub fn set_paused(admin: &Pubkey, paused: bool) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::ConfigAdmin {
+            admin: *admin,
+            config: pda::config(&PROGRAM_ID),
+        },
+        instruction::SetPaused { paused },
+    )
+}
+
+/// `set_fee` to `fee_bps` basis points, signed by the admin.
+pub fn set_fee(admin: &Pubkey, fee_bps: u16) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::ConfigAdmin {
+            admin: *admin,
+            config: pda::config(&PROGRAM_ID),
+        },
+        instruction::SetFee { fee_bps },
+    )
+}
+
+/// `set_timelock_window` to `min_window`..=`max_window` seconds ahead of the
+/// clock, signed by the admin.
+pub fn set_timelock_window(admin: &Pubkey, min_window: u64, max_window: u64) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::ConfigAdmin {
+            admin: *admin,
+            config: pda::config(&PROGRAM_ID),
+        },
+        instruction::SetTimelockWindow {
+            min_window,
+            max_window,
+        },
+    )
+}
+
+/// `withdraw_fees` of the fee vault to `recipient`, signed by the admin.
+pub fn withdraw_fees(admin: &Pubkey, recipient: &Pubkey) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::WithdrawFees {
+            admin: *admin,
+            config: pda::config(&PROGRAM_ID),
+            fee_vault: pda::fee_vault(&PROGRAM_ID),
+            recipient: *recipient,
+        },
+        instruction::WithdrawFees {},
+    )
+}
+
+/// `get_commit_id`: returns the Id of the sender's next commit. Nothing signs.
+pub fn get_commit_id(
+    sender: &Pubkey,
+    src_receiver: &Pubkey,
+    amount: u64,
+    timelock: u64,
+) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::GetCommitId {
+            sender: *sender,
+            receiver: *src_receiver,
+            sender_nonce: pda::sender_nonce(&PROGRAM_ID, sender),
+        },
+        instruction::GetCommitId { amount, timelock },
+    )
+}
+
+/// `commit`, signed by the sender. `page` is the sender's current contracts
+/// page, see [`crate::decode_current_page`].
+pub fn commit(sender: &Pubkey, page: u32, params: &CommitParams) -> Instruction {
+    let (htlc, commit_bump) = pda::htlc(&PROGRAM_ID, sender, &params.Id);
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::Commit {
+            sender: *sender,
+            config: pda::config(&PROGRAM_ID),
+            htlc,
+            contracts_index: pda::contracts_index(&PROGRAM_ID, sender),
+            contracts_page: pda::contracts_page(&PROGRAM_ID, sender, page),
+            sender_nonce: pda::sender_nonce(&PROGRAM_ID, sender),
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::Commit {
+            Id: params.Id,
+            hopChains: params.hop_chains.clone(),
+            hopAssets: params.hop_assets.clone(),
+            hopAddresses: params.hop_addresses.clone(),
+            dst_chain: params.dst_chain.clone(),
+            dst_asset: params.dst_asset.clone(),
+            dst_address: params.dst_address.clone(),
+            src_asset: params.src_asset.clone(),
+            src_receiver: params.src_receiver,
+            timelock: params.timelock,
+            amount: params.amount,
+            hash_algorithm: params.hash_algorithm.into(),
+            keeper_bounty: params.keeper_bounty,
+            commit_bump,
+        },
+    )
+}
+
+/// `lock`, signed by the sender. `page` is the sender's current contracts page.
+pub fn lock(sender: &Pubkey, page: u32, params: &LockParams) -> Instruction {
+    let (htlc, lock_bump) = pda::htlc(&PROGRAM_ID, sender, &params.Id);
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::Lock {
+            sender: *sender,
+            config: pda::config(&PROGRAM_ID),
+            htlc,
+            contracts_index: pda::contracts_index(&PROGRAM_ID, sender),
+            contracts_page: pda::contracts_page(&PROGRAM_ID, sender, page),
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::Lock {
+            Id: params.Id,
+            hashlock: params.hashlock,
+            timelock: params.timelock,
+            amount: params.amount,
+            reward: params.reward,
+            reward_timelock: params.reward_timelock,
+            dst_chain: params.dst_chain.clone(),
+            dst_address: params.dst_address.clone(),
+            dst_asset: params.dst_asset.clone(),
+            src_asset: params.src_asset.clone(),
+            src_receiver: params.src_receiver,
+            hash_algorithm: params.hash_algorithm.into(),
+            keeper_bounty: params.keeper_bounty,
+            lock_bump,
+        },
+    )
+}
+
+/// `add_lock`, signed by the sender and the payer.
+pub fn add_lock(
+    sender: &Pubkey,
+    payer: &Pubkey,
+    Id: [u8; 32],
+    hashlock: [u8; 32],
+    timelock: u64,
+    hash_algorithm: HashAlgorithm,
+) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::AddLock {
+            sender: *sender,
+            payer: *payer,
+            htlc: pda::htlc(&PROGRAM_ID, sender, &Id).0,
+            config: pda::config(&PROGRAM_ID),
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::AddLock {
+            Id,
+            hashlock,
+            timelock,
+            hash_algorithm: hash_algorithm.into(),
+        },
+    )
+}
+
+/// The Ed25519 verification and the `add_lock_sig` instruction that has to
+/// follow it, signed by the payer only. `signature` is the sender's signature
+/// over [`crate::add_lock_sig_message`].
+pub fn add_lock_sig(
+    payer: &Pubkey,
+    sender: &Pubkey,
+    Id: [u8; 32],
+    hashlock: [u8; 32],
+    timelock: u64,
+    hash_algorithm: HashAlgorithm,
+    signature: [u8; 64],
+) -> [Instruction; 2] {
+    let message = add_lock_sig_message(
+        &PROGRAM_ID.to_bytes(),
+        &Id,
+        &hashlock,
+        timelock,
+        hash_algorithm,
+    );
+    [
+        ed25519_verify_instruction(sender, &signature, &message),
+        anchor_instruction(
+            PROGRAM_ID,
+            accounts::AddLockSig {
+                payer: *payer,
+                htlc: pda::htlc(&PROGRAM_ID, sender, &Id).0,
+                config: pda::config(&PROGRAM_ID),
+                ix_sysvar: sysvar::instructions::ID,
+            },
+            instruction::AddLockSig {
+                Id,
+                hashlock,
+                timelock,
+                hash_algorithm: hash_algorithm.into(),
+                signature,
+            },
+        ),
+    ]
+}
+
+/// `extend_timelock`, signed by the sender.
+pub fn extend_timelock(sender: &Pubkey, Id: [u8; 32], timelock: u64) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::ExtendTimelock {
+            sender: *sender,
+            htlc: pda::htlc(&PROGRAM_ID, sender, &Id).0,
+            config: pda::config(&PROGRAM_ID),
+        },
+        instruction::ExtendTimelock { Id, timelock },
+    )
+}
+
+/// `redeem` of the decoded `htlc`, signed by `user_signing`.
+pub fn redeem(user_signing: &Pubkey, Id: [u8; 32], htlc: &HTLC, secret: [u8; 32]) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::Redeem {
+            user_signing: *user_signing,
+            fee_vault: pda::fee_vault(&PROGRAM_ID),
+            htlc: pda::htlc(&PROGRAM_ID, &htlc.sender, &Id).0,
+            contracts_page: pda::contracts_page(&PROGRAM_ID, &htlc.sender, htlc.index_page),
+            sender: htlc.sender,
+            src_receiver: htlc.src_receiver,
+            payer: htlc.payer,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::Redeem { Id, secret },
+    )
+}
+
+/// `redeem_batch` of the decoded HTLCs, each with its Id and secret, signed by
+/// `user_signing`. At most [`sol::MAX_BATCH_LEN`] items.
+pub fn redeem_batch(user_signing: &Pubkey, items: &[([u8; 32], &HTLC, [u8; 32])]) -> Instruction {
+    let mut ix = anchor_instruction(
+        PROGRAM_ID,
+        accounts::RedeemBatch {
+            user_signing: *user_signing,
+            fee_vault: pda::fee_vault(&PROGRAM_ID),
+        },
+        instruction::RedeemBatch {
+            items: items
+                .iter()
+                .map(|&(Id, _, secret)| sol::RedeemBatchItem { Id, secret })
+                .collect(),
+        },
+    );
+    for (Id, htlc, _) in items {
+        ix.accounts.extend([
+            AccountMeta::new(pda::htlc(&PROGRAM_ID, &htlc.sender, Id).0, false),
+            AccountMeta::new(
+                pda::contracts_page(&PROGRAM_ID, &htlc.sender, htlc.index_page),
+                false,
+            ),
+            AccountMeta::new(htlc.sender, false),
+            AccountMeta::new(htlc.src_receiver, false),
+            AccountMeta::new(htlc.payer, false),
+        ]);
+    }
+    ix
+}
+
+/// `refund` of the decoded `htlc`, signed by `user_signing`: anyone once the
+/// HTLC has expired. The keeper bounty, if any, goes to `user_signing`.
+pub fn refund(user_signing: &Pubkey, Id: [u8; 32], htlc: &HTLC) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::Refund {
+            user_signing: *user_signing,
+            htlc: pda::htlc(&PROGRAM_ID, &htlc.sender, &Id).0,
+            contracts_page: pda::contracts_page(&PROGRAM_ID, &htlc.sender, htlc.index_page),
+            sender: htlc.sender,
+            payer: htlc.payer,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::Refund { Id },
+    )
+}
+
+/// `refund_batch` of the decoded HTLCs of `sender`, each with its Id, signed by
+/// `user_signing`. At most [`sol::MAX_BATCH_LEN`] items.
+pub fn refund_batch(
+    user_signing: &Pubkey,
+    sender: &Pubkey,
+    items: &[([u8; 32], &HTLC)],
+) -> Instruction {
+    let mut ix = anchor_instruction(
+        PROGRAM_ID,
+        accounts::RefundBatch {
+            user_signing: *user_signing,
+            sender: *sender,
+        },
+        instruction::RefundBatch {
+            Ids: items.iter().map(|&(Id, _)| Id).collect(),
+        },
+    );
+    for (Id, htlc) in items {
+        ix.accounts.extend([
+            AccountMeta::new(pda::htlc(&PROGRAM_ID, sender, Id).0, false),
+            AccountMeta::new(
+                pda::contracts_page(&PROGRAM_ID, sender, htlc.index_page),
+                false,
+            ),
+            AccountMeta::new(htlc.payer, false),
+        ]);
+    }
+    ix
+}
+
+/// `redeem_legacy` of an HTLC at the `[Id]` address, signed by `user_signing`.
+pub fn redeem_legacy(
+    user_signing: &Pubkey,
+    Id: [u8; 32],
+    htlc: &LegacyHTLC,
+    secret: [u8; 32],
+) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::RedeemLegacy {
+            user_signing: *user_signing,
+            htlc: pda::legacy_htlc(&PROGRAM_ID, &Id),
+            sender: htlc.sender,
+            src_receiver: htlc.src_receiver,
+            system_program: system_program::ID,
+        },
+        instruction::RedeemLegacy { Id, secret },
+    )
+}
+
+/// `refund_legacy` of an HTLC at the `[Id]` address, signed by `user_signing`.
+pub fn refund_legacy(user_signing: &Pubkey, Id: [u8; 32], htlc: &LegacyHTLC) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::RefundLegacy {
+            user_signing: *user_signing,
+            htlc: pda::legacy_htlc(&PROGRAM_ID, &Id),
+            sender: htlc.sender,
+            system_program: system_program::ID,
+        },
+        instruction::RefundLegacy { Id },
+    )
+}
+
+/// `getDetails`: returns the HTLC. Nothing signs.
+pub fn get_details(sender: &Pubkey, Id: [u8; 32]) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::GetDetails {
+            htlc: pda::htlc(&PROGRAM_ID, sender, &Id).0,
+        },
+        instruction::GetDetails { Id },
+    )
+}
+
+/// `get_contracts`: returns one page of the sender's HTLC Ids. Nothing signs.
+pub fn get_contracts(sender: &Pubkey, page: u32) -> Instruction {
+    anchor_instruction(
+        PROGRAM_ID,
+        accounts::GetContracts {
+            contracts_page: pda::contracts_page(&PROGRAM_ID, sender, page),
+        },
+        instruction::GetContracts {
+            _sender: *sender,
+            _page: page,
+        },
+    )
+}
+
+/// Decodes the data of an HTLC account.
+pub fn decode_htlc(data: &[u8]) -> Result<HTLC, Error> {
+    decode(data, "HTLC")
+}
+
+/// Decodes the data of an HTLC account at a legacy `[Id]` address.
+pub fn decode_legacy_htlc(data: &[u8]) -> Result<LegacyHTLC, Error> {
+    if data.len() < 8 || data[..8] != HTLC::DISCRIMINATOR {
+        return Err(Error::InvalidAccount("LegacyHTLC"));
+    }
+    LegacyHTLC::deserialize(&mut &data[8..]).map_err(|_| Error::InvalidAccount("LegacyHTLC"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::AccountSerialize;
+
+    #[test]
+    fn redeem_reads_the_htlc_accounts() {
+        let Id = [9u8; 32];
+        let htlc = HTLC {
+            sender: Pubkey::new_unique(),
+            src_receiver: Pubkey::new_unique(),
+            payer: Pubkey::new_unique(),
+            index_page: 2,
+            ..HTLC::default()
+        };
+        let user_signing = Pubkey::new_unique();
+        let ix = redeem(&user_signing, Id, &htlc, [1u8; 32]);
+
+        let keys: Vec<Pubkey> = ix.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(ix.program_id, PROGRAM_ID);
+        assert_eq!(
+            keys[..7],
+            [
+                user_signing,
+                pda::fee_vault(&PROGRAM_ID),
+                pda::htlc(&PROGRAM_ID, &htlc.sender, &Id).0,
+                pda::contracts_page(&PROGRAM_ID, &htlc.sender, 2),
+                htlc.sender,
+                htlc.src_receiver,
+                htlc.payer,
+            ]
+        );
+        assert!(ix.accounts[0].is_signer);
+    }
+
+    #[test]
+    fn redeem_batch_appends_the_accounts_of_each_item() {
+        let htlcs: Vec<HTLC> = (0..2)
+            .map(|page| HTLC {
+                sender: Pubkey::new_unique(),
+                src_receiver: Pubkey::new_unique(),
+                payer: Pubkey::new_unique(),
+                index_page: page,
+                ..HTLC::default()
+            })
+            .collect();
+        let user_signing = Pubkey::new_unique();
+        let ix = redeem_batch(
+            &user_signing,
+            &[
+                ([1u8; 32], &htlcs[0], [2u8; 32]),
+                ([3u8; 32], &htlcs[1], [4u8; 32]),
+            ],
+        );
+
+        assert_eq!(ix.accounts.len(), 2 + 2 * sol::REDEEM_BATCH_ACCOUNTS);
+        let second = &ix.accounts[2 + sol::REDEEM_BATCH_ACCOUNTS..];
+        assert_eq!(
+            second[0].pubkey,
+            pda::htlc(&PROGRAM_ID, &htlcs[1].sender, &[3u8; 32]).0
+        );
+        assert_eq!(
+            second[1].pubkey,
+            pda::contracts_page(&PROGRAM_ID, &htlcs[1].sender, 1)
+        );
+        assert!(ix.accounts[3..]
+            .iter()
+            .all(|meta| meta.is_writable && !meta.is_signer));
+    }
+
+    #[test]
+    fn refund_batch_takes_the_sender_once() {
+        let sender = Pubkey::new_unique();
+        let htlc = HTLC {
+            sender,
+            payer: Pubkey::new_unique(),
+            index_page: 1,
+            ..HTLC::default()
+        };
+        let user_signing = Pubkey::new_unique();
+        let ix = refund_batch(
+            &user_signing,
+            &sender,
+            &[([1u8; 32], &htlc), ([2u8; 32], &htlc)],
+        );
+
+        let keys: Vec<Pubkey> = ix.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(keys.len(), 2 + 2 * sol::REFUND_BATCH_ACCOUNTS);
+        assert_eq!(
+            keys[2..5],
+            [
+                pda::htlc(&PROGRAM_ID, &sender, &[1u8; 32]).0,
+                pda::contracts_page(&PROGRAM_ID, &sender, 1),
+                htlc.payer,
+            ]
+        );
+        assert_eq!(keys[5], pda::htlc(&PROGRAM_ID, &sender, &[2u8; 32]).0);
+    }
+
+    #[test]
+    fn decodes_htlc_accounts() {
+        let htlc = HTLC {
+            sender: Pubkey::new_unique(),
+            amount: 42,
+            dst_chain: "ETHEREUM".to_string(),
+            ..HTLC::default()
+        };
+        let mut data = Vec::new();
+        htlc.try_serialize(&mut data).unwrap();
+
+        let decoded = decode_htlc(&data).unwrap();
+        assert_eq!(
+            (decoded.sender, decoded.amount, decoded.dst_chain),
+            (htlc.sender, 42, "ETHEREUM".to_string())
+        );
+        assert_eq!(
+            decode_htlc(&data[..8]).err(),
+            Some(Error::InvalidAccount("HTLC"))
+        );
+    }
+}
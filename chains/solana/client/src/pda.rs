@@ -0,0 +1,77 @@
+//! Addresses of the accounts the programs create. Every function takes the
+//! program id, so the same derivation serves native_htlc, anchor_htlc and any
+//! redeployment of either.
+use anchor_lang::solana_program::bpf_loader_upgradeable;
+use anchor_lang::solana_program::pubkey::Pubkey;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+
+/// The HTLC at `["htlc", sender, Id]`, with its bump.
+pub fn htlc(program_id: &Pubkey, sender: &Pubkey, Id: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"htlc", sender.as_ref(), Id], program_id)
+}
+
+/// anchor_htlc's token account for the HTLC, at `["htlc_token_account", sender, Id]`.
+pub fn htlc_token_account(program_id: &Pubkey, sender: &Pubkey, Id: &[u8; 32]) -> Pubkey {
+    Pubkey::find_program_address(&[b"htlc_token_account", sender.as_ref(), Id], program_id).0
+}
+
+/// An HTLC created before the seeds were namespaced by sender, at `[Id]`.
+pub fn legacy_htlc(program_id: &Pubkey, Id: &[u8; 32]) -> Pubkey {
+    Pubkey::find_program_address(&[Id], program_id).0
+}
+
+/// The token account of a legacy HTLC, at `["htlc_token_account", Id]`.
+pub fn legacy_htlc_token_account(program_id: &Pubkey, Id: &[u8; 32]) -> Pubkey {
+    Pubkey::find_program_address(&[b"htlc_token_account", Id], program_id).0
+}
+
+/// The sender's contracts index, at `["contracts_index", sender]`.
+pub fn contracts_index(program_id: &Pubkey, sender: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"contracts_index", sender.as_ref()], program_id).0
+}
+
+/// One page of the sender's HTLC Ids, at `["contracts_page", sender, page]`.
+pub fn contracts_page(program_id: &Pubkey, sender: &Pubkey, page: u32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"contracts_page", sender.as_ref(), &page.to_le_bytes()],
+        program_id,
+    )
+    .0
+}
+
+/// The sender's commit nonce, at `["nonce", sender]`.
+pub fn sender_nonce(program_id: &Pubkey, sender: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"nonce", sender.as_ref()], program_id).0
+}
+
+/// The program's Config, at `["config"]`.
+pub fn config(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"config"], program_id).0
+}
+
+/// The program's ProgramData, at `[program_id]` under the upgradeable BPF
+/// loader. Its upgrade authority is the only signer initialize_config takes.
+pub fn program_data(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::ID).0
+}
+
+/// native_htlc's fee vault, at `["fee_vault"]`.
+pub fn fee_vault(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"fee_vault"], program_id).0
+}
+
+/// anchor_htlc's fee vault for `mint`, at `["fee_vault", mint]`.
+pub fn token_fee_vault(program_id: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"fee_vault", mint.as_ref()], program_id).0
+}
+
+/// anchor_htlc's AllowedMint for `mint`, at `["allowed_mint", mint]`.
+pub fn allowed_mint(program_id: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"allowed_mint", mint.as_ref()], program_id).0
+}
+
+/// The associated token account of `owner` for `mint` under `token_program`
+/// (SPL Token or Token-2022).
+pub fn associated_token_account(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(owner, mint, token_program)
+}
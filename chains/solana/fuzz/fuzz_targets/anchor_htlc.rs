@@ -0,0 +1,10 @@
+use honggfuzz::fuzz;
+use train_htlc_fuzz::{run_anchor_htlc, Scenario};
+
+fn main() {
+    loop {
+        fuzz!(|scenario: Scenario| {
+            run_anchor_htlc(&scenario);
+        });
+    }
+}
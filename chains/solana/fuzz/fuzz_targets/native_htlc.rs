@@ -0,0 +1,10 @@
+use honggfuzz::fuzz;
+use train_htlc_fuzz::{run_native_htlc, Scenario};
+
+fn main() {
+    loop {
+        fuzz!(|scenario: Scenario| {
+            run_native_htlc(&scenario);
+        });
+    }
+}
@@ -0,0 +1,537 @@
+use anchor_lang::prelude::AccountInfo;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_spl::associated_token::{
+    self, get_associated_token_address, spl_associated_token_account,
+};
+use anchor_spl::token::spl_token::{
+    self,
+    solana_program::program_pack::Pack,
+    state::{Account as TokenAccountState, Mint as MintState},
+};
+use native::{accounts, instruction, ContractsIndex, SenderNonce, HTLC};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    bpf_loader_upgradeable,
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction, system_program, sysvar,
+};
+use train_htlc_core::{derive_commit_id, HashAlgorithm};
+use train_htlc_program_tests::{
+    anchor_account, anchor_ix, balance, funded_keypair, process, set_program_data, Outcome,
+};
+
+use crate::{
+    send, Balances, Htlc, OnChain, Program, MAX_TIMELOCK_WINDOW, MIN_TIMELOCK_WINDOW, USERS,
+};
+
+const DECIMALS: u8 = 6;
+/// Tokens minted to each user, enough for every deposit of a scenario.
+const SUPPLY: u64 = 1_000_000_000;
+
+fn entry<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+    data: &[u8],
+) -> ProgramResult {
+    // SAFETY: the accounts outlive the call; Anchor only needs the slice and
+    // its AccountInfos to share one lifetime.
+    let accounts: &'b [AccountInfo<'b>] = unsafe { std::mem::transmute(accounts) };
+    native::entry(program_id, accounts, data)
+}
+
+fn htlc_pda(sender: &Pubkey, Id: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"htlc", sender.as_ref(), Id], &native::ID)
+}
+
+fn htlc_token_account_pda(sender: &Pubkey, Id: &[u8; 32]) -> Pubkey {
+    Pubkey::find_program_address(&[b"htlc_token_account", sender.as_ref(), Id], &native::ID).0
+}
+
+fn contracts_index_pda(sender: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"contracts_index", sender.as_ref()], &native::ID).0
+}
+
+fn contracts_page_pda(sender: &Pubkey, page: u32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"contracts_page", sender.as_ref(), &page.to_le_bytes()],
+        &native::ID,
+    )
+    .0
+}
+
+fn nonce_pda(sender: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"nonce", sender.as_ref()], &native::ID).0
+}
+
+fn config_pda() -> Pubkey {
+    Pubkey::find_program_address(&[b"config"], &native::ID).0
+}
+
+fn program_data_pda() -> Pubkey {
+    Pubkey::find_program_address(&[native::ID.as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+fn fee_vault_pda(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"fee_vault", mint.as_ref()], &native::ID).0
+}
+
+fn allowed_mint_pda(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"allowed_mint", mint.as_ref()], &native::ID).0
+}
+
+/// The token balance of `address`, zero once the account is closed.
+async fn token_balance(ctx: &mut ProgramTestContext, address: Pubkey) -> u64 {
+    match ctx.banks_client.get_account(address).await.unwrap() {
+        Some(account) => TokenAccountState::unpack(&account.data).unwrap().amount,
+        None => 0,
+    }
+}
+
+pub(crate) struct AnchorHtlc {
+    ctx: ProgramTestContext,
+    users: Vec<Keypair>,
+    mint: Pubkey,
+    step: u32,
+}
+
+impl AnchorHtlc {
+    fn key(&self, user: usize) -> Pubkey {
+        self.users[user].pubkey()
+    }
+
+    fn token_account(&self, user: usize) -> Pubkey {
+        get_associated_token_address(&self.key(user), &self.mint)
+    }
+
+    async fn current_page(&mut self, sender: Pubkey) -> u32 {
+        anchor_account::<ContractsIndex>(&mut self.ctx, contracts_index_pda(&sender))
+            .await
+            .map_or(0, |index| index.current_page)
+    }
+
+    async fn index_page(&mut self, htlc: &Htlc) -> u32 {
+        let sender = self.key(htlc.sender);
+        anchor_account::<HTLC>(&mut self.ctx, htlc_pda(&sender, &htlc.Id).0)
+            .await
+            .map_or(0, |htlc| htlc.index_page)
+    }
+}
+
+impl Program for AnchorHtlc {
+    const LAMPORTS: bool = false;
+    const UNIT: u64 = 1;
+
+    async fn start() -> Self {
+        let program_test = ProgramTest::new("native", native::ID, processor!(entry));
+        let mut ctx = program_test.start_with_context().await;
+        let payer = ctx.payer.pubkey();
+        let rent = ctx.banks_client.get_rent().await.unwrap();
+
+        let mint = Keypair::new();
+        let ixs = [
+            system_instruction::create_account(
+                &payer,
+                &mint.pubkey(),
+                rent.minimum_balance(MintState::LEN),
+                MintState::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint2(
+                &spl_token::ID,
+                &mint.pubkey(),
+                &payer,
+                None,
+                DECIMALS,
+            )
+            .unwrap(),
+        ];
+        process(&mut ctx, &ixs, &[&mint]).await.assert_ok();
+        let mint = mint.pubkey();
+
+        // The fee payer is the admin, so the users' balances stay exact.
+        set_program_data(&mut ctx, &native::ID, payer).await;
+        let ix = anchor_ix(
+            native::ID,
+            accounts::InitializeConfig {
+                admin: payer,
+                program_data: program_data_pda(),
+                config: config_pda(),
+                system_program: system_program::ID,
+            },
+            instruction::InitializeConfig {},
+        );
+        process(&mut ctx, &[ix], &[]).await.assert_ok();
+        let ix = anchor_ix(
+            native::ID,
+            accounts::ConfigAdmin {
+                admin: payer,
+                config: config_pda(),
+            },
+            instruction::SetTimelockWindow {
+                min_window: MIN_TIMELOCK_WINDOW,
+                max_window: MAX_TIMELOCK_WINDOW,
+            },
+        );
+        process(&mut ctx, &[ix], &[]).await.assert_ok();
+        let ix = anchor_ix(
+            native::ID,
+            accounts::AllowMint {
+                admin: payer,
+                config: config_pda(),
+                allowed_mint: allowed_mint_pda(&mint),
+                token_contract: mint,
+                system_program: system_program::ID,
+            },
+            instruction::AllowMint {
+                src_asset: "USDC".to_string(),
+            },
+        );
+        process(&mut ctx, &[ix], &[]).await.assert_ok();
+
+        let mut users = Vec::with_capacity(USERS);
+        for _ in 0..USERS {
+            let user = funded_keypair(&mut ctx, 10 * LAMPORTS_PER_SOL).await;
+            let ixs = [
+                spl_associated_token_account::instruction::create_associated_token_account(
+                    &payer,
+                    &user.pubkey(),
+                    &mint,
+                    &spl_token::ID,
+                ),
+                spl_token::instruction::mint_to(
+                    &spl_token::ID,
+                    &mint,
+                    &get_associated_token_address(&user.pubkey(), &mint),
+                    &payer,
+                    &[],
+                    SUPPLY,
+                )
+                .unwrap(),
+            ];
+            process(&mut ctx, &ixs, &[]).await.assert_ok();
+            users.push(user);
+        }
+        AnchorHtlc {
+            ctx,
+            users,
+            mint,
+            step: 0,
+        }
+    }
+
+    fn ctx(&mut self) -> &mut ProgramTestContext {
+        &mut self.ctx
+    }
+
+    async fn next_commit_id(
+        &mut self,
+        sender: usize,
+        src_receiver: usize,
+        amount: u64,
+        timelock: u64,
+    ) -> [u8; 32] {
+        let sender_nonce = nonce_pda(&self.key(sender));
+        let nonce = anchor_account::<SenderNonce>(&mut self.ctx, sender_nonce)
+            .await
+            .map_or(0, |sender_nonce| sender_nonce.nonce);
+        derive_commit_id(
+            &native::ID.to_bytes(),
+            &self.key(sender).to_bytes(),
+            &self.key(src_receiver).to_bytes(),
+            amount,
+            timelock,
+            nonce,
+        )
+    }
+
+    async fn commit(
+        &mut self,
+        sender: usize,
+        src_receiver: usize,
+        Id: [u8; 32],
+        amount: u64,
+        timelock: u64,
+        keeper_bounty: u64,
+    ) -> Outcome {
+        let sender_key = self.key(sender);
+        let page = self.current_page(sender_key).await;
+        let ix = anchor_ix(
+            native::ID,
+            accounts::Commit {
+                sender: sender_key,
+                config: config_pda(),
+                htlc: htlc_pda(&sender_key, &Id).0,
+                contracts_index: contracts_index_pda(&sender_key),
+                contracts_page: contracts_page_pda(&sender_key, page),
+                sender_nonce: nonce_pda(&sender_key),
+                htlc_token_account: htlc_token_account_pda(&sender_key, &Id),
+                token_contract: self.mint,
+                fee_vault: fee_vault_pda(&self.mint),
+                allowed_mint: allowed_mint_pda(&self.mint),
+                sender_token_account: self.token_account(sender),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::Commit {
+                Id,
+                hopChains: vec!["ETHEREUM".to_string()],
+                hopAssets: vec!["USDC".to_string()],
+                hopAddress: vec!["0x1234".to_string()],
+                dst_chain: "ETHEREUM".to_string(),
+                dst_asset: "USDC".to_string(),
+                dst_address: "0x1234".to_string(),
+                src_asset: "USDC".to_string(),
+                src_receiver: self.key(src_receiver),
+                timelock,
+                amount,
+                hash_algorithm: HashAlgorithm::Sha256.into(),
+                keeper_bounty,
+                commit_bump: htlc_pda(&sender_key, &Id).1,
+            },
+        );
+        send(
+            &mut self.ctx,
+            &mut self.step,
+            vec![ix],
+            &[&self.users[sender]],
+        )
+        .await
+    }
+
+    async fn lock(&mut self, htlc: &Htlc) -> Outcome {
+        let sender = self.key(htlc.sender);
+        let page = self.current_page(sender).await;
+        let ix = anchor_ix(
+            native::ID,
+            accounts::Lock {
+                sender,
+                config: config_pda(),
+                htlc: htlc_pda(&sender, &htlc.Id).0,
+                contracts_index: contracts_index_pda(&sender),
+                contracts_page: contracts_page_pda(&sender, page),
+                htlc_token_account: htlc_token_account_pda(&sender, &htlc.Id),
+                token_contract: self.mint,
+                fee_vault: fee_vault_pda(&self.mint),
+                allowed_mint: allowed_mint_pda(&self.mint),
+                sender_token_account: self.token_account(htlc.sender),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::Lock {
+                Id: htlc.Id,
+                hashlock: htlc.hashlock,
+                timelock: htlc.timelock,
+                dst_chain: "ETHEREUM".to_string(),
+                dst_address: "0x1234".to_string(),
+                dst_asset: "USDC".to_string(),
+                src_asset: "USDC".to_string(),
+                src_receiver: self.key(htlc.src_receiver),
+                amount: htlc.amount,
+                reward: htlc.reward,
+                reward_timelock: htlc.reward_timelock,
+                hash_algorithm: htlc.hash_algorithm.into(),
+                keeper_bounty: htlc.keeper_bounty,
+                lock_bump: htlc_pda(&sender, &htlc.Id).1,
+            },
+        );
+        send(
+            &mut self.ctx,
+            &mut self.step,
+            vec![ix],
+            &[&self.users[htlc.sender]],
+        )
+        .await
+    }
+
+    async fn add_lock(
+        &mut self,
+        signer: usize,
+        htlc: &Htlc,
+        hashlock: [u8; 32],
+        hash_algorithm: HashAlgorithm,
+        timelock: u64,
+    ) -> Outcome {
+        let ix = anchor_ix(
+            native::ID,
+            accounts::AddLock {
+                sender: self.key(signer),
+                htlc: htlc_pda(&self.key(htlc.sender), &htlc.Id).0,
+                config: config_pda(),
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::AddLock {
+                Id: htlc.Id,
+                hashlock,
+                timelock,
+                hash_algorithm: hash_algorithm.into(),
+            },
+        );
+        send(
+            &mut self.ctx,
+            &mut self.step,
+            vec![ix],
+            &[&self.users[signer]],
+        )
+        .await
+    }
+
+    async fn extend_timelock(&mut self, signer: usize, htlc: &Htlc, timelock: u64) -> Outcome {
+        let ix = anchor_ix(
+            native::ID,
+            accounts::ExtendTimelock {
+                sender: self.key(signer),
+                htlc: htlc_pda(&self.key(htlc.sender), &htlc.Id).0,
+                config: config_pda(),
+            },
+            instruction::ExtendTimelock {
+                Id: htlc.Id,
+                timelock,
+            },
+        );
+        send(
+            &mut self.ctx,
+            &mut self.step,
+            vec![ix],
+            &[&self.users[signer]],
+        )
+        .await
+    }
+
+    async fn redeem(&mut self, signer: usize, htlc: &Htlc, secret: [u8; 32]) -> Outcome {
+        let sender = self.key(htlc.sender);
+        let page = self.index_page(htlc).await;
+        let ix = anchor_ix(
+            native::ID,
+            accounts::Redeem {
+                user_signing: self.key(signer),
+                fee_vault: None,
+                htlc: htlc_pda(&sender, &htlc.Id).0,
+                contracts_page: contracts_page_pda(&sender, page),
+                htlc_token_account: htlc_token_account_pda(&sender, &htlc.Id),
+                src_receiver_token_account: self.token_account(htlc.src_receiver),
+                sender_token_account: self.token_account(htlc.sender),
+                reward_token_account: (htlc.reward != 0).then(|| self.token_account(signer)),
+                sender,
+                src_receiver: self.key(htlc.src_receiver),
+                payer: sender,
+                token_contract: self.mint,
+                system_program: system_program::ID,
+                token_program: spl_token::ID,
+                associated_token_program: associated_token::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::Redeem {
+                Id: htlc.Id,
+                secret,
+                htlc_bump: htlc_pda(&sender, &htlc.Id).1,
+            },
+        );
+        send(
+            &mut self.ctx,
+            &mut self.step,
+            vec![ix],
+            &[&self.users[signer]],
+        )
+        .await
+    }
+
+    async fn refund(&mut self, signer: usize, htlc: &Htlc) -> Outcome {
+        let sender = self.key(htlc.sender);
+        let page = self.index_page(htlc).await;
+        let ix = anchor_ix(
+            native::ID,
+            accounts::Refund {
+                user_signing: self.key(signer),
+                htlc: htlc_pda(&sender, &htlc.Id).0,
+                contracts_page: contracts_page_pda(&sender, page),
+                htlc_token_account: htlc_token_account_pda(&sender, &htlc.Id),
+                sender,
+                payer: sender,
+                token_contract: self.mint,
+                sender_token_account: self.token_account(htlc.sender),
+                system_program: system_program::ID,
+                token_program: spl_token::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::Refund {
+                Id: htlc.Id,
+                htlc_bump: htlc_pda(&sender, &htlc.Id).1,
+            },
+        );
+        send(
+            &mut self.ctx,
+            &mut self.step,
+            vec![ix],
+            &[&self.users[signer]],
+        )
+        .await
+    }
+
+    async fn on_chain(&mut self, htlc: &Htlc) -> Option<OnChain> {
+        let sender = self.key(htlc.sender);
+        let state: HTLC = anchor_account(&mut self.ctx, htlc_pda(&sender, &htlc.Id).0).await?;
+        let escrow = token_balance(&mut self.ctx, htlc_token_account_pda(&sender, &htlc.Id)).await;
+        Some(OnChain {
+            hashlock: state.hashlock,
+            hash_algorithm: state.hash_algorithm.into(),
+            timelock: state.timelock,
+            amount: state.amount,
+            reward: state.reward,
+            reward_timelock: state.reward_timelock,
+            keeper_bounty: state.keeper_bounty,
+            status: state.status.into(),
+            escrow,
+        })
+    }
+
+    async fn closing_lamports(&mut self, htlc: &Htlc) -> u64 {
+        let sender = self.key(htlc.sender);
+        balance(&mut self.ctx, htlc_pda(&sender, &htlc.Id).0).await
+            + balance(&mut self.ctx, htlc_token_account_pda(&sender, &htlc.Id)).await
+    }
+
+    async fn balances(&mut self, htlcs: &[Htlc]) -> Balances {
+        let mut lamports = [0u64; USERS];
+        let mut tokens = [0u64; USERS];
+        let mut held_lamports = 0;
+        for user in 0..USERS {
+            let key = self.key(user);
+            lamports[user] = balance(&mut self.ctx, key).await;
+            let token_account = self.token_account(user);
+            tokens[user] = token_balance(&mut self.ctx, token_account).await;
+            held_lamports += balance(&mut self.ctx, contracts_index_pda(&key)).await
+                + balance(&mut self.ctx, nonce_pda(&key)).await;
+            for page in 0..=self.current_page(key).await {
+                held_lamports += balance(&mut self.ctx, contracts_page_pda(&key, page)).await;
+            }
+        }
+        // An Id may be locked again once its HTLC is closed; count each account once.
+        let mut ids: Vec<(Pubkey, [u8; 32])> = htlcs
+            .iter()
+            .map(|htlc| (self.key(htlc.sender), htlc.Id))
+            .collect();
+        ids.sort();
+        ids.dedup();
+        // The first commit or lock creates the mint's fee vault.
+        let fee_vault = fee_vault_pda(&self.mint);
+        held_lamports += balance(&mut self.ctx, fee_vault).await;
+        let mut held_tokens = token_balance(&mut self.ctx, fee_vault).await;
+        for (sender, Id) in ids {
+            let token_account = htlc_token_account_pda(&sender, &Id);
+            held_lamports += balance(&mut self.ctx, htlc_pda(&sender, &Id).0).await
+                + balance(&mut self.ctx, token_account).await;
+            held_tokens += token_balance(&mut self.ctx, token_account).await;
+        }
+        Balances {
+            lamports,
+            tokens,
+            held_lamports,
+            held_tokens,
+        }
+    }
+}
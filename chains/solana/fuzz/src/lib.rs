@@ -0,0 +1,548 @@
+//! Invariant fuzzing of HTLC instruction sequences.
+//!
+//! A [`Scenario`] is a sequence of `commit` / `lock` / `add_lock` / `redeem` /
+//! `refund` calls from a few users, interleaved with clock jumps. Each call is
+//! sent to the program under solana-program-test and checked against a
+//! reference model after every step:
+//!
+//! - the call succeeds exactly when the model says it may,
+//! - the users' balances move exactly by what the model pays out,
+//! - lamports and tokens are conserved across the users and the accounts the
+//!   program holds,
+//! - every open HTLC holds its full deposit and matches the model, status
+//!   included, and every redeemed or refunded HTLC is closed,
+//! - a refund, from any signer, pays the keeper bounty to that signer.
+//!
+//! The honggfuzz targets in `fuzz_targets/` feed [`run_native_htlc`] and
+//! [`run_anchor_htlc`] with arbitrary scenarios.
+#![allow(non_snake_case)]
+
+use arbitrary::Arbitrary;
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, instruction::Instruction, signature::Keypair,
+};
+use train_htlc_core::{HashAlgorithm, HtlcStatus};
+use train_htlc_program_tests::{now, process, warp_to, Outcome};
+
+mod anchor_htlc;
+mod native_htlc;
+
+/// Number of users taking part in a scenario.
+pub const USERS: usize = 3;
+/// Longest scenario run; longer inputs are truncated.
+pub const MAX_ACTIONS: usize = 48;
+const SECRETS: u8 = 4;
+/// Lamports a keeper bounty is counted in.
+const KEEPER_BOUNTY_UNIT: u64 = 10_000;
+/// The timelock window `start` configures, in seconds. The scenario timelocks
+/// fall on both sides of it.
+const MIN_TIMELOCK_WINDOW: u64 = 2 * 60;
+const MAX_TIMELOCK_WINDOW: u64 = 3 * 60 * 60;
+
+#[derive(Arbitrary, Clone, Debug)]
+pub enum Action {
+    Commit {
+        sender: u8,
+        src_receiver: u8,
+        amount: u16,
+        timelock: u8,
+        keeper_bounty: u8,
+    },
+    Lock {
+        sender: u8,
+        src_receiver: u8,
+        Id: u8,
+        secret: u8,
+        amount: u16,
+        reward: u16,
+        timelock: u8,
+        reward_timelock: u8,
+        /// Keccak-256 instead of SHA-256.
+        keccak: bool,
+        keeper_bounty: u8,
+    },
+    AddLock {
+        htlc: u8,
+        signer: u8,
+        secret: u8,
+        timelock: u8,
+        keccak: bool,
+    },
+    ExtendTimelock {
+        htlc: u8,
+        signer: u8,
+        timelock: u8,
+    },
+    Redeem {
+        htlc: u8,
+        signer: u8,
+        secret: u8,
+    },
+    Refund {
+        htlc: u8,
+        signer: u8,
+    },
+    Warp {
+        minutes: u8,
+    },
+}
+
+#[derive(Arbitrary, Clone, Debug)]
+pub struct Scenario {
+    pub actions: Vec<Action>,
+}
+
+/// Runs the scenario against native_htlc, panicking on the first broken invariant.
+pub fn run_native_htlc(scenario: &Scenario) {
+    run::<native_htlc::NativeHtlc>(scenario);
+}
+
+/// Runs the scenario against anchor_htlc, panicking on the first broken invariant.
+pub fn run_anchor_htlc(scenario: &Scenario) {
+    run::<anchor_htlc::AnchorHtlc>(scenario);
+}
+
+/// The model of one HTLC the program created.
+#[derive(Clone, Debug)]
+struct Htlc {
+    sender: usize,
+    src_receiver: usize,
+    Id: [u8; 32],
+    hashlock: [u8; 32],
+    hash_algorithm: HashAlgorithm,
+    timelock: u64,
+    amount: u64,
+    reward: u64,
+    reward_timelock: u64,
+    keeper_bounty: u64,
+    status: HtlcStatus,
+}
+
+/// The fields of an HTLC account the model is checked against.
+#[derive(Debug, PartialEq, Eq)]
+struct OnChain {
+    hashlock: [u8; 32],
+    hash_algorithm: HashAlgorithm,
+    timelock: u64,
+    amount: u64,
+    reward: u64,
+    reward_timelock: u64,
+    keeper_bounty: u64,
+    status: HtlcStatus,
+    /// Lamports (native_htlc) or tokens (anchor_htlc) held for amount + reward,
+    /// plus the keeper bounty in native_htlc, where it shares the lamports.
+    escrow: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Balances {
+    lamports: [u64; USERS],
+    tokens: [u64; USERS],
+    /// Lamports held by the accounts the program created.
+    held_lamports: u64,
+    /// Tokens held by the HTLC token accounts.
+    held_tokens: u64,
+}
+
+/// One of the HTLC programs, driven through solana-program-test.
+trait Program: Sized {
+    /// Whether the HTLCs lock lamports rather than tokens.
+    const LAMPORTS: bool;
+    /// Smallest amount an action deposits, in lamports or token base units.
+    const UNIT: u64;
+
+    async fn start() -> Self;
+    fn ctx(&mut self) -> &mut ProgramTestContext;
+
+    /// The Id the sender's next commit has to use.
+    async fn next_commit_id(
+        &mut self,
+        sender: usize,
+        src_receiver: usize,
+        amount: u64,
+        timelock: u64,
+    ) -> [u8; 32];
+    async fn commit(
+        &mut self,
+        sender: usize,
+        src_receiver: usize,
+        Id: [u8; 32],
+        amount: u64,
+        timelock: u64,
+        keeper_bounty: u64,
+    ) -> Outcome;
+    async fn lock(&mut self, htlc: &Htlc) -> Outcome;
+    async fn add_lock(
+        &mut self,
+        signer: usize,
+        htlc: &Htlc,
+        hashlock: [u8; 32],
+        hash_algorithm: HashAlgorithm,
+        timelock: u64,
+    ) -> Outcome;
+    async fn extend_timelock(&mut self, signer: usize, htlc: &Htlc, timelock: u64) -> Outcome;
+    async fn redeem(&mut self, signer: usize, htlc: &Htlc, secret: [u8; 32]) -> Outcome;
+    async fn refund(&mut self, signer: usize, htlc: &Htlc) -> Outcome;
+
+    async fn on_chain(&mut self, htlc: &Htlc) -> Option<OnChain>;
+    /// Lamports of the accounts closed when the HTLC is redeemed or refunded.
+    async fn closing_lamports(&mut self, htlc: &Htlc) -> u64;
+    async fn balances(&mut self, htlcs: &[Htlc]) -> Balances;
+}
+
+/// Sends the instructions with a compute unit limit unique to this step, so
+/// repeating a call, e.g. a refund after a clock jump, is a new transaction.
+async fn send(
+    ctx: &mut ProgramTestContext,
+    step: &mut u32,
+    instructions: Vec<Instruction>,
+    signers: &[&Keypair],
+) -> Outcome {
+    *step += 1;
+    let mut all = vec![ComputeBudgetInstruction::set_compute_unit_limit(
+        1_400_000 - *step,
+    )];
+    all.extend(instructions);
+    process(ctx, &all, signers).await
+}
+
+fn secret(index: u8) -> [u8; 32] {
+    [index % SECRETS + 1; 32]
+}
+
+fn hash_algorithm(keccak: bool) -> HashAlgorithm {
+    if keccak {
+        HashAlgorithm::Keccak256
+    } else {
+        HashAlgorithm::Sha256
+    }
+}
+
+/// A timelock between 16 minutes in the past and about 4 hours ahead.
+fn timelock_at(now: i64, minutes: u8) -> u64 {
+    (now + (i64::from(minutes) - 16) * 60) as u64
+}
+
+/// Whether commit, lock and add_lock accept the timelock at `now`.
+fn in_timelock_window(timelock: u64, now: i64) -> bool {
+    let window = timelock as i64 - now;
+    window >= MIN_TIMELOCK_WINDOW as i64 && window <= MAX_TIMELOCK_WINDOW as i64
+}
+
+/// Whether extend_timelock accepts moving the HTLC's timelock to `timelock` at
+/// `now`: later than before, in the future and within the maximum window.
+fn extends_timelock(htlc: &Htlc, timelock: u64, now: i64) -> bool {
+    timelock > htlc.timelock
+        && timelock as i64 > now
+        && timelock as i64 - now <= MAX_TIMELOCK_WINDOW as i64
+}
+
+/// How the model pays out a redeem: (src_receiver, sender, redeemer).
+fn payout(htlc: &Htlc, now: i64, redeemer_is_src_receiver: bool) -> (u64, u64, u64) {
+    if htlc.reward == 0 {
+        (htlc.amount, 0, 0)
+    } else if htlc.reward_timelock as i64 > now {
+        (htlc.amount, htlc.reward, 0)
+    } else if redeemer_is_src_receiver {
+        (htlc.amount + htlc.reward, 0, 0)
+    } else {
+        (htlc.amount, 0, htlc.reward)
+    }
+}
+
+fn run<P: Program>(scenario: &Scenario) {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(run_async::<P>(scenario));
+}
+
+async fn run_async<P: Program>(scenario: &Scenario) {
+    let mut program = P::start().await;
+    let mut htlcs: Vec<Htlc> = Vec::new();
+    let initial = program.balances(&htlcs).await;
+    let total_lamports = initial.lamports.iter().sum::<u64>() + initial.held_lamports;
+    let total_tokens = initial.tokens.iter().sum::<u64>() + initial.held_tokens;
+
+    for action in scenario.actions.iter().take(MAX_ACTIONS) {
+        let now = now(program.ctx()).await;
+        let before = program.balances(&htlcs).await;
+        let mut expected = before.clone();
+        // A commit or lock also pays the rent of the accounts it creates, which
+        // only the conservation check accounts for.
+        let mut depositor = None;
+        let pick = |index: u8, htlcs: &[Htlc]| {
+            (!htlcs.is_empty()).then(|| usize::from(index) % htlcs.len())
+        };
+
+        let (allowed, outcome) = match *action {
+            Action::Commit {
+                sender,
+                src_receiver,
+                amount,
+                timelock,
+                keeper_bounty,
+            } => {
+                let sender = usize::from(sender) % USERS;
+                let src_receiver = usize::from(src_receiver) % USERS;
+                let amount = u64::from(amount) * P::UNIT;
+                let timelock = timelock_at(now, timelock);
+                let keeper_bounty = u64::from(keeper_bounty) * KEEPER_BOUNTY_UNIT;
+                let allowed = amount != 0 && in_timelock_window(timelock, now);
+
+                let Id = program
+                    .next_commit_id(sender, src_receiver, amount, timelock)
+                    .await;
+                let outcome = program
+                    .commit(sender, src_receiver, Id, amount, timelock, keeper_bounty)
+                    .await;
+                if outcome.result.is_ok() {
+                    htlcs.push(Htlc {
+                        sender,
+                        src_receiver,
+                        Id,
+                        hashlock: [0u8; 32],
+                        hash_algorithm: HashAlgorithm::Sha256,
+                        timelock,
+                        amount,
+                        reward: 0,
+                        reward_timelock: 0,
+                        keeper_bounty,
+                        status: HtlcStatus::Committed,
+                    });
+                    depositor = Some(sender);
+                    if !P::LAMPORTS {
+                        expected.tokens[sender] -= amount;
+                    }
+                }
+                (allowed, outcome)
+            }
+            Action::Lock {
+                sender,
+                src_receiver,
+                Id,
+                secret: secret_index,
+                amount,
+                reward,
+                timelock,
+                reward_timelock,
+                keccak,
+                keeper_bounty,
+            } => {
+                let hash_algorithm = hash_algorithm(keccak);
+                let htlc = Htlc {
+                    sender: usize::from(sender) % USERS,
+                    src_receiver: usize::from(src_receiver) % USERS,
+                    Id: [Id; 32],
+                    hashlock: hash_algorithm.hash(&secret(secret_index)),
+                    hash_algorithm,
+                    timelock: timelock_at(now, timelock),
+                    amount: u64::from(amount) * P::UNIT,
+                    reward: u64::from(reward) * P::UNIT,
+                    reward_timelock: timelock_at(now, reward_timelock),
+                    keeper_bounty: u64::from(keeper_bounty) * KEEPER_BOUNTY_UNIT,
+                    status: HtlcStatus::Locked,
+                };
+                let taken = htlcs.iter().any(|other| {
+                    other.status.is_open() && other.sender == htlc.sender && other.Id == htlc.Id
+                });
+                let allowed = !taken
+                    && htlc.amount != 0
+                    && in_timelock_window(htlc.timelock, now)
+                    && (htlc.reward == 0
+                        || (htlc.reward_timelock <= htlc.timelock
+                            && htlc.reward_timelock as i64 > now));
+
+                let outcome = program.lock(&htlc).await;
+                if outcome.result.is_ok() {
+                    depositor = Some(htlc.sender);
+                    if !P::LAMPORTS {
+                        expected.tokens[htlc.sender] -= htlc.amount + htlc.reward;
+                    }
+                    htlcs.push(htlc);
+                }
+                (allowed, outcome)
+            }
+            Action::AddLock {
+                htlc,
+                signer,
+                secret: secret_index,
+                timelock,
+                keccak,
+            } => {
+                let Some(index) = pick(htlc, &htlcs) else {
+                    continue;
+                };
+                let signer = usize::from(signer) % USERS;
+                let hash_algorithm = hash_algorithm(keccak);
+                let hashlock = hash_algorithm.hash(&secret(secret_index));
+                let timelock = timelock_at(now, timelock);
+                let htlc = htlcs[index].clone();
+                let allowed = htlc.status == HtlcStatus::Committed
+                    && signer == htlc.sender
+                    && in_timelock_window(timelock, now);
+
+                let outcome = program
+                    .add_lock(signer, &htlc, hashlock, hash_algorithm, timelock)
+                    .await;
+                if outcome.result.is_ok() {
+                    htlcs[index].hashlock = hashlock;
+                    htlcs[index].hash_algorithm = hash_algorithm;
+                    htlcs[index].timelock = timelock;
+                    htlcs[index].status = HtlcStatus::Locked;
+                }
+                (allowed, outcome)
+            }
+            Action::ExtendTimelock {
+                htlc,
+                signer,
+                timelock,
+            } => {
+                let Some(index) = pick(htlc, &htlcs) else {
+                    continue;
+                };
+                let signer = usize::from(signer) % USERS;
+                let timelock = timelock_at(now, timelock);
+                let htlc = htlcs[index].clone();
+                let allowed = htlc.status.is_open()
+                    && signer == htlc.sender
+                    && extends_timelock(&htlc, timelock, now);
+
+                let outcome = program.extend_timelock(signer, &htlc, timelock).await;
+                if outcome.result.is_ok() {
+                    htlcs[index].timelock = timelock;
+                }
+                (allowed, outcome)
+            }
+            Action::Redeem {
+                htlc,
+                signer,
+                secret: secret_index,
+            } => {
+                let Some(index) = pick(htlc, &htlcs) else {
+                    continue;
+                };
+                let signer = usize::from(signer) % USERS;
+                let secret = secret(secret_index);
+                let htlc = htlcs[index].clone();
+                let allowed = htlc.status == HtlcStatus::Locked
+                    && htlc.hash_algorithm.hash(&secret) == htlc.hashlock;
+
+                let closing = program.closing_lamports(&htlc).await;
+                let outcome = program.redeem(signer, &htlc, secret).await;
+                if outcome.result.is_ok() {
+                    let (to_src_receiver, to_sender, to_redeemer) =
+                        payout(&htlc, now, signer == htlc.src_receiver);
+                    if P::LAMPORTS {
+                        expected.lamports[htlc.src_receiver] += to_src_receiver;
+                        expected.lamports[signer] += to_redeemer;
+                        expected.lamports[htlc.sender] += closing - to_src_receiver - to_redeemer;
+                    } else {
+                        expected.tokens[htlc.src_receiver] += to_src_receiver;
+                        expected.tokens[htlc.sender] += to_sender;
+                        expected.tokens[signer] += to_redeemer;
+                        expected.lamports[htlc.sender] += closing;
+                    }
+                    htlcs[index].status = HtlcStatus::Redeemed;
+                }
+                (allowed, outcome)
+            }
+            Action::Refund { htlc, signer } => {
+                let Some(index) = pick(htlc, &htlcs) else {
+                    continue;
+                };
+                let signer = usize::from(signer) % USERS;
+                let htlc = htlcs[index].clone();
+                let allowed = htlc.status.is_open() && now >= htlc.timelock as i64;
+
+                let closing = program.closing_lamports(&htlc).await;
+                let outcome = program.refund(signer, &htlc).await;
+                if outcome.result.is_ok() {
+                    expected.lamports[htlc.sender] += closing - htlc.keeper_bounty;
+                    expected.lamports[signer] += htlc.keeper_bounty;
+                    if !P::LAMPORTS {
+                        expected.tokens[htlc.sender] += htlc.amount + htlc.reward;
+                    }
+                    htlcs[index].status = HtlcStatus::Refunded;
+                }
+                (allowed, outcome)
+            }
+            Action::Warp { minutes } => {
+                warp_to(program.ctx(), now + i64::from(minutes) * 60).await;
+                continue;
+            }
+        };
+
+        assert_eq!(
+            outcome.result.is_ok(),
+            allowed,
+            "{action:?} was {} by the program but {} by the model: {:?}\n{:#?}",
+            if outcome.result.is_ok() {
+                "allowed"
+            } else {
+                "rejected"
+            },
+            if allowed { "allowed" } else { "rejected" },
+            outcome.result,
+            outcome.logs
+        );
+
+        let after = program.balances(&htlcs).await;
+        if let Some(user) = depositor {
+            expected.lamports[user] = after.lamports[user];
+        }
+        assert_eq!(
+            (after.lamports, after.tokens),
+            (expected.lamports, expected.tokens),
+            "{action:?} moved the wrong balances"
+        );
+        assert_eq!(
+            after.lamports.iter().sum::<u64>() + after.held_lamports,
+            total_lamports,
+            "{action:?} broke lamport conservation"
+        );
+        assert_eq!(
+            after.tokens.iter().sum::<u64>() + after.held_tokens,
+            total_tokens,
+            "{action:?} broke token conservation"
+        );
+
+        for htlc in &htlcs {
+            // A closed HTLC's address is reused once its Id is locked again.
+            let reopened = !htlc.status.is_open()
+                && htlcs.iter().any(|other| {
+                    other.status.is_open() && other.sender == htlc.sender && other.Id == htlc.Id
+                });
+            if reopened {
+                continue;
+            }
+            let on_chain = program.on_chain(htlc).await;
+            if htlc.status.is_open() {
+                assert_eq!(
+                    on_chain,
+                    Some(OnChain {
+                        hashlock: htlc.hashlock,
+                        hash_algorithm: htlc.hash_algorithm,
+                        timelock: htlc.timelock,
+                        amount: htlc.amount,
+                        reward: htlc.reward,
+                        reward_timelock: htlc.reward_timelock,
+                        keeper_bounty: htlc.keeper_bounty,
+                        status: htlc.status,
+                        escrow: htlc.amount
+                            + htlc.reward
+                            + if P::LAMPORTS { htlc.keeper_bounty } else { 0 },
+                    }),
+                    "after {action:?}, open HTLC {htlc:?} differs on chain"
+                );
+            } else {
+                assert!(
+                    on_chain.is_none(),
+                    "after {action:?}, {htlc:?} is still open on chain"
+                );
+            }
+        }
+    }
+}
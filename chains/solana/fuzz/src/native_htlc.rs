@@ -0,0 +1,412 @@
+use anchor_lang::prelude::AccountInfo;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use sol::{accounts, instruction, ContractsIndex, SenderNonce, HTLC};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    bpf_loader_upgradeable,
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_program, sysvar,
+};
+use train_htlc_core::{derive_commit_id, HashAlgorithm};
+use train_htlc_program_tests::{
+    anchor_account, anchor_ix, balance, funded_keypair, process, set_program_data, Outcome,
+};
+
+use crate::{
+    send, Balances, Htlc, OnChain, Program, MAX_TIMELOCK_WINDOW, MIN_TIMELOCK_WINDOW, USERS,
+};
+
+fn entry<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+    data: &[u8],
+) -> ProgramResult {
+    // SAFETY: the accounts outlive the call; Anchor only needs the slice and
+    // its AccountInfos to share one lifetime.
+    let accounts: &'b [AccountInfo<'b>] = unsafe { std::mem::transmute(accounts) };
+    sol::entry(program_id, accounts, data)
+}
+
+fn htlc_pda(sender: &Pubkey, Id: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"htlc", sender.as_ref(), Id], &sol::ID)
+}
+
+fn contracts_index_pda(sender: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"contracts_index", sender.as_ref()], &sol::ID).0
+}
+
+fn contracts_page_pda(sender: &Pubkey, page: u32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"contracts_page", sender.as_ref(), &page.to_le_bytes()],
+        &sol::ID,
+    )
+    .0
+}
+
+fn nonce_pda(sender: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"nonce", sender.as_ref()], &sol::ID).0
+}
+
+fn config_pda() -> Pubkey {
+    Pubkey::find_program_address(&[b"config"], &sol::ID).0
+}
+
+fn program_data_pda() -> Pubkey {
+    Pubkey::find_program_address(&[sol::ID.as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+fn fee_vault_pda() -> Pubkey {
+    Pubkey::find_program_address(&[b"fee_vault"], &sol::ID).0
+}
+
+pub(crate) struct NativeHtlc {
+    ctx: ProgramTestContext,
+    users: Vec<Keypair>,
+    step: u32,
+}
+
+impl NativeHtlc {
+    fn key(&self, user: usize) -> Pubkey {
+        self.users[user].pubkey()
+    }
+
+    async fn current_page(&mut self, sender: Pubkey) -> u32 {
+        anchor_account::<ContractsIndex>(&mut self.ctx, contracts_index_pda(&sender))
+            .await
+            .map_or(0, |index| index.current_page)
+    }
+
+    async fn index_page(&mut self, htlc: &Htlc) -> u32 {
+        let sender = self.key(htlc.sender);
+        anchor_account::<HTLC>(&mut self.ctx, htlc_pda(&sender, &htlc.Id).0)
+            .await
+            .map_or(0, |htlc| htlc.index_page)
+    }
+}
+
+impl Program for NativeHtlc {
+    const LAMPORTS: bool = true;
+    const UNIT: u64 = 10_000;
+
+    async fn start() -> Self {
+        let program_test = ProgramTest::new("sol", sol::ID, processor!(entry));
+        let mut ctx = program_test.start_with_context().await;
+        // The fee payer is the admin, so the users' balances stay exact.
+        let payer = ctx.payer.pubkey();
+        set_program_data(&mut ctx, &sol::ID, payer).await;
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::InitializeConfig {
+                admin: payer,
+                program_data: program_data_pda(),
+                config: config_pda(),
+                fee_vault: fee_vault_pda(),
+                system_program: system_program::ID,
+            },
+            instruction::InitializeConfig {},
+        );
+        process(&mut ctx, &[ix], &[]).await.assert_ok();
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::ConfigAdmin {
+                admin: payer,
+                config: config_pda(),
+            },
+            instruction::SetTimelockWindow {
+                min_window: MIN_TIMELOCK_WINDOW,
+                max_window: MAX_TIMELOCK_WINDOW,
+            },
+        );
+        process(&mut ctx, &[ix], &[]).await.assert_ok();
+        let mut users = Vec::with_capacity(USERS);
+        for _ in 0..USERS {
+            users.push(funded_keypair(&mut ctx, 100 * LAMPORTS_PER_SOL).await);
+        }
+        NativeHtlc {
+            ctx,
+            users,
+            step: 0,
+        }
+    }
+
+    fn ctx(&mut self) -> &mut ProgramTestContext {
+        &mut self.ctx
+    }
+
+    async fn next_commit_id(
+        &mut self,
+        sender: usize,
+        src_receiver: usize,
+        amount: u64,
+        timelock: u64,
+    ) -> [u8; 32] {
+        let sender_nonce = nonce_pda(&self.key(sender));
+        let nonce = anchor_account::<SenderNonce>(&mut self.ctx, sender_nonce)
+            .await
+            .map_or(0, |sender_nonce| sender_nonce.nonce);
+        derive_commit_id(
+            &sol::ID.to_bytes(),
+            &self.key(sender).to_bytes(),
+            &self.key(src_receiver).to_bytes(),
+            amount,
+            timelock,
+            nonce,
+        )
+    }
+
+    async fn commit(
+        &mut self,
+        sender: usize,
+        src_receiver: usize,
+        Id: [u8; 32],
+        amount: u64,
+        timelock: u64,
+        keeper_bounty: u64,
+    ) -> Outcome {
+        let sender_key = self.key(sender);
+        let page = self.current_page(sender_key).await;
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::Commit {
+                sender: sender_key,
+                config: config_pda(),
+                htlc: htlc_pda(&sender_key, &Id).0,
+                contracts_index: contracts_index_pda(&sender_key),
+                contracts_page: contracts_page_pda(&sender_key, page),
+                sender_nonce: nonce_pda(&sender_key),
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::Commit {
+                Id,
+                hopChains: vec!["ETHEREUM".to_string()],
+                hopAssets: vec!["ETH".to_string()],
+                hopAddresses: vec!["0x1234".to_string()],
+                dst_chain: "ETHEREUM".to_string(),
+                dst_asset: "ETH".to_string(),
+                dst_address: "0x1234".to_string(),
+                src_asset: "SOL".to_string(),
+                src_receiver: self.key(src_receiver),
+                timelock,
+                amount,
+                hash_algorithm: HashAlgorithm::Sha256.into(),
+                keeper_bounty,
+                commit_bump: htlc_pda(&sender_key, &Id).1,
+            },
+        );
+        send(
+            &mut self.ctx,
+            &mut self.step,
+            vec![ix],
+            &[&self.users[sender]],
+        )
+        .await
+    }
+
+    async fn lock(&mut self, htlc: &Htlc) -> Outcome {
+        let sender = self.key(htlc.sender);
+        let page = self.current_page(sender).await;
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::Lock {
+                sender,
+                config: config_pda(),
+                htlc: htlc_pda(&sender, &htlc.Id).0,
+                contracts_index: contracts_index_pda(&sender),
+                contracts_page: contracts_page_pda(&sender, page),
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::Lock {
+                Id: htlc.Id,
+                hashlock: htlc.hashlock,
+                timelock: htlc.timelock,
+                amount: htlc.amount,
+                reward: htlc.reward,
+                reward_timelock: htlc.reward_timelock,
+                dst_chain: "ETHEREUM".to_string(),
+                dst_address: "0x1234".to_string(),
+                dst_asset: "ETH".to_string(),
+                src_asset: "SOL".to_string(),
+                src_receiver: self.key(htlc.src_receiver),
+                hash_algorithm: htlc.hash_algorithm.into(),
+                keeper_bounty: htlc.keeper_bounty,
+                lock_bump: htlc_pda(&sender, &htlc.Id).1,
+            },
+        );
+        send(
+            &mut self.ctx,
+            &mut self.step,
+            vec![ix],
+            &[&self.users[htlc.sender]],
+        )
+        .await
+    }
+
+    async fn add_lock(
+        &mut self,
+        signer: usize,
+        htlc: &Htlc,
+        hashlock: [u8; 32],
+        hash_algorithm: HashAlgorithm,
+        timelock: u64,
+    ) -> Outcome {
+        let signer_key = self.key(signer);
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::AddLock {
+                sender: signer_key,
+                payer: signer_key,
+                htlc: htlc_pda(&self.key(htlc.sender), &htlc.Id).0,
+                config: config_pda(),
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::AddLock {
+                Id: htlc.Id,
+                hashlock,
+                timelock,
+                hash_algorithm: hash_algorithm.into(),
+            },
+        );
+        send(
+            &mut self.ctx,
+            &mut self.step,
+            vec![ix],
+            &[&self.users[signer]],
+        )
+        .await
+    }
+
+    async fn extend_timelock(&mut self, signer: usize, htlc: &Htlc, timelock: u64) -> Outcome {
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::ExtendTimelock {
+                sender: self.key(signer),
+                htlc: htlc_pda(&self.key(htlc.sender), &htlc.Id).0,
+                config: config_pda(),
+            },
+            instruction::ExtendTimelock {
+                Id: htlc.Id,
+                timelock,
+            },
+        );
+        send(
+            &mut self.ctx,
+            &mut self.step,
+            vec![ix],
+            &[&self.users[signer]],
+        )
+        .await
+    }
+
+    async fn redeem(&mut self, signer: usize, htlc: &Htlc, secret: [u8; 32]) -> Outcome {
+        let sender = self.key(htlc.sender);
+        let page = self.index_page(htlc).await;
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::Redeem {
+                user_signing: self.key(signer),
+                fee_vault: fee_vault_pda(),
+                htlc: htlc_pda(&sender, &htlc.Id).0,
+                contracts_page: contracts_page_pda(&sender, page),
+                sender,
+                src_receiver: self.key(htlc.src_receiver),
+                payer: sender,
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::Redeem {
+                Id: htlc.Id,
+                secret,
+            },
+        );
+        send(
+            &mut self.ctx,
+            &mut self.step,
+            vec![ix],
+            &[&self.users[signer]],
+        )
+        .await
+    }
+
+    async fn refund(&mut self, signer: usize, htlc: &Htlc) -> Outcome {
+        let sender = self.key(htlc.sender);
+        let page = self.index_page(htlc).await;
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::Refund {
+                user_signing: self.key(signer),
+                htlc: htlc_pda(&sender, &htlc.Id).0,
+                contracts_page: contracts_page_pda(&sender, page),
+                sender,
+                payer: sender,
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::Refund { Id: htlc.Id },
+        );
+        send(
+            &mut self.ctx,
+            &mut self.step,
+            vec![ix],
+            &[&self.users[signer]],
+        )
+        .await
+    }
+
+    async fn on_chain(&mut self, htlc: &Htlc) -> Option<OnChain> {
+        let address = htlc_pda(&self.key(htlc.sender), &htlc.Id).0;
+        let account = self.ctx.banks_client.get_account(address).await.unwrap()?;
+        let state: HTLC = anchor_account(&mut self.ctx, address).await?;
+        let rent = self.ctx.banks_client.get_rent().await.unwrap();
+        Some(OnChain {
+            hashlock: state.hashlock,
+            hash_algorithm: state.hash_algorithm.into(),
+            timelock: state.timelock,
+            amount: state.amount,
+            reward: state.reward,
+            reward_timelock: state.reward_timelock,
+            keeper_bounty: state.keeper_bounty,
+            status: state.status.into(),
+            escrow: account.lamports - rent.minimum_balance(account.data.len()),
+        })
+    }
+
+    async fn closing_lamports(&mut self, htlc: &Htlc) -> u64 {
+        let address = htlc_pda(&self.key(htlc.sender), &htlc.Id).0;
+        balance(&mut self.ctx, address).await
+    }
+
+    async fn balances(&mut self, htlcs: &[Htlc]) -> Balances {
+        let mut lamports = [0u64; USERS];
+        let mut held_lamports = balance(&mut self.ctx, fee_vault_pda()).await;
+        for (user, user_lamports) in lamports.iter_mut().enumerate() {
+            let key = self.key(user);
+            *user_lamports = balance(&mut self.ctx, key).await;
+            held_lamports += balance(&mut self.ctx, contracts_index_pda(&key)).await
+                + balance(&mut self.ctx, nonce_pda(&key)).await;
+            for page in 0..=self.current_page(key).await {
+                held_lamports += balance(&mut self.ctx, contracts_page_pda(&key, page)).await;
+            }
+        }
+        // An Id may be locked again once its HTLC is closed; count each address once.
+        let mut addresses: Vec<Pubkey> = htlcs
+            .iter()
+            .map(|htlc| htlc_pda(&self.key(htlc.sender), &htlc.Id).0)
+            .collect();
+        addresses.sort();
+        addresses.dedup();
+        for address in addresses {
+            held_lamports += balance(&mut self.ctx, address).await;
+        }
+        Balances {
+            lamports,
+            tokens: [0; USERS],
+            held_lamports,
+            held_tokens: 0,
+        }
+    }
+}
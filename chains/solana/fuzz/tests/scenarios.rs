@@ -0,0 +1,289 @@
+//! Fixed scenarios and a few seeded random ones, so the invariants are checked
+//! by `cargo test` without a fuzzing build.
+#![allow(non_snake_case)]
+
+use arbitrary::{Arbitrary, Unstructured};
+use train_htlc_fuzz::{run_anchor_htlc, run_native_htlc, Action, Scenario};
+
+fn both(actions: Vec<Action>) {
+    let scenario = Scenario { actions };
+    run_native_htlc(&scenario);
+    run_anchor_htlc(&scenario);
+}
+
+fn lock(Id: u8, secret: u8, reward: u16, reward_timelock: u8) -> Action {
+    Action::Lock {
+        sender: 0,
+        src_receiver: 1,
+        Id,
+        secret,
+        amount: 100,
+        reward,
+        timelock: 60,
+        reward_timelock,
+        keccak: false,
+        keeper_bounty: 0,
+    }
+}
+
+#[test]
+fn lock_then_redeem_twice() {
+    both(vec![
+        lock(7, 0, 0, 0),
+        Action::Redeem {
+            htlc: 0,
+            signer: 2,
+            secret: 1,
+        },
+        Action::Redeem {
+            htlc: 0,
+            signer: 1,
+            secret: 0,
+        },
+        Action::Redeem {
+            htlc: 0,
+            signer: 1,
+            secret: 0,
+        },
+        Action::Refund { htlc: 0, signer: 0 },
+    ]);
+}
+
+#[test]
+fn commit_add_lock_and_redeem() {
+    both(vec![
+        Action::Commit {
+            sender: 0,
+            src_receiver: 1,
+            amount: 100,
+            timelock: 60,
+            keeper_bounty: 0,
+        },
+        Action::Redeem {
+            htlc: 0,
+            signer: 1,
+            secret: 0,
+        },
+        Action::AddLock {
+            htlc: 0,
+            signer: 1,
+            secret: 0,
+            timelock: 90,
+            keccak: false,
+        },
+        Action::AddLock {
+            htlc: 0,
+            signer: 0,
+            secret: 0,
+            timelock: 90,
+            keccak: false,
+        },
+        Action::AddLock {
+            htlc: 0,
+            signer: 0,
+            secret: 1,
+            timelock: 90,
+            keccak: false,
+        },
+        Action::Redeem {
+            htlc: 0,
+            signer: 1,
+            secret: 0,
+        },
+    ]);
+}
+
+#[test]
+fn keccak_hashlocks_redeem_only_with_keccak() {
+    let mut keccak_lock = lock(5, 0, 0, 0);
+    if let Action::Lock { keccak, .. } = &mut keccak_lock {
+        *keccak = true;
+    }
+    both(vec![
+        keccak_lock,
+        Action::Commit {
+            sender: 0,
+            src_receiver: 1,
+            amount: 100,
+            timelock: 60,
+            keeper_bounty: 0,
+        },
+        Action::AddLock {
+            htlc: 1,
+            signer: 0,
+            secret: 2,
+            timelock: 90,
+            keccak: true,
+        },
+        Action::Redeem {
+            htlc: 0,
+            signer: 1,
+            secret: 1,
+        },
+        Action::Redeem {
+            htlc: 0,
+            signer: 1,
+            secret: 0,
+        },
+        Action::Redeem {
+            htlc: 1,
+            signer: 2,
+            secret: 2,
+        },
+    ]);
+}
+
+#[test]
+fn refund_only_after_the_timelock() {
+    both(vec![
+        Action::Commit {
+            sender: 0,
+            src_receiver: 1,
+            amount: 100,
+            timelock: 20,
+            keeper_bounty: 0,
+        },
+        Action::Commit {
+            sender: 0,
+            src_receiver: 1,
+            amount: 0,
+            timelock: 20,
+            keeper_bounty: 0,
+        },
+        Action::Commit {
+            sender: 0,
+            src_receiver: 1,
+            amount: 100,
+            timelock: 10,
+            keeper_bounty: 0,
+        },
+        Action::Refund { htlc: 0, signer: 2 },
+        Action::Warp { minutes: 5 },
+        Action::Refund { htlc: 0, signer: 2 },
+        Action::Refund { htlc: 0, signer: 0 },
+        Action::AddLock {
+            htlc: 0,
+            signer: 0,
+            secret: 0,
+            timelock: 60,
+            keccak: false,
+        },
+    ]);
+}
+
+#[test]
+fn extend_timelock_only_later_and_by_the_sender() {
+    both(vec![
+        lock(1, 0, 0, 0),
+        Action::ExtendTimelock {
+            htlc: 0,
+            signer: 1,
+            timelock: 90,
+        },
+        Action::ExtendTimelock {
+            htlc: 0,
+            signer: 0,
+            timelock: 50,
+        },
+        Action::ExtendTimelock {
+            htlc: 0,
+            signer: 0,
+            timelock: 250,
+        },
+        Action::ExtendTimelock {
+            htlc: 0,
+            signer: 0,
+            timelock: 90,
+        },
+        Action::Warp { minutes: 60 },
+        Action::Refund { htlc: 0, signer: 2 },
+        Action::Warp { minutes: 20 },
+        Action::Refund { htlc: 0, signer: 2 },
+        Action::ExtendTimelock {
+            htlc: 0,
+            signer: 0,
+            timelock: 200,
+        },
+    ]);
+}
+
+#[test]
+fn keepers_refund_for_the_bounty_and_redeem_returns_it() {
+    let mut bounty_lock = lock(3, 0, 10, 30);
+    if let Action::Lock { keeper_bounty, .. } = &mut bounty_lock {
+        *keeper_bounty = 50;
+    }
+    both(vec![
+        Action::Commit {
+            sender: 0,
+            src_receiver: 1,
+            amount: 100,
+            timelock: 20,
+            keeper_bounty: 20,
+        },
+        bounty_lock.clone(),
+        Action::Refund { htlc: 0, signer: 2 },
+        Action::Redeem {
+            htlc: 1,
+            signer: 1,
+            secret: 0,
+        },
+        Action::Warp { minutes: 5 },
+        Action::Refund { htlc: 0, signer: 2 },
+        bounty_lock,
+        Action::Warp { minutes: 60 },
+        Action::Refund { htlc: 2, signer: 1 },
+    ]);
+}
+
+#[test]
+fn rewards_before_and_after_the_reward_timelock() {
+    both(vec![
+        lock(1, 0, 10, 30),
+        lock(2, 0, 10, 30),
+        lock(3, 0, 10, 30),
+        lock(4, 0, 10, 70),
+        lock(1, 0, 0, 0),
+        Action::Redeem {
+            htlc: 0,
+            signer: 2,
+            secret: 0,
+        },
+        Action::Warp { minutes: 20 },
+        Action::Redeem {
+            htlc: 1,
+            signer: 2,
+            secret: 0,
+        },
+        Action::Redeem {
+            htlc: 2,
+            signer: 1,
+            secret: 0,
+        },
+        lock(1, 0, 0, 0),
+        Action::Redeem {
+            htlc: 3,
+            signer: 0,
+            secret: 0,
+        },
+    ]);
+}
+
+#[test]
+fn seeded_random_scenarios() {
+    // A fixed xorshift stream, so failures reproduce.
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    for _ in 0..8 {
+        let bytes: Vec<u8> = (0..512)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let scenario = Scenario::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+        run_native_htlc(&scenario);
+        run_anchor_htlc(&scenario);
+    }
+}
@@ -0,0 +1,252 @@
+//! Shared harness for the solana-program-test suites in `tests/`.
+//!
+//! Both HTLC programs run natively through `processor!`, so the suites need
+//! neither an SBF toolchain nor a validator, and timelocks are crossed by moving
+//! the `Clock` sysvar instead of waiting for it.
+
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    clock::Clock,
+    ed25519_program,
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+    transaction::{Transaction, TransactionError},
+};
+use train_htlc_core::HtlcError;
+
+/// What a processed transaction left behind.
+#[derive(Debug)]
+pub struct Outcome {
+    pub result: Result<(), TransactionError>,
+    pub return_data: Vec<u8>,
+    pub logs: Vec<String>,
+}
+
+impl Outcome {
+    #[track_caller]
+    pub fn assert_ok(&self) {
+        assert_eq!(self.result, Ok(()), "{:#?}", self.logs);
+    }
+
+    /// The custom error code the transaction failed with, if any.
+    pub fn error_code(&self) -> Option<u32> {
+        match self.result {
+            Err(TransactionError::InstructionError(_, InstructionError::Custom(code))) => {
+                Some(code)
+            }
+            _ => None,
+        }
+    }
+
+    #[track_caller]
+    pub fn assert_htlc_error(&self, error: HtlcError) {
+        assert_eq!(
+            self.error_code(),
+            Some(error.code()),
+            "expected {}, got {:?}\n{:#?}",
+            error.name(),
+            self.result,
+            self.logs
+        );
+    }
+
+    #[track_caller]
+    pub fn assert_anchor_error(&self, error: anchor_lang::error::ErrorCode) {
+        assert_eq!(
+            self.error_code(),
+            Some(u32::from(error)),
+            "expected {}, got {:?}\n{:#?}",
+            error.name(),
+            self.result,
+            self.logs
+        );
+    }
+}
+
+/// Builds an Anchor instruction from its generated accounts and arguments structs.
+pub fn anchor_ix(
+    program_id: Pubkey,
+    accounts: impl ToAccountMetas,
+    args: impl InstructionData,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: accounts.to_account_metas(None),
+        data: args.data(),
+    }
+}
+
+/// Sends the instructions in one transaction paid by the context payer.
+pub async fn process(
+    ctx: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Outcome {
+    let blockhash = ctx.banks_client.get_latest_blockhash().await.unwrap();
+    // next_blockhash waits for a blockhash other than the last one used.
+    ctx.last_blockhash = blockhash;
+    let mut all_signers = vec![&ctx.payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&ctx.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    let processed = ctx
+        .banks_client
+        .process_transaction_with_metadata(transaction)
+        .await
+        .unwrap();
+    let (return_data, logs) = match processed.metadata {
+        Some(metadata) => (
+            metadata
+                .return_data
+                .map(|return_data| return_data.data)
+                .unwrap_or_default(),
+            metadata.log_messages,
+        ),
+        None => (Vec::new(), Vec::new()),
+    };
+    Outcome {
+        result: processed.result,
+        return_data,
+        logs,
+    }
+}
+
+/// Waits for a fresh blockhash, so a transaction identical to an earlier one
+/// is processed again instead of being rejected as a duplicate.
+pub async fn next_blockhash(ctx: &mut ProgramTestContext) {
+    ctx.get_new_latest_blockhash().await.unwrap();
+}
+
+pub async fn now(ctx: &mut ProgramTestContext) -> i64 {
+    ctx.banks_client
+        .get_sysvar::<Clock>()
+        .await
+        .unwrap()
+        .unix_timestamp
+}
+
+/// Moves the cluster clock to `unix_timestamp`.
+pub async fn warp_to(ctx: &mut ProgramTestContext, unix_timestamp: i64) {
+    let mut clock = ctx.banks_client.get_sysvar::<Clock>().await.unwrap();
+    clock.unix_timestamp = unix_timestamp;
+    ctx.set_sysvar(&clock);
+}
+
+/// A new keypair holding `lamports`, funded by the context payer.
+pub async fn funded_keypair(ctx: &mut ProgramTestContext, lamports: u64) -> Keypair {
+    let keypair = Keypair::new();
+    let payer = ctx.payer.pubkey();
+    process(
+        ctx,
+        &[system_instruction::transfer(
+            &payer,
+            &keypair.pubkey(),
+            lamports,
+        )],
+        &[],
+    )
+    .await
+    .assert_ok();
+    keypair
+}
+
+pub async fn balance(ctx: &mut ProgramTestContext, address: Pubkey) -> u64 {
+    ctx.banks_client.get_balance(address).await.unwrap()
+}
+
+pub async fn account_exists(ctx: &mut ProgramTestContext, address: Pubkey) -> bool {
+    ctx.banks_client
+        .get_account(address)
+        .await
+        .unwrap()
+        .is_some()
+}
+
+/// Reads and deserializes an Anchor account, discriminator included.
+pub async fn anchor_account<T: AccountDeserialize>(
+    ctx: &mut ProgramTestContext,
+    address: Pubkey,
+) -> Option<T> {
+    let account = ctx.banks_client.get_account(address).await.unwrap()?;
+    Some(T::try_deserialize(&mut &account.data[..]).unwrap())
+}
+
+/// Writes a rent-exempt account holding `data` plus `extra_lamports`, e.g. an
+/// HTLC in a layout no instruction creates anymore.
+pub async fn set_account_data(
+    ctx: &mut ProgramTestContext,
+    address: Pubkey,
+    owner: Pubkey,
+    data: Vec<u8>,
+    extra_lamports: u64,
+) {
+    let rent = ctx.banks_client.get_rent().await.unwrap();
+    let account = Account {
+        lamports: rent.minimum_balance(data.len()) + extra_lamports,
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    };
+    ctx.set_account(&address, &AccountSharedData::from(account));
+}
+
+/// Writes the ProgramData the upgradeable loader keeps for `program_id`, with
+/// `upgrade_authority` as its upgrade authority. Programs run through
+/// `processor!` are not deployed by that loader, so they have none otherwise.
+pub async fn set_program_data(
+    ctx: &mut ProgramTestContext,
+    program_id: &Pubkey,
+    upgrade_authority: Pubkey,
+) {
+    let (address, _) =
+        Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+    let state = UpgradeableLoaderState::ProgramData {
+        slot: 0,
+        upgrade_authority_address: Some(upgrade_authority),
+    };
+    let rent = ctx.banks_client.get_rent().await.unwrap();
+    let lamports = rent.minimum_balance(UpgradeableLoaderState::size_of_programdata_metadata());
+    let account = Account::new_data(lamports, &state, &bpf_loader_upgradeable::id()).unwrap();
+    ctx.set_account(&address, &AccountSharedData::from(account));
+}
+
+/// An Ed25519 program instruction verifying `signer`'s signature over `message`,
+/// with the public key, signature and message stored inside the instruction.
+pub fn ed25519_verify_ix(signer: &Keypair, message: &[u8]) -> Instruction {
+    const HEADER_LEN: u16 = 16;
+    let public_key_offset = HEADER_LEN;
+    let signature_offset = public_key_offset + 32;
+    let message_offset = signature_offset + 64;
+
+    let mut data = vec![1u8, 0];
+    for field in [
+        signature_offset,
+        u16::MAX,
+        public_key_offset,
+        u16::MAX,
+        message_offset,
+        message.len() as u16,
+        u16::MAX,
+    ] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(signer.pubkey().as_ref());
+    data.extend_from_slice(signer.sign_message(message).as_ref());
+    data.extend_from_slice(message);
+
+    Instruction {
+        program_id: ed25519_program::ID,
+        accounts: Vec::new(),
+        data,
+    }
+}
@@ -0,0 +1,2419 @@
+//! anchor_htlc (`native`) under solana-program-test, against the SPL Token,
+//! Token-2022 and Associated Token Account programs bundled with
+//! solana-program-test.
+//!
+//! As in native_htlc.rs, a second redeem or refund fails on the closed HTLC
+//! account and AlreadyRedeemed / AlreadyRefunded are reached through legacy
+//! accounts.
+#![allow(non_snake_case)]
+
+use anchor_lang::error::ErrorCode;
+use anchor_lang::prelude::AccountInfo;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::{
+    AnchorDeserialize, AnchorSerialize, Discriminator, InstructionData, ToAccountMetas,
+};
+use anchor_spl::associated_token::{
+    self, get_associated_token_address, get_associated_token_address_with_program_id,
+    spl_associated_token_account,
+};
+use anchor_spl::token::spl_token::{
+    self,
+    solana_program::{program_option::COption, program_pack::Pack},
+    state::{Account as TokenAccountState, AccountState, Mint as MintState},
+};
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{
+        transfer_fee::{instruction::initialize_transfer_fee_config, TransferFeeConfig},
+        BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+    },
+};
+use native::{
+    accounts, instruction, Config, ContractsIndex, ContractsPage, HTLCStatus, HashAlgorithm,
+    LegacyHTLC, RedeemBatchItem, SenderNonce, DEFAULT_MAX_TIMELOCK_WINDOW,
+    DEFAULT_MIN_TIMELOCK_WINDOW, HTLC, MAX_FEE_BPS,
+};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    bpf_loader_upgradeable,
+    instruction::{AccountMeta, Instruction},
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction, system_program, sysvar,
+};
+use train_htlc_core::{add_lock_sig_message, derive_commit_id, keccak256, sha256, HtlcError};
+use train_htlc_program_tests::*;
+
+const DECIMALS: u8 = 6;
+const SUPPLY: u64 = 1_000_000_000;
+const AMOUNT: u64 = 1_000_000;
+const REWARD: u64 = 100_000;
+const KEEPER_BOUNTY: u64 = LAMPORTS_PER_SOL / 1000;
+const SECRET: [u8; 32] = [42u8; 32];
+
+fn entry<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+    data: &[u8],
+) -> ProgramResult {
+    let accounts: &'b [AccountInfo<'b>] = Box::leak(Box::new(accounts.to_vec()));
+    native::entry(program_id, accounts, data)
+}
+
+fn hashlock() -> [u8; 32] {
+    sha256(&SECRET)
+}
+
+fn htlc_pda(sender: &Pubkey, Id: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"htlc", sender.as_ref(), Id], &native::ID)
+}
+
+fn htlc_token_account_pda(sender: &Pubkey, Id: &[u8; 32]) -> Pubkey {
+    Pubkey::find_program_address(&[b"htlc_token_account", sender.as_ref(), Id], &native::ID).0
+}
+
+fn legacy_htlc_pda(Id: &[u8; 32]) -> Pubkey {
+    Pubkey::find_program_address(&[Id], &native::ID).0
+}
+
+fn legacy_htlc_token_account_pda(Id: &[u8; 32]) -> Pubkey {
+    Pubkey::find_program_address(&[b"htlc_token_account", Id], &native::ID).0
+}
+
+fn contracts_index_pda(sender: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"contracts_index", sender.as_ref()], &native::ID).0
+}
+
+fn contracts_page_pda(sender: &Pubkey, page: u32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"contracts_page", sender.as_ref(), &page.to_le_bytes()],
+        &native::ID,
+    )
+    .0
+}
+
+fn nonce_pda(sender: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"nonce", sender.as_ref()], &native::ID).0
+}
+
+fn config_pda() -> Pubkey {
+    Pubkey::find_program_address(&[b"config"], &native::ID).0
+}
+
+fn program_data_pda() -> Pubkey {
+    Pubkey::find_program_address(&[native::ID.as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+fn fee_vault_pda(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"fee_vault", mint.as_ref()], &native::ID).0
+}
+
+fn allowed_mint_pda(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"allowed_mint", mint.as_ref()], &native::ID).0
+}
+
+/// A new SPL Token mint whose authority is the context payer.
+async fn create_mint(ctx: &mut ProgramTestContext) -> Pubkey {
+    let mint = Keypair::new();
+    let payer = ctx.payer.pubkey();
+    let rent = ctx.banks_client.get_rent().await.unwrap();
+    let ixs = [
+        system_instruction::create_account(
+            &payer,
+            &mint.pubkey(),
+            rent.minimum_balance(MintState::LEN),
+            MintState::LEN as u64,
+            &spl_token::ID,
+        ),
+        spl_token::instruction::initialize_mint2(
+            &spl_token::ID,
+            &mint.pubkey(),
+            &payer,
+            None,
+            DECIMALS,
+        )
+        .unwrap(),
+    ];
+    process(ctx, &ixs, &[&mint]).await.assert_ok();
+    mint.pubkey()
+}
+
+/// A new Token-2022 mint whose authority is the context payer, charging a
+/// transfer fee of `transfer_fee_bps` if any.
+async fn create_token_2022_mint(
+    ctx: &mut ProgramTestContext,
+    transfer_fee_bps: Option<u16>,
+) -> Pubkey {
+    let mint = Keypair::new();
+    let payer = ctx.payer.pubkey();
+    let rent = ctx.banks_client.get_rent().await.unwrap();
+    let extensions = match transfer_fee_bps {
+        Some(_) => vec![ExtensionType::TransferFeeConfig],
+        None => Vec::new(),
+    };
+    let space =
+        ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&extensions)
+            .unwrap();
+    let mut ixs = vec![system_instruction::create_account(
+        &payer,
+        &mint.pubkey(),
+        rent.minimum_balance(space),
+        space as u64,
+        &spl_token_2022::ID,
+    )];
+    if let Some(transfer_fee_bps) = transfer_fee_bps {
+        ixs.push(
+            initialize_transfer_fee_config(
+                &spl_token_2022::ID,
+                &mint.pubkey(),
+                Some(&payer),
+                Some(&payer),
+                transfer_fee_bps,
+                u64::MAX,
+            )
+            .unwrap(),
+        );
+    }
+    ixs.push(
+        spl_token_2022::instruction::initialize_mint2(
+            &spl_token_2022::ID,
+            &mint.pubkey(),
+            &payer,
+            None,
+            DECIMALS,
+        )
+        .unwrap(),
+    );
+    process(ctx, &ixs, &[&mint]).await.assert_ok();
+    mint.pubkey()
+}
+
+/// Creates the associated token account of `owner` for `mint`, minting `amount`
+/// into it, under the token program owning `mint`.
+async fn create_token_account(
+    ctx: &mut ProgramTestContext,
+    mint: Pubkey,
+    owner: Pubkey,
+    amount: u64,
+) -> Pubkey {
+    let payer = ctx.payer.pubkey();
+    let token_program = ctx
+        .banks_client
+        .get_account(mint)
+        .await
+        .unwrap()
+        .unwrap()
+        .owner;
+    let address = get_associated_token_address_with_program_id(&owner, &mint, &token_program);
+    let mut ixs = vec![
+        spl_associated_token_account::instruction::create_associated_token_account(
+            &payer,
+            &owner,
+            &mint,
+            &token_program,
+        ),
+    ];
+    if amount != 0 {
+        ixs.push(
+            spl_token_2022::instruction::mint_to(
+                &token_program,
+                &mint,
+                &address,
+                &payer,
+                &[],
+                amount,
+            )
+            .unwrap(),
+        );
+    }
+    process(ctx, &ixs, &[]).await.assert_ok();
+    address
+}
+
+struct Env {
+    ctx: ProgramTestContext,
+    admin: Keypair,
+    sender: Keypair,
+    src_receiver: Keypair,
+    mint: Pubkey,
+    /// The token program of `mint`.
+    token_program: Pubkey,
+}
+
+/// An Env whose Config is not created yet, with `admin` as the program's
+/// upgrade authority.
+async fn setup_without_config() -> Env {
+    let program_test = ProgramTest::new("native", native::ID, processor!(entry));
+    let mut ctx = program_test.start_with_context().await;
+    let sender = funded_keypair(&mut ctx, 10 * LAMPORTS_PER_SOL).await;
+    let src_receiver = funded_keypair(&mut ctx, LAMPORTS_PER_SOL).await;
+    let admin = funded_keypair(&mut ctx, LAMPORTS_PER_SOL).await;
+    set_program_data(&mut ctx, &native::ID, admin.pubkey()).await;
+    let mint = create_mint(&mut ctx).await;
+    create_token_account(&mut ctx, mint, sender.pubkey(), SUPPLY).await;
+    create_token_account(&mut ctx, mint, src_receiver.pubkey(), 0).await;
+    Env {
+        ctx,
+        admin,
+        sender,
+        src_receiver,
+        mint,
+        token_program: spl_token::ID,
+    }
+}
+
+async fn setup() -> Env {
+    let mut env = setup_without_config().await;
+    let admin = env.admin.insecure_clone();
+    let mint = env.mint;
+    env.initialize_config(&admin).await.assert_ok();
+    env.allow_mint(&admin, mint, "USDC").await.assert_ok();
+    env
+}
+
+impl Env {
+    async fn initialize_config(&mut self, admin: &Keypair) -> Outcome {
+        let ix = anchor_ix(
+            native::ID,
+            accounts::InitializeConfig {
+                admin: admin.pubkey(),
+                program_data: program_data_pda(),
+                config: config_pda(),
+                system_program: system_program::ID,
+            },
+            instruction::InitializeConfig {},
+        );
+        process(&mut self.ctx, &[ix], &[admin]).await
+    }
+
+    async fn transfer_admin(&mut self, admin: &Keypair, new_admin: Pubkey) -> Outcome {
+        let ix = anchor_ix(
+            native::ID,
+            accounts::ConfigAdmin {
+                admin: admin.pubkey(),
+                config: config_pda(),
+            },
+            instruction::TransferAdmin { new_admin },
+        );
+        process(&mut self.ctx, &[ix], &[admin]).await
+    }
+
+    async fn accept_admin(&mut self, pending_admin: &Keypair) -> Outcome {
+        let ix = anchor_ix(
+            native::ID,
+            accounts::AcceptAdmin {
+                pending_admin: pending_admin.pubkey(),
+                config: config_pda(),
+            },
+            instruction::AcceptAdmin {},
+        );
+        process(&mut self.ctx, &[ix], &[pending_admin]).await
+    }
+
+    async fn set_paused(&mut self, admin: &Keypair, paused: bool) -> Outcome {
+        let ix = anchor_ix(
+            native::ID,
+            accounts::ConfigAdmin {
+                admin: admin.pubkey(),
+                config: config_pda(),
+            },
+            instruction::SetPaused { paused },
+        );
+        process(&mut self.ctx, &[ix], &[admin]).await
+    }
+
+    async fn set_fee(&mut self, admin: &Keypair, fee_bps: u16) -> Outcome {
+        let ix = anchor_ix(
+            native::ID,
+            accounts::ConfigAdmin {
+                admin: admin.pubkey(),
+                config: config_pda(),
+            },
+            instruction::SetFee { fee_bps },
+        );
+        process(&mut self.ctx, &[ix], &[admin]).await
+    }
+
+    async fn withdraw_fees(
+        &mut self,
+        admin: &Keypair,
+        recipient_token_account: Pubkey,
+        mint: Pubkey,
+    ) -> Outcome {
+        let ix = anchor_ix(
+            native::ID,
+            accounts::WithdrawFees {
+                admin: admin.pubkey(),
+                config: config_pda(),
+                fee_vault: fee_vault_pda(&mint),
+                recipient_token_account,
+                token_contract: mint,
+                token_program: self.token_program,
+            },
+            instruction::WithdrawFees {},
+        );
+        process(&mut self.ctx, &[ix], &[admin]).await
+    }
+
+    async fn allow_mint(&mut self, admin: &Keypair, mint: Pubkey, src_asset: &str) -> Outcome {
+        let ix = anchor_ix(
+            native::ID,
+            accounts::AllowMint {
+                admin: admin.pubkey(),
+                config: config_pda(),
+                allowed_mint: allowed_mint_pda(&mint),
+                token_contract: mint,
+                system_program: system_program::ID,
+            },
+            instruction::AllowMint {
+                src_asset: src_asset.to_string(),
+            },
+        );
+        process(&mut self.ctx, &[ix], &[admin]).await
+    }
+
+    async fn remove_mint(&mut self, admin: &Keypair, mint: Pubkey) -> Outcome {
+        let ix = anchor_ix(
+            native::ID,
+            accounts::RemoveMint {
+                admin: admin.pubkey(),
+                config: config_pda(),
+                allowed_mint: allowed_mint_pda(&mint),
+                token_contract: mint,
+            },
+            instruction::RemoveMint {},
+        );
+        process(&mut self.ctx, &[ix], &[admin]).await
+    }
+
+    async fn set_timelock_window(
+        &mut self,
+        admin: &Keypair,
+        min_window: u64,
+        max_window: u64,
+    ) -> Outcome {
+        let ix = anchor_ix(
+            native::ID,
+            accounts::ConfigAdmin {
+                admin: admin.pubkey(),
+                config: config_pda(),
+            },
+            instruction::SetTimelockWindow {
+                min_window,
+                max_window,
+            },
+        );
+        process(&mut self.ctx, &[ix], &[admin]).await
+    }
+
+    async fn config(&mut self) -> Config {
+        anchor_account(&mut self.ctx, config_pda()).await.unwrap()
+    }
+
+    async fn token_balance(&mut self, address: Pubkey) -> u64 {
+        let account = self
+            .ctx
+            .banks_client
+            .get_account(address)
+            .await
+            .unwrap()
+            .unwrap();
+        // A Token-2022 account keeps its extensions after the SPL Token layout.
+        TokenAccountState::unpack_from_slice(&account.data[..TokenAccountState::LEN])
+            .unwrap()
+            .amount
+    }
+
+    fn token_account(&self, owner: &Pubkey) -> Pubkey {
+        get_associated_token_address_with_program_id(owner, &self.mint, &self.token_program)
+    }
+
+    fn sender_token_account(&self) -> Pubkey {
+        self.token_account(&self.sender.pubkey())
+    }
+
+    fn src_receiver_token_account(&self) -> Pubkey {
+        self.token_account(&self.src_receiver.pubkey())
+    }
+
+    /// Switches to a new allowed Token-2022 mint charging a transfer fee of
+    /// `transfer_fee_bps` if any, with the sender holding SUPPLY of it.
+    async fn use_token_2022_mint(&mut self, transfer_fee_bps: Option<u16>) {
+        let mint = create_token_2022_mint(&mut self.ctx, transfer_fee_bps).await;
+        create_token_account(&mut self.ctx, mint, self.sender.pubkey(), SUPPLY).await;
+        create_token_account(&mut self.ctx, mint, self.src_receiver.pubkey(), 0).await;
+        let admin = self.admin.insecure_clone();
+        self.allow_mint(&admin, mint, "USDC").await.assert_ok();
+        self.mint = mint;
+        self.token_program = spl_token_2022::ID;
+    }
+
+    /// The meta of the mint, writable for a Token-2022 mint, which redeem and
+    /// refund harvest the transfer fees withheld in the HTLC token account to.
+    fn mint_meta(&self) -> AccountMeta {
+        if self.token_program == spl_token_2022::ID {
+            AccountMeta::new(self.mint, false)
+        } else {
+            AccountMeta::new_readonly(self.mint, false)
+        }
+    }
+
+    /// anchor_ix with the mint passed as mint_meta has it.
+    fn token_ix(&self, accounts: impl ToAccountMetas, args: impl InstructionData) -> Instruction {
+        let mut ix = anchor_ix(native::ID, accounts, args);
+        for meta in ix
+            .accounts
+            .iter_mut()
+            .filter(|meta| meta.pubkey == self.mint)
+        {
+            *meta = self.mint_meta();
+        }
+        ix
+    }
+
+    async fn in_seconds(&mut self, seconds: i64) -> u64 {
+        (now(&mut self.ctx).await + seconds) as u64
+    }
+
+    async fn next_commit_id(&mut self, timelock: u64, amount: u64) -> [u8; 32] {
+        let nonce = anchor_account::<SenderNonce>(&mut self.ctx, nonce_pda(&self.sender.pubkey()))
+            .await
+            .map_or(0, |sender_nonce| sender_nonce.nonce);
+        derive_commit_id(
+            &native::ID.to_bytes(),
+            &self.sender.pubkey().to_bytes(),
+            &self.src_receiver.pubkey().to_bytes(),
+            amount,
+            timelock,
+            nonce,
+        )
+    }
+
+    async fn current_page(&mut self) -> u32 {
+        anchor_account::<ContractsIndex>(&mut self.ctx, contracts_index_pda(&self.sender.pubkey()))
+            .await
+            .map_or(0, |index| index.current_page)
+    }
+
+    async fn commit_args(&mut self, timelock: u64, amount: u64) -> instruction::Commit {
+        let Id = self.next_commit_id(timelock, amount).await;
+        instruction::Commit {
+            Id,
+            hopChains: vec!["ETHEREUM".to_string()],
+            hopAssets: vec!["USDC".to_string()],
+            hopAddress: vec!["0x1234".to_string()],
+            dst_chain: "ETHEREUM".to_string(),
+            dst_asset: "USDC".to_string(),
+            dst_address: "0x1234".to_string(),
+            src_asset: "USDC".to_string(),
+            src_receiver: self.src_receiver.pubkey(),
+            timelock,
+            amount,
+            hash_algorithm: HashAlgorithm::Sha256,
+            keeper_bounty: 0,
+            commit_bump: htlc_pda(&self.sender.pubkey(), &Id).1,
+        }
+    }
+
+    async fn commit_accounts(&mut self, Id: [u8; 32]) -> accounts::Commit {
+        let sender = self.sender.pubkey();
+        let page = self.current_page().await;
+        accounts::Commit {
+            sender,
+            config: config_pda(),
+            htlc: htlc_pda(&sender, &Id).0,
+            contracts_index: contracts_index_pda(&sender),
+            contracts_page: contracts_page_pda(&sender, page),
+            sender_nonce: nonce_pda(&sender),
+            htlc_token_account: htlc_token_account_pda(&sender, &Id),
+            fee_vault: fee_vault_pda(&self.mint),
+            token_contract: self.mint,
+            allowed_mint: allowed_mint_pda(&self.mint),
+            sender_token_account: self.sender_token_account(),
+            token_program: self.token_program,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        }
+    }
+
+    async fn commit_with(&mut self, args: instruction::Commit) -> Outcome {
+        let accounts = self.commit_accounts(args.Id).await;
+        let ix = anchor_ix(native::ID, accounts, args);
+        process(&mut self.ctx, &[ix], &[&self.sender]).await
+    }
+
+    async fn commit(&mut self, timelock: u64) -> [u8; 32] {
+        let args = self.commit_args(timelock, AMOUNT).await;
+        let Id = args.Id;
+        self.commit_with(args).await.assert_ok();
+        Id
+    }
+
+    fn lock_args(&self, Id: [u8; 32], timelock: u64) -> instruction::Lock {
+        instruction::Lock {
+            Id,
+            hashlock: hashlock(),
+            timelock,
+            dst_chain: "ETHEREUM".to_string(),
+            dst_address: "0x1234".to_string(),
+            dst_asset: "USDC".to_string(),
+            src_asset: "USDC".to_string(),
+            src_receiver: self.src_receiver.pubkey(),
+            amount: AMOUNT,
+            reward: 0,
+            reward_timelock: 0,
+            hash_algorithm: HashAlgorithm::Sha256,
+            keeper_bounty: 0,
+            lock_bump: htlc_pda(&self.sender.pubkey(), &Id).1,
+        }
+    }
+
+    async fn lock_accounts(&mut self, Id: [u8; 32]) -> accounts::Lock {
+        let sender = self.sender.pubkey();
+        let page = self.current_page().await;
+        accounts::Lock {
+            sender,
+            config: config_pda(),
+            htlc: htlc_pda(&sender, &Id).0,
+            contracts_index: contracts_index_pda(&sender),
+            contracts_page: contracts_page_pda(&sender, page),
+            htlc_token_account: htlc_token_account_pda(&sender, &Id),
+            fee_vault: fee_vault_pda(&self.mint),
+            token_contract: self.mint,
+            allowed_mint: allowed_mint_pda(&self.mint),
+            sender_token_account: self.sender_token_account(),
+            token_program: self.token_program,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        }
+    }
+
+    async fn lock_with(&mut self, args: instruction::Lock) -> Outcome {
+        let accounts = self.lock_accounts(args.Id).await;
+        let ix = anchor_ix(native::ID, accounts, args);
+        process(&mut self.ctx, &[ix], &[&self.sender]).await
+    }
+
+    async fn add_lock(&mut self, Id: [u8; 32], hashlock: [u8; 32], timelock: u64) -> Outcome {
+        self.add_lock_with(Id, hashlock, timelock, HashAlgorithm::Sha256)
+            .await
+    }
+
+    async fn add_lock_with(
+        &mut self,
+        Id: [u8; 32],
+        hashlock: [u8; 32],
+        timelock: u64,
+        hash_algorithm: HashAlgorithm,
+    ) -> Outcome {
+        let sender = self.sender.pubkey();
+        let ix = anchor_ix(
+            native::ID,
+            accounts::AddLock {
+                sender,
+                htlc: htlc_pda(&sender, &Id).0,
+                config: config_pda(),
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::AddLock {
+                Id,
+                hashlock,
+                timelock,
+                hash_algorithm,
+            },
+        );
+        process(&mut self.ctx, &[ix], &[&self.sender]).await
+    }
+
+    async fn extend_timelock(&mut self, Id: [u8; 32], timelock: u64) -> Outcome {
+        let sender = self.sender.insecure_clone();
+        self.extend_timelock_by(Id, timelock, &sender).await
+    }
+
+    async fn extend_timelock_by(
+        &mut self,
+        Id: [u8; 32],
+        timelock: u64,
+        sender: &Keypair,
+    ) -> Outcome {
+        let ix = anchor_ix(
+            native::ID,
+            accounts::ExtendTimelock {
+                sender: sender.pubkey(),
+                htlc: htlc_pda(&self.sender.pubkey(), &Id).0,
+                config: config_pda(),
+            },
+            instruction::ExtendTimelock { Id, timelock },
+        );
+        process(&mut self.ctx, &[ix], &[sender]).await
+    }
+
+    fn add_lock_sig_ixs(
+        &self,
+        signer: &Keypair,
+        Id: [u8; 32],
+        timelock: u64,
+        hash_algorithm: HashAlgorithm,
+    ) -> Vec<Instruction> {
+        let message = add_lock_sig_message(
+            &native::ID.to_bytes(),
+            &Id,
+            &hashlock(),
+            timelock,
+            hash_algorithm.into(),
+        );
+        let signature = signer.sign_message(&message);
+        vec![
+            ed25519_verify_ix(signer, &message),
+            anchor_ix(
+                native::ID,
+                accounts::AddLockSig {
+                    payer: self.ctx.payer.pubkey(),
+                    htlc: htlc_pda(&self.sender.pubkey(), &Id).0,
+                    config: config_pda(),
+                    ix_sysvar: sysvar::instructions::ID,
+                },
+                instruction::AddLockSig {
+                    Id,
+                    hashlock: hashlock(),
+                    timelock,
+                    hash_algorithm,
+                    signature: signature.as_ref().try_into().unwrap(),
+                },
+            ),
+        ]
+    }
+
+    /// Redeem accounts with the reward, if any, paid into `user_signing`'s token account.
+    async fn redeem_accounts(&mut self, Id: [u8; 32], user_signing: Pubkey) -> accounts::Redeem {
+        let sender = self.sender.pubkey();
+        let htlc = htlc_pda(&sender, &Id).0;
+        let page = anchor_account::<HTLC>(&mut self.ctx, htlc)
+            .await
+            .map_or(0, |htlc| htlc.index_page);
+        accounts::Redeem {
+            user_signing,
+            htlc,
+            contracts_page: contracts_page_pda(&sender, page),
+            htlc_token_account: htlc_token_account_pda(&sender, &Id),
+            fee_vault: Some(fee_vault_pda(&self.mint)),
+            src_receiver_token_account: self.src_receiver_token_account(),
+            sender_token_account: self.sender_token_account(),
+            reward_token_account: Some(self.token_account(&user_signing)),
+            sender,
+            src_receiver: self.src_receiver.pubkey(),
+            payer: sender,
+            token_contract: self.mint,
+            system_program: system_program::ID,
+            token_program: self.token_program,
+            associated_token_program: associated_token::ID,
+            rent: sysvar::rent::ID,
+        }
+    }
+
+    async fn redeem_by(
+        &mut self,
+        Id: [u8; 32],
+        secret: [u8; 32],
+        user_signing: &Keypair,
+    ) -> Outcome {
+        let accounts = self.redeem_accounts(Id, user_signing.pubkey()).await;
+        let ix = self.token_ix(
+            accounts,
+            instruction::Redeem {
+                Id,
+                secret,
+                htlc_bump: htlc_pda(&self.sender.pubkey(), &Id).1,
+            },
+        );
+        process(&mut self.ctx, &[ix], &[user_signing]).await
+    }
+
+    async fn redeem(&mut self, Id: [u8; 32], secret: [u8; 32]) -> Outcome {
+        let user_signing = self.src_receiver.insecure_clone();
+        self.redeem_by(Id, secret, &user_signing).await
+    }
+
+    async fn redeem_batch_ix(
+        &mut self,
+        items: &[([u8; 32], [u8; 32])],
+        user_signing: Pubkey,
+    ) -> Instruction {
+        let mut ix = anchor_ix(
+            native::ID,
+            accounts::RedeemBatch {
+                user_signing,
+                token_program: self.token_program,
+            },
+            instruction::RedeemBatch {
+                items: items
+                    .iter()
+                    .map(|&(Id, secret)| RedeemBatchItem { Id, secret })
+                    .collect(),
+            },
+        );
+        for &(Id, _) in items {
+            let accounts = self.redeem_accounts(Id, user_signing).await;
+            ix.accounts.extend([
+                AccountMeta::new(accounts.htlc, false),
+                AccountMeta::new(accounts.contracts_page, false),
+                AccountMeta::new(accounts.htlc_token_account, false),
+                self.mint_meta(),
+                AccountMeta::new(accounts.fee_vault.unwrap_or(native::ID), false),
+                AccountMeta::new(accounts.src_receiver_token_account, false),
+                AccountMeta::new(accounts.sender_token_account, false),
+                AccountMeta::new(accounts.reward_token_account.unwrap_or(native::ID), false),
+                AccountMeta::new(accounts.sender, false),
+                AccountMeta::new(accounts.payer, false),
+            ]);
+        }
+        ix
+    }
+
+    async fn refund_accounts(&mut self, Id: [u8; 32]) -> accounts::Refund {
+        let sender = self.sender.pubkey();
+        let htlc = htlc_pda(&sender, &Id).0;
+        let page = anchor_account::<HTLC>(&mut self.ctx, htlc)
+            .await
+            .map_or(0, |htlc| htlc.index_page);
+        accounts::Refund {
+            user_signing: sender,
+            htlc,
+            contracts_page: contracts_page_pda(&sender, page),
+            htlc_token_account: htlc_token_account_pda(&sender, &Id),
+            sender,
+            payer: sender,
+            token_contract: self.mint,
+            sender_token_account: self.sender_token_account(),
+            system_program: system_program::ID,
+            token_program: self.token_program,
+            rent: sysvar::rent::ID,
+        }
+    }
+
+    async fn refund_with(&mut self, Id: [u8; 32], accounts: accounts::Refund) -> Outcome {
+        let ix = self.token_ix(
+            accounts,
+            instruction::Refund {
+                Id,
+                htlc_bump: htlc_pda(&self.sender.pubkey(), &Id).1,
+            },
+        );
+        let sender = self.sender.insecure_clone();
+        process(&mut self.ctx, &[ix], &[&sender]).await
+    }
+
+    async fn refund(&mut self, Id: [u8; 32]) -> Outcome {
+        let accounts = self.refund_accounts(Id).await;
+        self.refund_with(Id, accounts).await
+    }
+
+    async fn refund_by(&mut self, Id: [u8; 32], user_signing: &Keypair) -> Outcome {
+        let mut accounts = self.refund_accounts(Id).await;
+        accounts.user_signing = user_signing.pubkey();
+        let ix = self.token_ix(
+            accounts,
+            instruction::Refund {
+                Id,
+                htlc_bump: htlc_pda(&self.sender.pubkey(), &Id).1,
+            },
+        );
+        process(&mut self.ctx, &[ix], &[user_signing]).await
+    }
+
+    async fn refund_batch_ix(&mut self, Ids: &[[u8; 32]], user_signing: Pubkey) -> Instruction {
+        let sender = self.sender.pubkey();
+        let mut ix = anchor_ix(
+            native::ID,
+            accounts::RefundBatch {
+                user_signing,
+                sender,
+                token_program: self.token_program,
+            },
+            instruction::RefundBatch { Ids: Ids.to_vec() },
+        );
+        for &Id in Ids {
+            let accounts = self.refund_accounts(Id).await;
+            ix.accounts.extend([
+                AccountMeta::new(accounts.htlc, false),
+                AccountMeta::new(accounts.contracts_page, false),
+                AccountMeta::new(accounts.htlc_token_account, false),
+                self.mint_meta(),
+                AccountMeta::new(accounts.sender_token_account, false),
+                AccountMeta::new(accounts.payer, false),
+            ]);
+        }
+        ix
+    }
+
+    async fn contracts(&mut self, page: u32) -> Vec<[u8; 32]> {
+        anchor_account::<ContractsPage>(
+            &mut self.ctx,
+            contracts_page_pda(&self.sender.pubkey(), page),
+        )
+        .await
+        .map_or_else(Vec::new, |page| page.ids)
+    }
+
+    /// Writes an HTLC and its token account at the pre-namespacing [Id] and
+    /// ["htlc_token_account", Id] addresses, as the old program left them.
+    async fn legacy_htlc(&mut self, Id: [u8; 32], timelock: u64, redeemed: bool, refunded: bool) {
+        let htlc = legacy_htlc_pda(&Id);
+        let token_wallet = legacy_htlc_token_account_pda(&Id);
+        let legacy = LegacyHTLC {
+            dst_address: "0x1234".to_string(),
+            dst_chain: "ETHEREUM".to_string(),
+            dst_asset: "USDC".to_string(),
+            src_asset: "USDC".to_string(),
+            sender: self.sender.pubkey(),
+            src_receiver: self.src_receiver.pubkey(),
+            hashlock: hashlock(),
+            secret: [0u8; 32],
+            amount: AMOUNT,
+            timelock,
+            token_contract: self.mint,
+            token_wallet,
+            redeemed,
+            refunded,
+        };
+        let mut data = HTLC::DISCRIMINATOR.to_vec();
+        data.extend(legacy.try_to_vec().unwrap());
+        set_account_data(&mut self.ctx, htlc, native::ID, data, 0).await;
+        self.legacy_token_account(Id).await;
+    }
+
+    async fn legacy_token_account(&mut self, Id: [u8; 32]) {
+        let token_account = TokenAccountState {
+            mint: self.mint,
+            owner: legacy_htlc_pda(&Id),
+            amount: AMOUNT,
+            delegate: COption::None,
+            state: AccountState::Initialized,
+            is_native: COption::None,
+            delegated_amount: 0,
+            close_authority: COption::None,
+        };
+        let mut data = vec![0u8; TokenAccountState::LEN];
+        TokenAccountState::pack(token_account, &mut data).unwrap();
+        set_account_data(
+            &mut self.ctx,
+            legacy_htlc_token_account_pda(&Id),
+            spl_token::ID,
+            data,
+            0,
+        )
+        .await;
+    }
+
+    fn redeem_legacy_accounts(&self, Id: [u8; 32]) -> accounts::RedeemLegacy {
+        accounts::RedeemLegacy {
+            user_signing: self.src_receiver.pubkey(),
+            htlc: legacy_htlc_pda(&Id),
+            htlc_token_account: legacy_htlc_token_account_pda(&Id),
+            src_receiver_token_account: self.src_receiver_token_account(),
+            sender: self.sender.pubkey(),
+            src_receiver: self.src_receiver.pubkey(),
+            token_contract: self.mint,
+            system_program: system_program::ID,
+            token_program: self.token_program,
+            associated_token_program: associated_token::ID,
+        }
+    }
+
+    async fn redeem_legacy_with(
+        &mut self,
+        Id: [u8; 32],
+        secret: [u8; 32],
+        accounts: accounts::RedeemLegacy,
+    ) -> Outcome {
+        let ix = self.token_ix(accounts, instruction::RedeemLegacy { Id, secret });
+        let src_receiver = self.src_receiver.insecure_clone();
+        process(&mut self.ctx, &[ix], &[&src_receiver]).await
+    }
+
+    async fn redeem_legacy(&mut self, Id: [u8; 32], secret: [u8; 32]) -> Outcome {
+        let accounts = self.redeem_legacy_accounts(Id);
+        self.redeem_legacy_with(Id, secret, accounts).await
+    }
+
+    fn refund_legacy_accounts(&self, Id: [u8; 32]) -> accounts::RefundLegacy {
+        accounts::RefundLegacy {
+            user_signing: self.src_receiver.pubkey(),
+            htlc: legacy_htlc_pda(&Id),
+            htlc_token_account: legacy_htlc_token_account_pda(&Id),
+            sender: self.sender.pubkey(),
+            token_contract: self.mint,
+            sender_token_account: self.sender_token_account(),
+            system_program: system_program::ID,
+            token_program: self.token_program,
+        }
+    }
+
+    async fn refund_legacy_with(
+        &mut self,
+        Id: [u8; 32],
+        accounts: accounts::RefundLegacy,
+    ) -> Outcome {
+        let ix = self.token_ix(accounts, instruction::RefundLegacy { Id });
+        let src_receiver = self.src_receiver.insecure_clone();
+        process(&mut self.ctx, &[ix], &[&src_receiver]).await
+    }
+}
+
+#[tokio::test]
+async fn get_commit_id_follows_the_sender_nonce() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let get_commit_id = |env: &Env| {
+        anchor_ix(
+            native::ID,
+            accounts::GetCommitId {
+                sender: env.sender.pubkey(),
+                receiver: env.src_receiver.pubkey(),
+                sender_nonce: nonce_pda(&env.sender.pubkey()),
+            },
+            instruction::GetCommitId {
+                amount: AMOUNT,
+                timelock,
+            },
+        )
+    };
+
+    let ix = get_commit_id(&env);
+    let outcome = process(&mut env.ctx, &[ix], &[]).await;
+    outcome.assert_ok();
+    let first = env.next_commit_id(timelock, AMOUNT).await;
+    assert_eq!(outcome.return_data, first);
+
+    env.commit(timelock).await;
+    next_blockhash(&mut env.ctx).await;
+    let ix = get_commit_id(&env);
+    let outcome = process(&mut env.ctx, &[ix], &[]).await;
+    outcome.assert_ok();
+    assert_ne!(outcome.return_data, first);
+    assert_eq!(
+        outcome.return_data,
+        env.next_commit_id(timelock, AMOUNT).await
+    );
+}
+
+#[tokio::test]
+async fn commit_escrows_the_tokens_and_indexes_the_htlc() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+
+    let Id = env.commit(timelock).await;
+
+    let htlc_address = htlc_pda(&env.sender.pubkey(), &Id).0;
+    let htlc: HTLC = anchor_account(&mut env.ctx, htlc_address).await.unwrap();
+    let htlc_token_account = htlc_token_account_pda(&env.sender.pubkey(), &Id);
+    assert_eq!(htlc.sender, env.sender.pubkey());
+    assert_eq!(htlc.src_receiver, env.src_receiver.pubkey());
+    assert_eq!(htlc.token_contract, env.mint);
+    assert_eq!(htlc.token_wallet, htlc_token_account);
+    assert_eq!(htlc.hashlock, [0u8; 32]);
+    assert_eq!(htlc.status, HTLCStatus::Committed);
+    assert_eq!(htlc.amount, AMOUNT);
+    assert_eq!(htlc.timelock, timelock);
+    assert_eq!(htlc.hop_assets, vec!["USDC".to_string()]);
+    assert_eq!(env.token_balance(htlc_token_account).await, AMOUNT);
+    assert_eq!(
+        env.token_balance(env.sender_token_account()).await,
+        SUPPLY - AMOUNT
+    );
+    assert_eq!(env.contracts(0).await, vec![Id]);
+
+    let ix = anchor_ix(
+        native::ID,
+        accounts::GetDetails { htlc: htlc_address },
+        instruction::GetDetails { Id },
+    );
+    let outcome = process(&mut env.ctx, &[ix], &[]).await;
+    outcome.assert_ok();
+    let details = HTLC::deserialize(&mut &outcome.return_data[..]).unwrap();
+    assert_eq!(details.amount, AMOUNT);
+    assert_eq!(details.token_contract, env.mint);
+
+    let ix = anchor_ix(
+        native::ID,
+        accounts::GetContracts {
+            contracts_page: contracts_page_pda(&env.sender.pubkey(), 0),
+        },
+        instruction::GetContracts {
+            _sender: env.sender.pubkey(),
+            _page: 0,
+        },
+    );
+    let outcome = process(&mut env.ctx, &[ix], &[]).await;
+    outcome.assert_ok();
+    assert_eq!(
+        Vec::<[u8; 32]>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        vec![Id]
+    );
+}
+
+#[tokio::test]
+async fn commit_rejects_bad_terms() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+
+    let past = env.in_seconds(0).await;
+    let args = env.commit_args(past, AMOUNT).await;
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::NotFutureTimeLock);
+
+    let args = env.commit_args(timelock, 0).await;
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::FundsNotSent);
+
+    let mut args = env.commit_args(timelock, AMOUNT).await;
+    args.amount += 1;
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::InvalidCommitId);
+
+    let mut args = env.commit_args(timelock, AMOUNT).await;
+    args.hopAddress.push("0x5678".to_string());
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::HopRouteMismatch);
+
+    let mut args = env.commit_args(timelock, AMOUNT).await;
+    args.dst_address = "a".repeat(native::MAX_ADDRESS_LEN + 1);
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::DstAddressTooLong);
+}
+
+#[tokio::test]
+async fn commit_rejects_a_token_account_of_another_mint_or_owner() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let other_mint = create_mint(&mut env.ctx).await;
+
+    let args = env.commit_args(timelock, AMOUNT).await;
+    let mut accounts = env.commit_accounts(args.Id).await;
+    accounts.token_contract = other_mint;
+    accounts.fee_vault = fee_vault_pda(&other_mint);
+    accounts.allowed_mint = allowed_mint_pda(&other_mint);
+    let ix = anchor_ix(native::ID, accounts, args);
+    let sender = env.sender.insecure_clone();
+    process(&mut env.ctx, &[ix], &[&sender])
+        .await
+        .assert_htlc_error(HtlcError::NoToken);
+
+    let args = env.commit_args(timelock, AMOUNT).await;
+    let mut accounts = env.commit_accounts(args.Id).await;
+    accounts.sender_token_account = env.src_receiver_token_account();
+    let ix = anchor_ix(native::ID, accounts, args);
+    process(&mut env.ctx, &[ix], &[&sender])
+        .await
+        .assert_htlc_error(HtlcError::NotSender);
+}
+
+#[tokio::test]
+async fn add_lock_then_redeem_pays_the_src_receiver_and_closes_the_htlc() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+    let htlc_address = htlc_pda(&env.sender.pubkey(), &Id).0;
+    let htlc_token_account = htlc_token_account_pda(&env.sender.pubkey(), &Id);
+
+    env.add_lock(Id, hashlock(), timelock + 60)
+        .await
+        .assert_ok();
+    let htlc: HTLC = anchor_account(&mut env.ctx, htlc_address).await.unwrap();
+    assert_eq!(htlc.hashlock, hashlock());
+    assert_eq!(htlc.timelock, timelock + 60);
+    assert_eq!(htlc.status, HTLCStatus::Locked);
+
+    let rent_lamports =
+        balance(&mut env.ctx, htlc_address).await + balance(&mut env.ctx, htlc_token_account).await;
+    let sender_before = balance(&mut env.ctx, env.sender.pubkey()).await;
+    env.redeem(Id, SECRET).await.assert_ok();
+
+    assert!(!account_exists(&mut env.ctx, htlc_address).await);
+    assert!(!account_exists(&mut env.ctx, htlc_token_account).await);
+    assert_eq!(
+        env.token_balance(env.src_receiver_token_account()).await,
+        AMOUNT
+    );
+    assert_eq!(
+        balance(&mut env.ctx, env.sender.pubkey()).await,
+        sender_before + rent_lamports
+    );
+    assert!(env.contracts(0).await.is_empty());
+}
+
+#[tokio::test]
+async fn redeem_creates_the_src_receiver_token_account() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let fresh_receiver = Keypair::new().pubkey();
+    let mut args = env.lock_args([1u8; 32], timelock);
+    args.src_receiver = fresh_receiver;
+    env.lock_with(args).await.assert_ok();
+    let solver = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+    create_token_account(&mut env.ctx, env.mint, solver.pubkey(), 0).await;
+
+    let fresh_token_account = get_associated_token_address(&fresh_receiver, &env.mint);
+    let mut accounts = env.redeem_accounts([1u8; 32], solver.pubkey()).await;
+    accounts.src_receiver = fresh_receiver;
+    accounts.src_receiver_token_account = fresh_token_account;
+    let ix = anchor_ix(
+        native::ID,
+        accounts,
+        instruction::Redeem {
+            Id: [1u8; 32],
+            secret: SECRET,
+            htlc_bump: htlc_pda(&env.sender.pubkey(), &[1u8; 32]).1,
+        },
+    );
+    process(&mut env.ctx, &[ix], &[&solver]).await.assert_ok();
+    assert_eq!(env.token_balance(fresh_token_account).await, AMOUNT);
+}
+
+#[tokio::test]
+async fn add_lock_rejects_a_second_hashlock_a_past_timelock_and_other_senders() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+
+    let past = env.in_seconds(0).await;
+    env.add_lock(Id, hashlock(), past)
+        .await
+        .assert_htlc_error(HtlcError::NotFutureTimeLock);
+
+    env.add_lock(Id, hashlock(), timelock).await.assert_ok();
+    env.add_lock(Id, [1u8; 32], timelock)
+        .await
+        .assert_htlc_error(HtlcError::HashlockAlreadySet);
+
+    // An all-zero hashlock locks the HTLC like any other.
+    let Id = env.commit(timelock).await;
+    env.add_lock(Id, [0u8; 32], timelock).await.assert_ok();
+    env.add_lock(Id, hashlock(), timelock)
+        .await
+        .assert_htlc_error(HtlcError::HashlockAlreadySet);
+
+    let intruder = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+    let ix = anchor_ix(
+        native::ID,
+        accounts::AddLock {
+            sender: intruder.pubkey(),
+            htlc: htlc_pda(&env.sender.pubkey(), &Id).0,
+            config: config_pda(),
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::AddLock {
+            Id,
+            hashlock: hashlock(),
+            timelock,
+            hash_algorithm: HashAlgorithm::Sha256,
+        },
+    );
+    process(&mut env.ctx, &[ix], &[&intruder])
+        .await
+        .assert_anchor_error(ErrorCode::ConstraintSeeds);
+}
+
+#[tokio::test]
+async fn add_lock_sig_accepts_only_the_sender_signature() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+    let sender = env.sender.insecure_clone();
+
+    let ixs = env.add_lock_sig_ixs(&Keypair::new(), Id, timelock, HashAlgorithm::Sha256);
+    process(&mut env.ctx, &ixs, &[])
+        .await
+        .assert_htlc_error(HtlcError::InvalidSignature);
+
+    let ixs = env.add_lock_sig_ixs(&sender, Id, timelock, HashAlgorithm::Sha256);
+    process(&mut env.ctx, &ixs[1..], &[])
+        .await
+        .assert_htlc_error(HtlcError::InvalidSignature);
+
+    // A signature over another hash algorithm does not cover this one.
+    let mut ixs = env.add_lock_sig_ixs(&sender, Id, timelock, HashAlgorithm::Sha256);
+    ixs[1] = env
+        .add_lock_sig_ixs(&sender, Id, timelock, HashAlgorithm::Keccak256)
+        .remove(1);
+    process(&mut env.ctx, &ixs, &[])
+        .await
+        .assert_htlc_error(HtlcError::InvalidSignature);
+
+    let ixs = env.add_lock_sig_ixs(&sender, Id, timelock, HashAlgorithm::Sha256);
+    process(&mut env.ctx, &ixs, &[]).await.assert_ok();
+    let htlc: HTLC = anchor_account(&mut env.ctx, htlc_pda(&sender.pubkey(), &Id).0)
+        .await
+        .unwrap();
+    assert_eq!(htlc.hashlock, hashlock());
+}
+
+#[tokio::test]
+async fn redeem_rejects_a_wrong_secret_and_an_unlocked_commit() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+
+    env.redeem(Id, SECRET)
+        .await
+        .assert_htlc_error(HtlcError::HashlockNotSet);
+
+    env.add_lock(Id, hashlock(), timelock).await.assert_ok();
+    env.redeem(Id, [0u8; 32])
+        .await
+        .assert_htlc_error(HtlcError::HashlockNoMatch);
+}
+
+#[tokio::test]
+async fn redeem_hashes_the_secret_with_the_htlc_hash_algorithm() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+
+    let mut args = env.lock_args([1u8; 32], timelock);
+    args.hashlock = keccak256(&SECRET);
+    args.hash_algorithm = HashAlgorithm::Keccak256;
+    env.lock_with(args).await.assert_ok();
+    let htlc: HTLC = anchor_account(&mut env.ctx, htlc_pda(&env.sender.pubkey(), &[1u8; 32]).0)
+        .await
+        .unwrap();
+    assert_eq!(htlc.hash_algorithm, HashAlgorithm::Keccak256);
+    env.redeem([1u8; 32], SECRET).await.assert_ok();
+
+    // A sha256 hashlock tagged Keccak256 does not match its own secret.
+    let mut args = env.lock_args([2u8; 32], timelock);
+    args.hash_algorithm = HashAlgorithm::Keccak256;
+    env.lock_with(args).await.assert_ok();
+    env.redeem([2u8; 32], SECRET)
+        .await
+        .assert_htlc_error(HtlcError::HashlockNoMatch);
+
+    // add_lock sets the algorithm of a committed HTLC.
+    let Id = env.commit(timelock).await;
+    env.add_lock_with(Id, keccak256(&SECRET), timelock, HashAlgorithm::Keccak256)
+        .await
+        .assert_ok();
+    env.redeem(Id, SECRET).await.assert_ok();
+}
+
+#[tokio::test]
+async fn redeem_twice_fails_on_the_closed_htlc() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+    env.add_lock(Id, hashlock(), timelock).await.assert_ok();
+
+    env.redeem(Id, SECRET).await.assert_ok();
+    next_blockhash(&mut env.ctx).await;
+    env.redeem(Id, SECRET)
+        .await
+        .assert_anchor_error(ErrorCode::AccountNotInitialized);
+}
+
+#[tokio::test]
+async fn redeem_rejects_the_wrong_mint_and_accounts() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+    env.add_lock(Id, hashlock(), timelock).await.assert_ok();
+    let src_receiver = env.src_receiver.insecure_clone();
+    let other_mint = create_mint(&mut env.ctx).await;
+    let other = Keypair::new().pubkey();
+    type Tamper = Box<dyn Fn(&mut accounts::Redeem)>;
+    let cases: Vec<(Tamper, HtlcError)> = vec![
+        (
+            Box::new(move |redeem: &mut accounts::Redeem| {
+                redeem.token_contract = other_mint;
+                redeem.src_receiver_token_account =
+                    get_associated_token_address(&redeem.src_receiver, &other_mint);
+            }),
+            HtlcError::NoToken,
+        ),
+        (
+            Box::new(move |redeem: &mut accounts::Redeem| {
+                redeem.src_receiver = other;
+                redeem.src_receiver_token_account =
+                    get_associated_token_address(&other, &redeem.token_contract);
+            }),
+            HtlcError::NotReciever,
+        ),
+        (
+            Box::new(move |redeem: &mut accounts::Redeem| redeem.payer = other),
+            HtlcError::NotPayer,
+        ),
+        (
+            Box::new(|redeem: &mut accounts::Redeem| {
+                redeem.sender_token_account = redeem.src_receiver_token_account
+            }),
+            HtlcError::NotSender,
+        ),
+        (
+            Box::new(|redeem: &mut accounts::Redeem| {
+                redeem.reward_token_account = Some(redeem.sender_token_account)
+            }),
+            HtlcError::UnauthorizedAccess,
+        ),
+    ];
+    for (tamper, error) in cases {
+        let mut accounts = env.redeem_accounts(Id, src_receiver.pubkey()).await;
+        tamper(&mut accounts);
+        let ix = anchor_ix(
+            native::ID,
+            accounts,
+            instruction::Redeem {
+                Id,
+                secret: SECRET,
+                htlc_bump: htlc_pda(&env.sender.pubkey(), &Id).1,
+            },
+        );
+        process(&mut env.ctx, &[ix], &[&src_receiver])
+            .await
+            .assert_htlc_error(error);
+    }
+
+    let mut accounts = env.redeem_accounts(Id, src_receiver.pubkey()).await;
+    accounts.sender = other;
+    let ix = anchor_ix(
+        native::ID,
+        accounts,
+        instruction::Redeem {
+            Id,
+            secret: SECRET,
+            htlc_bump: htlc_pda(&env.sender.pubkey(), &Id).1,
+        },
+    );
+    process(&mut env.ctx, &[ix], &[&src_receiver])
+        .await
+        .assert_anchor_error(ErrorCode::ConstraintSeeds);
+
+    env.redeem(Id, SECRET).await.assert_ok();
+}
+
+#[tokio::test]
+async fn redeem_batch_pays_out_each_item_and_skips_the_failing_ones() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let reward_timelock = timelock - 1800;
+    for Id in [[1u8; 32], [2u8; 32], [3u8; 32]] {
+        let mut args = env.lock_args(Id, timelock);
+        args.reward = REWARD;
+        args.reward_timelock = reward_timelock;
+        env.lock_with(args).await.assert_ok();
+    }
+    warp_to(&mut env.ctx, reward_timelock as i64).await;
+
+    let solver = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+    let solver_token_account =
+        create_token_account(&mut env.ctx, env.mint, solver.pubkey(), 0).await;
+    let ix = env
+        .redeem_batch_ix(
+            &[
+                ([1u8; 32], SECRET),
+                ([2u8; 32], [0u8; 32]),
+                ([3u8; 32], SECRET),
+            ],
+            solver.pubkey(),
+        )
+        .await;
+    let outcome = process(&mut env.ctx, &[ix], &[&solver]).await;
+    outcome.assert_ok();
+    assert_eq!(
+        Vec::<u32>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        [0, HtlcError::HashlockNoMatch.code(), 0]
+    );
+
+    assert_eq!(
+        env.token_balance(env.src_receiver_token_account()).await,
+        2 * AMOUNT
+    );
+    assert_eq!(env.token_balance(solver_token_account).await, 2 * REWARD);
+    for (Id, open) in [([1u8; 32], false), ([2u8; 32], true), ([3u8; 32], false)] {
+        let sender = env.sender.pubkey();
+        assert_eq!(
+            account_exists(&mut env.ctx, htlc_token_account_pda(&sender, &Id)).await,
+            open
+        );
+    }
+    assert_eq!(env.contracts(0).await, [[2u8; 32]]);
+}
+
+#[tokio::test]
+async fn redeem_batch_skips_items_with_the_wrong_mint_or_token_accounts() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let args = env.lock_args([1u8; 32], timelock);
+    env.lock_with(args).await.assert_ok();
+    let src_receiver = env.src_receiver.insecure_clone();
+    let other_mint = create_mint(&mut env.ctx).await;
+    let other_token_account =
+        create_token_account(&mut env.ctx, env.mint, Keypair::new().pubkey(), 0).await;
+
+    let mut ix = env
+        .redeem_batch_ix(&[([1u8; 32], SECRET)], src_receiver.pubkey())
+        .await;
+    ix.accounts.pop();
+    process(&mut env.ctx, &[ix], &[&src_receiver])
+        .await
+        .assert_htlc_error(HtlcError::BatchAccountsMismatch);
+
+    for (index, address, error) in [
+        (5, other_mint, HtlcError::NoToken),
+        (7, other_token_account, HtlcError::NotReciever),
+        (8, other_token_account, HtlcError::NotSender),
+    ] {
+        let mut ix = env
+            .redeem_batch_ix(&[([1u8; 32], SECRET)], src_receiver.pubkey())
+            .await;
+        ix.accounts[index].pubkey = address;
+        let outcome = process(&mut env.ctx, &[ix], &[&src_receiver]).await;
+        outcome.assert_ok();
+        assert_eq!(
+            Vec::<u32>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+            [error.code()]
+        );
+    }
+    env.redeem([1u8; 32], SECRET).await.assert_ok();
+}
+
+#[tokio::test]
+async fn lock_rejects_bad_terms_and_the_wrong_mint() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let past = env.in_seconds(0).await;
+
+    let args = env.lock_args([1u8; 32], past);
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::NotFutureTimeLock);
+
+    let mut args = env.lock_args([2u8; 32], timelock);
+    args.amount = 0;
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::FundsNotSent);
+
+    let mut args = env.lock_args([3u8; 32], timelock);
+    args.reward = REWARD;
+    args.reward_timelock = timelock + 1;
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::InvalidRewardTimelock);
+
+    let mut args = env.lock_args([4u8; 32], timelock);
+    args.src_asset = "a".repeat(native::MAX_ASSET_LEN + 1);
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::SrcAssetTooLong);
+
+    let other_mint = create_mint(&mut env.ctx).await;
+    let args = env.lock_args([5u8; 32], timelock);
+    let mut accounts = env.lock_accounts(args.Id).await;
+    accounts.token_contract = other_mint;
+    accounts.fee_vault = fee_vault_pda(&other_mint);
+    accounts.allowed_mint = allowed_mint_pda(&other_mint);
+    let ix = anchor_ix(native::ID, accounts, args);
+    let sender = env.sender.insecure_clone();
+    process(&mut env.ctx, &[ix], &[&sender])
+        .await
+        .assert_htlc_error(HtlcError::NoToken);
+}
+
+#[tokio::test]
+async fn lock_reward_goes_back_to_the_sender_before_the_reward_timelock() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let mut args = env.lock_args([1u8; 32], timelock);
+    args.reward = REWARD;
+    args.reward_timelock = timelock - 1800;
+    env.lock_with(args).await.assert_ok();
+    let htlc: HTLC = anchor_account(&mut env.ctx, htlc_pda(&env.sender.pubkey(), &[1u8; 32]).0)
+        .await
+        .unwrap();
+    assert_eq!(htlc.status, HTLCStatus::Locked);
+    assert_eq!(
+        env.token_balance(env.sender_token_account()).await,
+        SUPPLY - AMOUNT - REWARD
+    );
+
+    env.redeem([1u8; 32], SECRET).await.assert_ok();
+    assert_eq!(
+        env.token_balance(env.src_receiver_token_account()).await,
+        AMOUNT
+    );
+    assert_eq!(
+        env.token_balance(env.sender_token_account()).await,
+        SUPPLY - AMOUNT
+    );
+}
+
+#[tokio::test]
+async fn lock_reward_goes_to_the_redeemer_after_the_reward_timelock() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let reward_timelock = timelock - 1800;
+    for Id in [[1u8; 32], [2u8; 32]] {
+        let mut args = env.lock_args(Id, timelock);
+        args.reward = REWARD;
+        args.reward_timelock = reward_timelock;
+        env.lock_with(args).await.assert_ok();
+    }
+    warp_to(&mut env.ctx, reward_timelock as i64).await;
+
+    let solver = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+    let solver_token_account =
+        create_token_account(&mut env.ctx, env.mint, solver.pubkey(), 0).await;
+    env.redeem_by([1u8; 32], SECRET, &solver).await.assert_ok();
+    assert_eq!(
+        env.token_balance(env.src_receiver_token_account()).await,
+        AMOUNT
+    );
+    assert_eq!(env.token_balance(solver_token_account).await, REWARD);
+
+    env.redeem([2u8; 32], SECRET).await.assert_ok();
+    assert_eq!(
+        env.token_balance(env.src_receiver_token_account()).await,
+        2 * AMOUNT + REWARD
+    );
+    assert_eq!(
+        env.token_balance(env.sender_token_account()).await,
+        SUPPLY - 2 * (AMOUNT + REWARD)
+    );
+}
+
+#[tokio::test]
+async fn redeem_needs_the_redeemer_token_account_only_to_pay_it_the_reward() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let reward_timelock = timelock - 1800;
+    let args = env.lock_args([1u8; 32], timelock);
+    env.lock_with(args).await.assert_ok();
+    for Id in [[2u8; 32], [3u8; 32]] {
+        let mut args = env.lock_args(Id, timelock);
+        args.reward = REWARD;
+        args.reward_timelock = reward_timelock;
+        env.lock_with(args).await.assert_ok();
+    }
+    warp_to(&mut env.ctx, reward_timelock as i64).await;
+    let solver = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+
+    for (Id, expected) in [
+        ([2u8; 32], Err(HtlcError::RewardAccountMissing)),
+        ([1u8; 32], Ok(())),
+    ] {
+        let mut accounts = env.redeem_accounts(Id, solver.pubkey()).await;
+        accounts.reward_token_account = None;
+        let ix = anchor_ix(
+            native::ID,
+            accounts,
+            instruction::Redeem {
+                Id,
+                secret: SECRET,
+                htlc_bump: htlc_pda(&env.sender.pubkey(), &Id).1,
+            },
+        );
+        let outcome = process(&mut env.ctx, &[ix], &[&solver]).await;
+        match expected {
+            Ok(()) => outcome.assert_ok(),
+            Err(error) => outcome.assert_htlc_error(error),
+        }
+    }
+    assert_eq!(
+        env.token_balance(env.src_receiver_token_account()).await,
+        AMOUNT
+    );
+
+    let mut ix = env
+        .redeem_batch_ix(&[([3u8; 32], SECRET)], solver.pubkey())
+        .await;
+    assert_eq!(
+        ix.accounts[9].pubkey,
+        get_associated_token_address(&solver.pubkey(), &env.mint)
+    );
+    ix.accounts[9].pubkey = native::ID;
+    let outcome = process(&mut env.ctx, &[ix], &[&solver]).await;
+    outcome.assert_ok();
+    assert_eq!(
+        Vec::<u32>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        [HtlcError::RewardAccountMissing.code()]
+    );
+}
+
+#[tokio::test]
+async fn redeem_needs_the_fee_vault_only_to_pay_it_the_fee() {
+    let mut env = setup().await;
+    let admin = env.admin.insecure_clone();
+    let src_receiver = env.src_receiver.insecure_clone();
+    let timelock = env.in_seconds(3600).await;
+    let args = env.lock_args([1u8; 32], timelock);
+    env.lock_with(args).await.assert_ok();
+    env.set_fee(&admin, 100).await.assert_ok();
+    for Id in [[2u8; 32], [3u8; 32]] {
+        let args = env.lock_args(Id, timelock);
+        env.lock_with(args).await.assert_ok();
+    }
+
+    for (Id, expected) in [
+        ([2u8; 32], Err(HtlcError::FeeVaultMissing)),
+        ([1u8; 32], Ok(())),
+    ] {
+        let mut accounts = env.redeem_accounts(Id, src_receiver.pubkey()).await;
+        accounts.fee_vault = None;
+        let ix = anchor_ix(
+            native::ID,
+            accounts,
+            instruction::Redeem {
+                Id,
+                secret: SECRET,
+                htlc_bump: htlc_pda(&env.sender.pubkey(), &Id).1,
+            },
+        );
+        let outcome = process(&mut env.ctx, &[ix], &[&src_receiver]).await;
+        match expected {
+            Ok(()) => outcome.assert_ok(),
+            Err(error) => outcome.assert_htlc_error(error),
+        }
+    }
+    assert_eq!(
+        env.token_balance(env.src_receiver_token_account()).await,
+        AMOUNT
+    );
+
+    let mut ix = env
+        .redeem_batch_ix(&[([3u8; 32], SECRET)], src_receiver.pubkey())
+        .await;
+    assert_eq!(ix.accounts[6].pubkey, fee_vault_pda(&env.mint));
+    ix.accounts[6].pubkey = native::ID;
+    let outcome = process(&mut env.ctx, &[ix], &[&src_receiver]).await;
+    outcome.assert_ok();
+    assert_eq!(
+        Vec::<u32>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        [HtlcError::FeeVaultMissing.code()]
+    );
+
+    let ix = env
+        .redeem_batch_ix(&[([3u8; 32], SECRET)], src_receiver.pubkey())
+        .await;
+    let outcome = process(&mut env.ctx, &[ix], &[&src_receiver]).await;
+    outcome.assert_ok();
+    assert_eq!(
+        Vec::<u32>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        [0]
+    );
+    assert_eq!(
+        env.token_balance(fee_vault_pda(&env.mint)).await,
+        AMOUNT / 100
+    );
+}
+
+#[tokio::test]
+async fn refund_waits_for_the_timelock_and_returns_everything() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = [1u8; 32];
+    let mut args = env.lock_args(Id, timelock);
+    args.reward = REWARD;
+    args.reward_timelock = timelock;
+    env.lock_with(args).await.assert_ok();
+    let htlc_address = htlc_pda(&env.sender.pubkey(), &Id).0;
+    let htlc_token_account = htlc_token_account_pda(&env.sender.pubkey(), &Id);
+
+    env.refund(Id)
+        .await
+        .assert_htlc_error(HtlcError::NotPastTimeLock);
+
+    warp_to(&mut env.ctx, timelock as i64).await;
+    next_blockhash(&mut env.ctx).await;
+    env.refund(Id).await.assert_ok();
+
+    assert!(!account_exists(&mut env.ctx, htlc_address).await);
+    assert!(!account_exists(&mut env.ctx, htlc_token_account).await);
+    assert_eq!(env.token_balance(env.sender_token_account()).await, SUPPLY);
+    assert!(env.contracts(0).await.is_empty());
+
+    next_blockhash(&mut env.ctx).await;
+    env.refund(Id)
+        .await
+        .assert_anchor_error(ErrorCode::AccountNotInitialized);
+}
+
+#[tokio::test]
+async fn refund_batch_refunds_expired_htlcs_and_skips_the_rest() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    for (Id, timelock) in [
+        ([1u8; 32], timelock),
+        ([2u8; 32], timelock + 3600),
+        ([3u8; 32], timelock),
+    ] {
+        let mut args = env.lock_args(Id, timelock);
+        args.reward = REWARD;
+        args.reward_timelock = timelock;
+        env.lock_with(args).await.assert_ok();
+    }
+    warp_to(&mut env.ctx, timelock as i64).await;
+
+    let ix = env
+        .refund_batch_ix(&[[1u8; 32], [2u8; 32], [3u8; 32]], env.sender.pubkey())
+        .await;
+    let sender = env.sender.insecure_clone();
+    let outcome = process(&mut env.ctx, &[ix], &[&sender]).await;
+    outcome.assert_ok();
+    assert_eq!(
+        Vec::<u32>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        [0, HtlcError::NotPastTimeLock.code(), 0]
+    );
+
+    assert_eq!(
+        env.token_balance(env.sender_token_account()).await,
+        SUPPLY - AMOUNT - REWARD
+    );
+    for (Id, open) in [([1u8; 32], false), ([2u8; 32], true), ([3u8; 32], false)] {
+        let htlc_address = htlc_pda(&sender.pubkey(), &Id).0;
+        assert_eq!(account_exists(&mut env.ctx, htlc_address).await, open);
+    }
+    assert_eq!(env.contracts(0).await, [[2u8; 32]]);
+
+    let mut ix = env.refund_batch_ix(&[[2u8; 32]], env.sender.pubkey()).await;
+    ix.accounts[7].pubkey = get_associated_token_address(&env.src_receiver.pubkey(), &env.mint);
+    let outcome = process(&mut env.ctx, &[ix], &[&sender]).await;
+    outcome.assert_ok();
+    assert_eq!(
+        Vec::<u32>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        [HtlcError::NotSender.code()]
+    );
+}
+
+#[tokio::test]
+async fn refund_by_a_keeper_returns_the_tokens_to_the_sender_and_pays_the_bounty() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let mut args = env.commit_args(timelock, AMOUNT).await;
+    args.keeper_bounty = KEEPER_BOUNTY;
+    let committed = args.Id;
+    env.commit_with(args).await.assert_ok();
+    for Id in [[1u8; 32], [2u8; 32]] {
+        let mut args = env.lock_args(Id, timelock);
+        args.keeper_bounty = KEEPER_BOUNTY;
+        env.lock_with(args).await.assert_ok();
+    }
+    let htlc: HTLC = anchor_account(&mut env.ctx, htlc_pda(&env.sender.pubkey(), &committed).0)
+        .await
+        .unwrap();
+    assert_eq!(htlc.keeper_bounty, KEEPER_BOUNTY);
+
+    let keeper = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+    env.refund_by(committed, &keeper)
+        .await
+        .assert_htlc_error(HtlcError::NotPastTimeLock);
+
+    warp_to(&mut env.ctx, timelock as i64).await;
+    let mut htlc_lamports = 0;
+    for Id in [committed, [1u8; 32], [2u8; 32]] {
+        htlc_lamports += balance(&mut env.ctx, htlc_pda(&env.sender.pubkey(), &Id).0).await;
+        htlc_lamports += balance(
+            &mut env.ctx,
+            htlc_token_account_pda(&env.sender.pubkey(), &Id),
+        )
+        .await;
+    }
+    let sender_before = balance(&mut env.ctx, env.sender.pubkey()).await;
+    let keeper_before = balance(&mut env.ctx, keeper.pubkey()).await;
+    next_blockhash(&mut env.ctx).await;
+    env.refund_by(committed, &keeper).await.assert_ok();
+    let ix = env
+        .refund_batch_ix(&[[1u8; 32], [2u8; 32]], keeper.pubkey())
+        .await;
+    let outcome = process(&mut env.ctx, &[ix], &[&keeper]).await;
+    outcome.assert_ok();
+    assert_eq!(
+        Vec::<u32>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        [0, 0]
+    );
+
+    assert_eq!(env.token_balance(env.sender_token_account()).await, SUPPLY);
+    assert_eq!(
+        balance(&mut env.ctx, keeper.pubkey()).await,
+        keeper_before + 3 * KEEPER_BOUNTY
+    );
+    assert_eq!(
+        balance(&mut env.ctx, env.sender.pubkey()).await,
+        sender_before + htlc_lamports - 3 * KEEPER_BOUNTY
+    );
+    assert!(env.contracts(0).await.is_empty());
+}
+
+#[tokio::test]
+async fn redeem_returns_the_keeper_bounty_to_the_sender() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = [1u8; 32];
+    let mut args = env.lock_args(Id, timelock);
+    args.keeper_bounty = KEEPER_BOUNTY;
+    env.lock_with(args).await.assert_ok();
+    let htlc_lamports = balance(&mut env.ctx, htlc_pda(&env.sender.pubkey(), &Id).0).await
+        + balance(
+            &mut env.ctx,
+            htlc_token_account_pda(&env.sender.pubkey(), &Id),
+        )
+        .await;
+
+    let sender_before = balance(&mut env.ctx, env.sender.pubkey()).await;
+    env.redeem(Id, SECRET).await.assert_ok();
+
+    assert_eq!(
+        env.token_balance(env.src_receiver_token_account()).await,
+        AMOUNT
+    );
+    assert_eq!(
+        balance(&mut env.ctx, env.sender.pubkey()).await,
+        sender_before + htlc_lamports
+    );
+}
+
+#[tokio::test]
+async fn refund_rejects_the_wrong_mint_payer_and_token_account() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+    warp_to(&mut env.ctx, timelock as i64).await;
+    let other_mint = create_mint(&mut env.ctx).await;
+
+    let mut accounts = env.refund_accounts(Id).await;
+    accounts.token_contract = other_mint;
+    env.refund_with(Id, accounts)
+        .await
+        .assert_htlc_error(HtlcError::NoToken);
+
+    let mut accounts = env.refund_accounts(Id).await;
+    accounts.payer = Keypair::new().pubkey();
+    env.refund_with(Id, accounts)
+        .await
+        .assert_htlc_error(HtlcError::NotPayer);
+
+    let mut accounts = env.refund_accounts(Id).await;
+    accounts.sender_token_account = env.src_receiver_token_account();
+    env.refund_with(Id, accounts)
+        .await
+        .assert_htlc_error(HtlcError::NotSender);
+
+    env.refund(Id).await.assert_ok();
+    env.redeem(Id, SECRET)
+        .await
+        .assert_anchor_error(ErrorCode::AccountNotInitialized);
+}
+
+#[tokio::test]
+async fn token_2022_htlcs_are_redeemed_and_refunded_with_and_without_a_transfer_fee() {
+    for transfer_fee_bps in [None, Some(100u16)] {
+        let mut env = setup().await;
+        env.use_token_2022_mint(transfer_fee_bps).await;
+        let after_transfer_fee =
+            |amount: u64| amount - amount * u64::from(transfer_fee_bps.unwrap_or(0)) / 10_000;
+        let locked = after_transfer_fee(AMOUNT);
+        let timelock = env.in_seconds(3600).await;
+        let Ids = [[1u8; 32], [2u8; 32], [3u8; 32], [4u8; 32]];
+        for Id in Ids {
+            let args = env.lock_args(Id, timelock);
+            env.lock_with(args).await.assert_ok();
+            let htlc: HTLC = anchor_account(&mut env.ctx, htlc_pda(&env.sender.pubkey(), &Id).0)
+                .await
+                .unwrap();
+            assert_eq!(htlc.amount, locked);
+        }
+
+        if transfer_fee_bps.is_some() {
+            // The deposit left a withheld fee the mint has to take before the close.
+            let src_receiver = env.src_receiver.insecure_clone();
+            let accounts = env.redeem_accounts(Ids[0], src_receiver.pubkey()).await;
+            let ix = anchor_ix(
+                native::ID,
+                accounts,
+                instruction::Redeem {
+                    Id: Ids[0],
+                    secret: SECRET,
+                    htlc_bump: htlc_pda(&env.sender.pubkey(), &Ids[0]).1,
+                },
+            );
+            process(&mut env.ctx, &[ix], &[&src_receiver])
+                .await
+                .assert_anchor_error(ErrorCode::ConstraintMut);
+        }
+        env.redeem(Ids[0], SECRET).await.assert_ok();
+        let src_receiver = env.src_receiver.insecure_clone();
+        let ix = env
+            .redeem_batch_ix(&[(Ids[1], SECRET)], src_receiver.pubkey())
+            .await;
+        let outcome = process(&mut env.ctx, &[ix], &[&src_receiver]).await;
+        outcome.assert_ok();
+        assert_eq!(
+            Vec::<u32>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+            [0]
+        );
+
+        warp_to(&mut env.ctx, timelock as i64).await;
+        env.refund(Ids[2]).await.assert_ok();
+        let ix = env.refund_batch_ix(&[Ids[3]], env.sender.pubkey()).await;
+        let sender = env.sender.insecure_clone();
+        let outcome = process(&mut env.ctx, &[ix], &[&sender]).await;
+        outcome.assert_ok();
+        assert_eq!(
+            Vec::<u32>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+            [0]
+        );
+
+        for Id in Ids {
+            let sender = env.sender.pubkey();
+            assert!(!account_exists(&mut env.ctx, htlc_pda(&sender, &Id).0).await);
+            assert!(!account_exists(&mut env.ctx, htlc_token_account_pda(&sender, &Id)).await);
+        }
+        assert_eq!(
+            env.token_balance(env.src_receiver_token_account()).await,
+            2 * after_transfer_fee(locked)
+        );
+        assert_eq!(
+            env.token_balance(env.sender_token_account()).await,
+            SUPPLY - 4 * AMOUNT + 2 * after_transfer_fee(locked)
+        );
+        let mint = env
+            .ctx
+            .banks_client
+            .get_account(env.mint)
+            .await
+            .unwrap()
+            .unwrap();
+        let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint.data).unwrap();
+        let harvested = mint
+            .get_extension::<TransferFeeConfig>()
+            .map_or(0, |config| u64::from(config.withheld_amount));
+        assert_eq!(harvested, 4 * (AMOUNT - locked));
+        assert!(env.contracts(0).await.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn legacy_htlcs_can_still_be_redeemed() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = [5u8; 32];
+    env.legacy_htlc(Id, timelock, false, false).await;
+
+    env.redeem_legacy(Id, [0u8; 32])
+        .await
+        .assert_htlc_error(HtlcError::HashlockNoMatch);
+
+    let other_mint = create_mint(&mut env.ctx).await;
+    let mut accounts = env.redeem_legacy_accounts(Id);
+    accounts.token_contract = other_mint;
+    accounts.src_receiver_token_account =
+        get_associated_token_address(&env.src_receiver.pubkey(), &other_mint);
+    env.redeem_legacy_with(Id, SECRET, accounts)
+        .await
+        .assert_htlc_error(HtlcError::NoToken);
+
+    let mut accounts = env.redeem_legacy_accounts(Id);
+    accounts.sender = Keypair::new().pubkey();
+    env.redeem_legacy_with(Id, SECRET, accounts)
+        .await
+        .assert_htlc_error(HtlcError::NotSender);
+
+    let rent_lamports = balance(&mut env.ctx, legacy_htlc_pda(&Id)).await
+        + balance(&mut env.ctx, legacy_htlc_token_account_pda(&Id)).await;
+    let sender_before = balance(&mut env.ctx, env.sender.pubkey()).await;
+    env.redeem_legacy(Id, SECRET).await.assert_ok();
+
+    assert!(!account_exists(&mut env.ctx, legacy_htlc_pda(&Id)).await);
+    assert!(!account_exists(&mut env.ctx, legacy_htlc_token_account_pda(&Id)).await);
+    assert_eq!(
+        env.token_balance(env.src_receiver_token_account()).await,
+        AMOUNT
+    );
+    assert_eq!(
+        balance(&mut env.ctx, env.sender.pubkey()).await,
+        sender_before + rent_lamports
+    );
+}
+
+#[tokio::test]
+async fn legacy_htlcs_can_still_be_refunded() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = [6u8; 32];
+    env.legacy_htlc(Id, timelock, false, false).await;
+
+    let accounts = env.refund_legacy_accounts(Id);
+    env.refund_legacy_with(Id, accounts)
+        .await
+        .assert_htlc_error(HtlcError::NotPastTimeLock);
+    warp_to(&mut env.ctx, timelock as i64).await;
+    next_blockhash(&mut env.ctx).await;
+
+    let other = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+    let other_token_account = create_token_account(&mut env.ctx, env.mint, other.pubkey(), 0).await;
+    let mut accounts = env.refund_legacy_accounts(Id);
+    accounts.sender = other.pubkey();
+    accounts.sender_token_account = other_token_account;
+    env.refund_legacy_with(Id, accounts)
+        .await
+        .assert_htlc_error(HtlcError::NotSender);
+
+    let sender_before = env.token_balance(env.sender_token_account()).await;
+    let accounts = env.refund_legacy_accounts(Id);
+    env.refund_legacy_with(Id, accounts).await.assert_ok();
+    assert!(!account_exists(&mut env.ctx, legacy_htlc_pda(&Id)).await);
+    assert_eq!(
+        env.token_balance(env.sender_token_account()).await,
+        sender_before + AMOUNT
+    );
+}
+
+#[tokio::test]
+async fn legacy_htlcs_keep_their_redeemed_and_refunded_flags() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    env.legacy_htlc([7u8; 32], timelock, true, false).await;
+    env.legacy_htlc([8u8; 32], timelock, false, true).await;
+
+    env.redeem_legacy([7u8; 32], SECRET)
+        .await
+        .assert_htlc_error(HtlcError::AlreadyRedeemed);
+    env.redeem_legacy([8u8; 32], SECRET)
+        .await
+        .assert_htlc_error(HtlcError::AlreadyRefunded);
+}
+
+#[tokio::test]
+async fn legacy_paths_reject_non_legacy_accounts() {
+    let mut env = setup().await;
+    let Id = [9u8; 32];
+    env.legacy_token_account(Id).await;
+    set_account_data(
+        &mut env.ctx,
+        legacy_htlc_pda(&Id),
+        native::ID,
+        vec![0u8; 64],
+        0,
+    )
+    .await;
+    env.redeem_legacy(Id, SECRET)
+        .await
+        .assert_htlc_error(HtlcError::NotLegacyHTLC);
+
+    let Id = [10u8; 32];
+    env.legacy_token_account(Id).await;
+    env.redeem_legacy(Id, SECRET)
+        .await
+        .assert_htlc_error(HtlcError::NotLegacyHTLC);
+}
+
+#[tokio::test]
+async fn initialize_config_makes_the_upgrade_authority_admin_only_once() {
+    let mut env = setup_without_config().await;
+    let admin = env.admin.insecure_clone();
+    let other = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+    env.initialize_config(&other)
+        .await
+        .assert_htlc_error(HtlcError::NotUpgradeAuthority);
+    assert!(!account_exists(&mut env.ctx, config_pda()).await);
+
+    env.initialize_config(&admin).await.assert_ok();
+    let config = env.config().await;
+    assert_eq!(config.admin, env.admin.pubkey());
+    assert_eq!(config.pending_admin, None);
+    assert!(!config.paused);
+
+    // The Config already exists, so the system program refuses to create it
+    // again (AccountAlreadyInUse).
+    next_blockhash(&mut env.ctx).await;
+    assert_eq!(env.initialize_config(&admin).await.error_code(), Some(0));
+    assert_eq!(env.config().await.admin, env.admin.pubkey());
+}
+
+#[tokio::test]
+async fn admin_transfer_takes_effect_once_accepted() {
+    let mut env = setup().await;
+    let admin = env.admin.insecure_clone();
+    let new_admin = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+
+    env.transfer_admin(&new_admin, new_admin.pubkey())
+        .await
+        .assert_htlc_error(HtlcError::NotAdmin);
+    env.accept_admin(&new_admin)
+        .await
+        .assert_htlc_error(HtlcError::NotPendingAdmin);
+
+    env.transfer_admin(&admin, new_admin.pubkey())
+        .await
+        .assert_ok();
+    let config = env.config().await;
+    assert_eq!(config.admin, admin.pubkey());
+    assert_eq!(config.pending_admin, Some(new_admin.pubkey()));
+    env.accept_admin(&admin)
+        .await
+        .assert_htlc_error(HtlcError::NotPendingAdmin);
+
+    next_blockhash(&mut env.ctx).await;
+    env.accept_admin(&new_admin).await.assert_ok();
+    let config = env.config().await;
+    assert_eq!(config.admin, new_admin.pubkey());
+    assert_eq!(config.pending_admin, None);
+
+    env.set_paused(&admin, true)
+        .await
+        .assert_htlc_error(HtlcError::NotAdmin);
+    env.set_paused(&new_admin, true).await.assert_ok();
+    assert!(env.config().await.paused);
+}
+
+#[tokio::test]
+async fn pause_blocks_commit_and_lock_but_not_redeem_or_refund() {
+    let mut env = setup().await;
+    let admin = env.admin.insecure_clone();
+    let timelock = env.in_seconds(3600).await;
+    let refunded = env.commit(timelock).await;
+    let redeemed = env.commit(timelock).await;
+    let args = env.lock_args([1u8; 32], timelock);
+    env.lock_with(args).await.assert_ok();
+
+    env.set_paused(&admin, true).await.assert_ok();
+    let args = env.commit_args(timelock, AMOUNT).await;
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::Paused);
+    let args = env.lock_args([2u8; 32], timelock);
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::Paused);
+
+    env.add_lock(redeemed, hashlock(), timelock)
+        .await
+        .assert_ok();
+    env.redeem(redeemed, SECRET).await.assert_ok();
+    env.redeem([1u8; 32], SECRET).await.assert_ok();
+    warp_to(&mut env.ctx, timelock as i64).await;
+    env.refund(refunded).await.assert_ok();
+
+    env.set_paused(&admin, false).await.assert_ok();
+    let timelock = env.in_seconds(3600).await;
+    env.commit(timelock).await;
+    let args = env.lock_args([2u8; 32], timelock);
+    env.lock_with(args).await.assert_ok();
+}
+
+#[tokio::test]
+async fn redeem_pays_the_fee_into_the_vault_and_the_admin_withdraws_it() {
+    let mut env = setup().await;
+    let admin = env.admin.insecure_clone();
+    let sender = env.sender.insecure_clone();
+    assert_eq!(env.config().await.fee_bps, 0);
+
+    env.set_fee(&sender, 100)
+        .await
+        .assert_htlc_error(HtlcError::NotAdmin);
+    env.set_fee(&admin, MAX_FEE_BPS + 1)
+        .await
+        .assert_htlc_error(HtlcError::FeeTooHigh);
+    env.set_fee(&admin, 100).await.assert_ok();
+    assert_eq!(env.config().await.fee_bps, 100);
+
+    let timelock = env.in_seconds(3600).await;
+    let args = env.lock_args([1u8; 32], timelock);
+    env.lock_with(args).await.assert_ok();
+    let htlc = htlc_pda(&sender.pubkey(), &[1u8; 32]).0;
+    let locked = anchor_account::<HTLC>(&mut env.ctx, htlc).await.unwrap();
+    assert_eq!(locked.fee_bps, 100);
+    // The HTLC keeps the fee it was locked with.
+    env.set_fee(&admin, MAX_FEE_BPS).await.assert_ok();
+    let fee = AMOUNT / 100;
+    let fee_vault = fee_vault_pda(&env.mint);
+    assert_eq!(env.token_balance(fee_vault).await, 0);
+    let receiver_before = env.token_balance(env.src_receiver_token_account()).await;
+    let outcome = env.redeem([1u8; 32], SECRET).await;
+    outcome.assert_ok();
+    assert_eq!(
+        u64::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        fee
+    );
+    assert_eq!(
+        env.token_balance(env.src_receiver_token_account()).await,
+        receiver_before + AMOUNT - fee
+    );
+    assert_eq!(env.token_balance(fee_vault).await, fee);
+
+    let recipient = Keypair::new().pubkey();
+    let recipient_token_account = create_token_account(&mut env.ctx, env.mint, recipient, 0).await;
+    let other_mint = create_mint(&mut env.ctx).await;
+    let other_token_account = create_token_account(&mut env.ctx, other_mint, recipient, 0).await;
+    env.withdraw_fees(&sender, recipient_token_account, env.mint)
+        .await
+        .assert_htlc_error(HtlcError::NotAdmin);
+    env.withdraw_fees(&admin, other_token_account, env.mint)
+        .await
+        .assert_htlc_error(HtlcError::NoToken);
+    let outcome = env
+        .withdraw_fees(&admin, recipient_token_account, env.mint)
+        .await;
+    outcome.assert_ok();
+    assert_eq!(
+        u64::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        fee
+    );
+    assert_eq!(env.token_balance(recipient_token_account).await, fee);
+    assert_eq!(env.token_balance(fee_vault).await, 0);
+}
+
+#[tokio::test]
+async fn commit_and_lock_take_only_allowed_mints_with_their_src_asset() {
+    let mut env = setup().await;
+    let admin = env.admin.insecure_clone();
+    let sender = env.sender.insecure_clone();
+    let timelock = env.in_seconds(3600).await;
+    let other_mint = create_mint(&mut env.ctx).await;
+    let other_token_account =
+        create_token_account(&mut env.ctx, other_mint, sender.pubkey(), SUPPLY).await;
+
+    env.allow_mint(&sender, other_mint, "USDT")
+        .await
+        .assert_htlc_error(HtlcError::NotAdmin);
+    env.allow_mint(&admin, other_mint, &"a".repeat(native::MAX_ASSET_LEN + 1))
+        .await
+        .assert_htlc_error(HtlcError::SrcAssetTooLong);
+
+    let args = env.commit_args(timelock, AMOUNT).await;
+    let mut accounts = env.commit_accounts(args.Id).await;
+    accounts.token_contract = other_mint;
+    accounts.fee_vault = fee_vault_pda(&other_mint);
+    accounts.allowed_mint = allowed_mint_pda(&other_mint);
+    accounts.sender_token_account = other_token_account;
+    let ix = anchor_ix(native::ID, accounts, args);
+    process(&mut env.ctx, std::slice::from_ref(&ix), &[&sender])
+        .await
+        .assert_htlc_error(HtlcError::MintNotAllowed);
+
+    // A spoofed mint claiming to be USDC is rejected once allowed as USDT.
+    env.allow_mint(&admin, other_mint, "USDT").await.assert_ok();
+    process(&mut env.ctx, &[ix], &[&sender])
+        .await
+        .assert_htlc_error(HtlcError::SrcAssetMismatch);
+    let mut args = env.lock_args([1u8; 32], timelock);
+    args.src_asset = "USDT".to_string();
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::SrcAssetMismatch);
+
+    let mut args = env.commit_args(timelock, AMOUNT).await;
+    args.src_asset = "USDT".to_string();
+    let mut accounts = env.commit_accounts(args.Id).await;
+    accounts.token_contract = other_mint;
+    accounts.fee_vault = fee_vault_pda(&other_mint);
+    accounts.allowed_mint = allowed_mint_pda(&other_mint);
+    accounts.sender_token_account = other_token_account;
+    let ix = anchor_ix(native::ID, accounts, args);
+    process(&mut env.ctx, &[ix], &[&sender]).await.assert_ok();
+
+    // Removing a mint blocks new HTLCs of it but not the open ones.
+    let args = env.lock_args([1u8; 32], timelock);
+    env.lock_with(args).await.assert_ok();
+    env.remove_mint(&sender, env.mint)
+        .await
+        .assert_htlc_error(HtlcError::NotAdmin);
+    env.remove_mint(&admin, env.mint).await.assert_ok();
+    assert!(!account_exists(&mut env.ctx, allowed_mint_pda(&env.mint)).await);
+    let args = env.lock_args([2u8; 32], timelock);
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::MintNotAllowed);
+    env.redeem([1u8; 32], SECRET).await.assert_ok();
+}
+
+#[tokio::test]
+async fn timelocks_must_fall_within_the_timelock_window() {
+    let mut env = setup().await;
+    let admin = env.admin.insecure_clone();
+    let sender = env.sender.insecure_clone();
+    let config = env.config().await;
+    assert_eq!(
+        (config.min_timelock_window, config.max_timelock_window),
+        (DEFAULT_MIN_TIMELOCK_WINDOW, DEFAULT_MAX_TIMELOCK_WINDOW)
+    );
+
+    let too_short = env.in_seconds(DEFAULT_MIN_TIMELOCK_WINDOW as i64 - 1).await;
+    let too_long = env.in_seconds(DEFAULT_MAX_TIMELOCK_WINDOW as i64 + 1).await;
+    let args = env.commit_args(too_short, AMOUNT).await;
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::TimelockTooShort);
+    let args = env.lock_args([1u8; 32], too_long);
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::TimelockTooLong);
+
+    let timelock = env.in_seconds(DEFAULT_MIN_TIMELOCK_WINDOW as i64).await;
+    let Id = env.commit(timelock).await;
+    env.add_lock(Id, hashlock(), too_short)
+        .await
+        .assert_htlc_error(HtlcError::TimelockTooShort);
+    env.add_lock(Id, hashlock(), too_long)
+        .await
+        .assert_htlc_error(HtlcError::TimelockTooLong);
+
+    env.set_timelock_window(&sender, 60, 600)
+        .await
+        .assert_htlc_error(HtlcError::NotAdmin);
+    env.set_timelock_window(&admin, 601, 600)
+        .await
+        .assert_htlc_error(HtlcError::InvalidTimelockWindow);
+    env.set_timelock_window(&admin, 60, 600).await.assert_ok();
+    let timelock = env.in_seconds(601).await;
+    let args = env.lock_args([1u8; 32], timelock);
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::TimelockTooLong);
+    let timelock = env.in_seconds(60).await;
+    let args = env.lock_args([1u8; 32], timelock);
+    env.lock_with(args).await.assert_ok();
+    let timelock = env.in_seconds(600).await;
+    env.add_lock(Id, hashlock(), timelock).await.assert_ok();
+}
+
+#[tokio::test]
+async fn extend_timelock_only_moves_the_timelock_later_within_the_window() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+    let htlc_address = htlc_pda(&env.sender.pubkey(), &Id).0;
+
+    env.extend_timelock(Id, timelock)
+        .await
+        .assert_htlc_error(HtlcError::TimelockNotExtended);
+    env.extend_timelock(Id, timelock - 1)
+        .await
+        .assert_htlc_error(HtlcError::TimelockNotExtended);
+    let too_long = env.in_seconds(DEFAULT_MAX_TIMELOCK_WINDOW as i64 + 1).await;
+    env.extend_timelock(Id, too_long)
+        .await
+        .assert_htlc_error(HtlcError::TimelockTooLong);
+    let intruder = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+    env.extend_timelock_by(Id, timelock + 3600, &intruder)
+        .await
+        .assert_anchor_error(ErrorCode::ConstraintSeeds);
+
+    env.extend_timelock(Id, timelock + 3600).await.assert_ok();
+    let htlc: HTLC = anchor_account(&mut env.ctx, htlc_address).await.unwrap();
+    assert_eq!(htlc.timelock, timelock + 3600);
+    assert_eq!(htlc.status, HTLCStatus::Committed);
+
+    warp_to(&mut env.ctx, timelock as i64).await;
+    env.refund_by(Id, &intruder)
+        .await
+        .assert_htlc_error(HtlcError::NotPastTimeLock);
+    warp_to(&mut env.ctx, (timelock + 3600) as i64).await;
+    env.refund(Id).await.assert_ok();
+}
@@ -0,0 +1,1740 @@
+//! native_htlc (`sol`) under solana-program-test.
+//!
+//! Redeem and refund close the HTLC account, so a second redeem or refund fails
+//! on the missing account; AlreadyRedeemed / AlreadyRefunded are reached through
+//! the legacy accounts, which keep their flags. The HTLC seeds include the
+//! sender, so passing another sender fails on the seeds before has_one.
+#![allow(non_snake_case)]
+
+use anchor_lang::error::ErrorCode;
+use anchor_lang::prelude::AccountInfo;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::{AnchorDeserialize, AnchorSerialize, Discriminator};
+use sol::{
+    accounts, instruction, Config, ContractsIndex, ContractsPage, HTLCStatus, HashAlgorithm,
+    LegacyHTLC, RedeemBatchItem, SenderNonce, DEFAULT_MAX_TIMELOCK_WINDOW,
+    DEFAULT_MIN_TIMELOCK_WINDOW, HTLC, MAX_BATCH_LEN, MAX_FEE_BPS,
+};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    bpf_loader_upgradeable,
+    instruction::{AccountMeta, Instruction},
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_program, sysvar,
+};
+use train_htlc_core::{add_lock_sig_message, derive_commit_id, keccak256, sha256, HtlcError};
+use train_htlc_program_tests::*;
+
+const AMOUNT: u64 = LAMPORTS_PER_SOL / 10;
+const REWARD: u64 = LAMPORTS_PER_SOL / 100;
+const KEEPER_BOUNTY: u64 = LAMPORTS_PER_SOL / 1000;
+const SECRET: [u8; 32] = [42u8; 32];
+
+fn entry<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+    data: &[u8],
+) -> ProgramResult {
+    let accounts: &'b [AccountInfo<'b>] = Box::leak(Box::new(accounts.to_vec()));
+    sol::entry(program_id, accounts, data)
+}
+
+fn hashlock() -> [u8; 32] {
+    sha256(&SECRET)
+}
+
+fn htlc_pda(sender: &Pubkey, Id: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"htlc", sender.as_ref(), Id], &sol::ID)
+}
+
+fn legacy_htlc_pda(Id: &[u8; 32]) -> Pubkey {
+    Pubkey::find_program_address(&[Id], &sol::ID).0
+}
+
+fn contracts_index_pda(sender: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"contracts_index", sender.as_ref()], &sol::ID).0
+}
+
+fn contracts_page_pda(sender: &Pubkey, page: u32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"contracts_page", sender.as_ref(), &page.to_le_bytes()],
+        &sol::ID,
+    )
+    .0
+}
+
+fn nonce_pda(sender: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"nonce", sender.as_ref()], &sol::ID).0
+}
+
+fn config_pda() -> Pubkey {
+    Pubkey::find_program_address(&[b"config"], &sol::ID).0
+}
+
+fn program_data_pda() -> Pubkey {
+    Pubkey::find_program_address(&[sol::ID.as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+fn fee_vault_pda() -> Pubkey {
+    Pubkey::find_program_address(&[b"fee_vault"], &sol::ID).0
+}
+
+struct Env {
+    ctx: ProgramTestContext,
+    admin: Keypair,
+    sender: Keypair,
+    src_receiver: Keypair,
+}
+
+/// An Env whose Config is not created yet, with `admin` as the program's
+/// upgrade authority.
+async fn setup_without_config() -> Env {
+    let program_test = ProgramTest::new("sol", sol::ID, processor!(entry));
+    let mut ctx = program_test.start_with_context().await;
+    let sender = funded_keypair(&mut ctx, 10 * LAMPORTS_PER_SOL).await;
+    let src_receiver = funded_keypair(&mut ctx, LAMPORTS_PER_SOL).await;
+    let admin = funded_keypair(&mut ctx, LAMPORTS_PER_SOL).await;
+    set_program_data(&mut ctx, &sol::ID, admin.pubkey()).await;
+    Env {
+        ctx,
+        admin,
+        sender,
+        src_receiver,
+    }
+}
+
+async fn setup() -> Env {
+    let mut env = setup_without_config().await;
+    let admin = env.admin.insecure_clone();
+    env.initialize_config(&admin).await.assert_ok();
+    env
+}
+
+impl Env {
+    async fn initialize_config(&mut self, admin: &Keypair) -> Outcome {
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::InitializeConfig {
+                admin: admin.pubkey(),
+                program_data: program_data_pda(),
+                config: config_pda(),
+                fee_vault: fee_vault_pda(),
+                system_program: system_program::ID,
+            },
+            instruction::InitializeConfig {},
+        );
+        process(&mut self.ctx, &[ix], &[admin]).await
+    }
+
+    async fn transfer_admin(&mut self, admin: &Keypair, new_admin: Pubkey) -> Outcome {
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::ConfigAdmin {
+                admin: admin.pubkey(),
+                config: config_pda(),
+            },
+            instruction::TransferAdmin { new_admin },
+        );
+        process(&mut self.ctx, &[ix], &[admin]).await
+    }
+
+    async fn accept_admin(&mut self, pending_admin: &Keypair) -> Outcome {
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::AcceptAdmin {
+                pending_admin: pending_admin.pubkey(),
+                config: config_pda(),
+            },
+            instruction::AcceptAdmin {},
+        );
+        process(&mut self.ctx, &[ix], &[pending_admin]).await
+    }
+
+    async fn set_paused(&mut self, admin: &Keypair, paused: bool) -> Outcome {
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::ConfigAdmin {
+                admin: admin.pubkey(),
+                config: config_pda(),
+            },
+            instruction::SetPaused { paused },
+        );
+        process(&mut self.ctx, &[ix], &[admin]).await
+    }
+
+    async fn set_fee(&mut self, admin: &Keypair, fee_bps: u16) -> Outcome {
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::ConfigAdmin {
+                admin: admin.pubkey(),
+                config: config_pda(),
+            },
+            instruction::SetFee { fee_bps },
+        );
+        process(&mut self.ctx, &[ix], &[admin]).await
+    }
+
+    async fn withdraw_fees(&mut self, admin: &Keypair, recipient: Pubkey) -> Outcome {
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::WithdrawFees {
+                admin: admin.pubkey(),
+                config: config_pda(),
+                fee_vault: fee_vault_pda(),
+                recipient,
+            },
+            instruction::WithdrawFees {},
+        );
+        process(&mut self.ctx, &[ix], &[admin]).await
+    }
+
+    async fn set_timelock_window(
+        &mut self,
+        admin: &Keypair,
+        min_window: u64,
+        max_window: u64,
+    ) -> Outcome {
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::ConfigAdmin {
+                admin: admin.pubkey(),
+                config: config_pda(),
+            },
+            instruction::SetTimelockWindow {
+                min_window,
+                max_window,
+            },
+        );
+        process(&mut self.ctx, &[ix], &[admin]).await
+    }
+
+    async fn config(&mut self) -> Config {
+        anchor_account(&mut self.ctx, config_pda()).await.unwrap()
+    }
+
+    async fn in_seconds(&mut self, seconds: i64) -> u64 {
+        (now(&mut self.ctx).await + seconds) as u64
+    }
+
+    async fn next_commit_id(&mut self, timelock: u64, amount: u64) -> [u8; 32] {
+        let nonce = anchor_account::<SenderNonce>(&mut self.ctx, nonce_pda(&self.sender.pubkey()))
+            .await
+            .map_or(0, |sender_nonce| sender_nonce.nonce);
+        derive_commit_id(
+            &sol::ID.to_bytes(),
+            &self.sender.pubkey().to_bytes(),
+            &self.src_receiver.pubkey().to_bytes(),
+            amount,
+            timelock,
+            nonce,
+        )
+    }
+
+    async fn current_page(&mut self) -> u32 {
+        anchor_account::<ContractsIndex>(&mut self.ctx, contracts_index_pda(&self.sender.pubkey()))
+            .await
+            .map_or(0, |index| index.current_page)
+    }
+
+    async fn commit_args(&mut self, timelock: u64, amount: u64) -> instruction::Commit {
+        let Id = self.next_commit_id(timelock, amount).await;
+        instruction::Commit {
+            Id,
+            hopChains: vec!["ETHEREUM".to_string()],
+            hopAssets: vec!["ETH".to_string()],
+            hopAddresses: vec!["0x1234".to_string()],
+            dst_chain: "ETHEREUM".to_string(),
+            dst_asset: "ETH".to_string(),
+            dst_address: "0x1234".to_string(),
+            src_asset: "SOL".to_string(),
+            src_receiver: self.src_receiver.pubkey(),
+            timelock,
+            amount,
+            hash_algorithm: HashAlgorithm::Sha256,
+            keeper_bounty: 0,
+            commit_bump: htlc_pda(&self.sender.pubkey(), &Id).1,
+        }
+    }
+
+    async fn commit_with(&mut self, args: instruction::Commit) -> Outcome {
+        let sender = self.sender.pubkey();
+        let page = self.current_page().await;
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::Commit {
+                sender,
+                config: config_pda(),
+                htlc: htlc_pda(&sender, &args.Id).0,
+                contracts_index: contracts_index_pda(&sender),
+                contracts_page: contracts_page_pda(&sender, page),
+                sender_nonce: nonce_pda(&sender),
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            },
+            args,
+        );
+        process(&mut self.ctx, &[ix], &[&self.sender]).await
+    }
+
+    async fn commit(&mut self, timelock: u64) -> [u8; 32] {
+        let args = self.commit_args(timelock, AMOUNT).await;
+        let Id = args.Id;
+        self.commit_with(args).await.assert_ok();
+        Id
+    }
+
+    fn lock_args(&self, Id: [u8; 32], timelock: u64) -> instruction::Lock {
+        instruction::Lock {
+            Id,
+            hashlock: hashlock(),
+            timelock,
+            amount: AMOUNT,
+            reward: 0,
+            reward_timelock: 0,
+            dst_chain: "ETHEREUM".to_string(),
+            dst_address: "0x1234".to_string(),
+            dst_asset: "ETH".to_string(),
+            src_asset: "SOL".to_string(),
+            src_receiver: self.src_receiver.pubkey(),
+            hash_algorithm: HashAlgorithm::Sha256,
+            keeper_bounty: 0,
+            lock_bump: htlc_pda(&self.sender.pubkey(), &Id).1,
+        }
+    }
+
+    async fn lock_with(&mut self, args: instruction::Lock) -> Outcome {
+        let sender = self.sender.pubkey();
+        let page = self.current_page().await;
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::Lock {
+                sender,
+                config: config_pda(),
+                htlc: htlc_pda(&sender, &args.Id).0,
+                contracts_index: contracts_index_pda(&sender),
+                contracts_page: contracts_page_pda(&sender, page),
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            },
+            args,
+        );
+        process(&mut self.ctx, &[ix], &[&self.sender]).await
+    }
+
+    async fn add_lock(&mut self, Id: [u8; 32], hashlock: [u8; 32], timelock: u64) -> Outcome {
+        self.add_lock_with(Id, hashlock, timelock, HashAlgorithm::Sha256)
+            .await
+    }
+
+    async fn add_lock_with(
+        &mut self,
+        Id: [u8; 32],
+        hashlock: [u8; 32],
+        timelock: u64,
+        hash_algorithm: HashAlgorithm,
+    ) -> Outcome {
+        let sender = self.sender.pubkey();
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::AddLock {
+                sender,
+                payer: sender,
+                htlc: htlc_pda(&sender, &Id).0,
+                config: config_pda(),
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::AddLock {
+                Id,
+                hashlock,
+                timelock,
+                hash_algorithm,
+            },
+        );
+        process(&mut self.ctx, &[ix], &[&self.sender]).await
+    }
+
+    async fn extend_timelock(&mut self, Id: [u8; 32], timelock: u64) -> Outcome {
+        let sender = self.sender.insecure_clone();
+        self.extend_timelock_by(Id, timelock, &sender).await
+    }
+
+    async fn extend_timelock_by(
+        &mut self,
+        Id: [u8; 32],
+        timelock: u64,
+        sender: &Keypair,
+    ) -> Outcome {
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::ExtendTimelock {
+                sender: sender.pubkey(),
+                htlc: htlc_pda(&self.sender.pubkey(), &Id).0,
+                config: config_pda(),
+            },
+            instruction::ExtendTimelock { Id, timelock },
+        );
+        process(&mut self.ctx, &[ix], &[sender]).await
+    }
+
+    async fn add_lock_sig_ixs(
+        &mut self,
+        signer: &Keypair,
+        Id: [u8; 32],
+        timelock: u64,
+        hash_algorithm: HashAlgorithm,
+    ) -> Vec<Instruction> {
+        let message = add_lock_sig_message(
+            &sol::ID.to_bytes(),
+            &Id,
+            &hashlock(),
+            timelock,
+            hash_algorithm.into(),
+        );
+        let signature = signer.sign_message(&message);
+        vec![
+            ed25519_verify_ix(signer, &message),
+            anchor_ix(
+                sol::ID,
+                accounts::AddLockSig {
+                    payer: self.ctx.payer.pubkey(),
+                    htlc: htlc_pda(&self.sender.pubkey(), &Id).0,
+                    config: config_pda(),
+                    ix_sysvar: sysvar::instructions::ID,
+                },
+                instruction::AddLockSig {
+                    Id,
+                    hashlock: hashlock(),
+                    timelock,
+                    hash_algorithm,
+                    signature: signature.as_ref().try_into().unwrap(),
+                },
+            ),
+        ]
+    }
+
+    async fn redeem_accounts(&mut self, Id: [u8; 32], user_signing: Pubkey) -> accounts::Redeem {
+        let sender = self.sender.pubkey();
+        let htlc = htlc_pda(&sender, &Id).0;
+        let page = anchor_account::<HTLC>(&mut self.ctx, htlc)
+            .await
+            .map_or(0, |htlc| htlc.index_page);
+        accounts::Redeem {
+            user_signing,
+            fee_vault: fee_vault_pda(),
+            htlc,
+            contracts_page: contracts_page_pda(&sender, page),
+            sender,
+            src_receiver: self.src_receiver.pubkey(),
+            payer: sender,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        }
+    }
+
+    async fn redeem_by(
+        &mut self,
+        Id: [u8; 32],
+        secret: [u8; 32],
+        user_signing: &Keypair,
+    ) -> Outcome {
+        let accounts = self.redeem_accounts(Id, user_signing.pubkey()).await;
+        let ix = anchor_ix(sol::ID, accounts, instruction::Redeem { Id, secret });
+        process(&mut self.ctx, &[ix], &[user_signing]).await
+    }
+
+    async fn redeem(&mut self, Id: [u8; 32], secret: [u8; 32]) -> Outcome {
+        let user_signing = self.src_receiver.insecure_clone();
+        self.redeem_by(Id, secret, &user_signing).await
+    }
+
+    async fn redeem_batch_ix(
+        &mut self,
+        items: &[([u8; 32], [u8; 32])],
+        user_signing: Pubkey,
+    ) -> Instruction {
+        let mut ix = anchor_ix(
+            sol::ID,
+            accounts::RedeemBatch {
+                user_signing,
+                fee_vault: fee_vault_pda(),
+            },
+            instruction::RedeemBatch {
+                items: items
+                    .iter()
+                    .map(|&(Id, secret)| RedeemBatchItem { Id, secret })
+                    .collect(),
+            },
+        );
+        for &(Id, _) in items {
+            let accounts = self.redeem_accounts(Id, user_signing).await;
+            ix.accounts.extend(
+                [
+                    accounts.htlc,
+                    accounts.contracts_page,
+                    accounts.sender,
+                    accounts.src_receiver,
+                    accounts.payer,
+                ]
+                .map(|address| AccountMeta::new(address, false)),
+            );
+        }
+        ix
+    }
+
+    async fn redeem_batch(&mut self, items: &[([u8; 32], [u8; 32])]) -> Outcome {
+        let src_receiver = self.src_receiver.insecure_clone();
+        let ix = self.redeem_batch_ix(items, src_receiver.pubkey()).await;
+        process(&mut self.ctx, &[ix], &[&src_receiver]).await
+    }
+
+    async fn refund_accounts(&mut self, Id: [u8; 32]) -> accounts::Refund {
+        let sender = self.sender.pubkey();
+        let htlc = htlc_pda(&sender, &Id).0;
+        let page = anchor_account::<HTLC>(&mut self.ctx, htlc)
+            .await
+            .map_or(0, |htlc| htlc.index_page);
+        accounts::Refund {
+            user_signing: sender,
+            htlc,
+            contracts_page: contracts_page_pda(&sender, page),
+            sender,
+            payer: sender,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        }
+    }
+
+    async fn refund_by(&mut self, Id: [u8; 32], user_signing: &Keypair) -> Outcome {
+        let mut accounts = self.refund_accounts(Id).await;
+        accounts.user_signing = user_signing.pubkey();
+        let ix = anchor_ix(sol::ID, accounts, instruction::Refund { Id });
+        process(&mut self.ctx, &[ix], &[user_signing]).await
+    }
+
+    async fn refund(&mut self, Id: [u8; 32]) -> Outcome {
+        let sender = self.sender.insecure_clone();
+        self.refund_by(Id, &sender).await
+    }
+
+    async fn refund_batch_ix(&mut self, Ids: &[[u8; 32]], user_signing: Pubkey) -> Instruction {
+        let sender = self.sender.pubkey();
+        let mut ix = anchor_ix(
+            sol::ID,
+            accounts::RefundBatch {
+                user_signing,
+                sender,
+            },
+            instruction::RefundBatch { Ids: Ids.to_vec() },
+        );
+        for &Id in Ids {
+            let accounts = self.refund_accounts(Id).await;
+            ix.accounts.extend(
+                [accounts.htlc, accounts.contracts_page, accounts.payer]
+                    .map(|address| AccountMeta::new(address, false)),
+            );
+        }
+        ix
+    }
+
+    async fn refund_batch(&mut self, Ids: &[[u8; 32]]) -> Outcome {
+        let ix = self.refund_batch_ix(Ids, self.sender.pubkey()).await;
+        process(&mut self.ctx, &[ix], &[&self.sender]).await
+    }
+
+    async fn contracts(&mut self, page: u32) -> Vec<[u8; 32]> {
+        anchor_account::<ContractsPage>(
+            &mut self.ctx,
+            contracts_page_pda(&self.sender.pubkey(), page),
+        )
+        .await
+        .map_or_else(Vec::new, |page| page.ids)
+    }
+
+    /// Writes an HTLC at the pre-namespacing [Id] address, as the old program left it.
+    async fn legacy_htlc(&mut self, Id: [u8; 32], timelock: u64, redeemed: bool, refunded: bool) {
+        let legacy = LegacyHTLC {
+            dst_address: "0x1234".to_string(),
+            dst_chain: "ETHEREUM".to_string(),
+            dst_asset: "ETH".to_string(),
+            src_asset: "SOL".to_string(),
+            sender: self.sender.pubkey(),
+            src_receiver: self.src_receiver.pubkey(),
+            hashlock: hashlock(),
+            secret: [0u8; 32],
+            amount: AMOUNT,
+            timelock,
+            redeemed,
+            refunded,
+        };
+        let mut data = HTLC::DISCRIMINATOR.to_vec();
+        data.extend(legacy.try_to_vec().unwrap());
+        set_account_data(&mut self.ctx, legacy_htlc_pda(&Id), sol::ID, data, AMOUNT).await;
+    }
+
+    async fn redeem_legacy(&mut self, Id: [u8; 32], secret: [u8; 32]) -> Outcome {
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::RedeemLegacy {
+                user_signing: self.src_receiver.pubkey(),
+                htlc: legacy_htlc_pda(&Id),
+                sender: self.sender.pubkey(),
+                src_receiver: self.src_receiver.pubkey(),
+                system_program: system_program::ID,
+            },
+            instruction::RedeemLegacy { Id, secret },
+        );
+        let src_receiver = self.src_receiver.insecure_clone();
+        process(&mut self.ctx, &[ix], &[&src_receiver]).await
+    }
+
+    async fn refund_legacy(&mut self, Id: [u8; 32], sender: Pubkey) -> Outcome {
+        let ix = anchor_ix(
+            sol::ID,
+            accounts::RefundLegacy {
+                user_signing: self.src_receiver.pubkey(),
+                htlc: legacy_htlc_pda(&Id),
+                sender,
+                system_program: system_program::ID,
+            },
+            instruction::RefundLegacy { Id },
+        );
+        let src_receiver = self.src_receiver.insecure_clone();
+        process(&mut self.ctx, &[ix], &[&src_receiver]).await
+    }
+}
+
+#[tokio::test]
+async fn get_commit_id_follows_the_sender_nonce() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let get_commit_id = |env: &Env| {
+        anchor_ix(
+            sol::ID,
+            accounts::GetCommitId {
+                sender: env.sender.pubkey(),
+                receiver: env.src_receiver.pubkey(),
+                sender_nonce: nonce_pda(&env.sender.pubkey()),
+            },
+            instruction::GetCommitId {
+                amount: AMOUNT,
+                timelock,
+            },
+        )
+    };
+
+    let ix = get_commit_id(&env);
+    let outcome = process(&mut env.ctx, &[ix], &[]).await;
+    outcome.assert_ok();
+    let first = env.next_commit_id(timelock, AMOUNT).await;
+    assert_eq!(outcome.return_data, first);
+
+    env.commit(timelock).await;
+    next_blockhash(&mut env.ctx).await;
+    let ix = get_commit_id(&env);
+    let outcome = process(&mut env.ctx, &[ix], &[]).await;
+    outcome.assert_ok();
+    assert_ne!(outcome.return_data, first);
+    assert_eq!(
+        outcome.return_data,
+        env.next_commit_id(timelock, AMOUNT).await
+    );
+}
+
+#[tokio::test]
+async fn commit_escrows_the_amount_and_indexes_the_htlc() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let sender_before = balance(&mut env.ctx, env.sender.pubkey()).await;
+
+    let Id = env.commit(timelock).await;
+
+    let htlc_address = htlc_pda(&env.sender.pubkey(), &Id).0;
+    let htlc: HTLC = anchor_account(&mut env.ctx, htlc_address).await.unwrap();
+    assert_eq!(htlc.sender, env.sender.pubkey());
+    assert_eq!(htlc.src_receiver, env.src_receiver.pubkey());
+    assert_eq!(htlc.payer, env.sender.pubkey());
+    assert_eq!(htlc.hashlock, [0u8; 32]);
+    assert_eq!(htlc.amount, AMOUNT);
+    assert_eq!(htlc.timelock, timelock);
+    assert_eq!(htlc.hop_chains, vec!["ETHEREUM".to_string()]);
+    assert_eq!(htlc.status, HTLCStatus::Committed);
+
+    let rent = env.ctx.banks_client.get_rent().await.unwrap();
+    let htlc_account = env
+        .ctx
+        .banks_client
+        .get_account(htlc_address)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        htlc_account.lamports,
+        rent.minimum_balance(htlc_account.data.len()) + AMOUNT
+    );
+    assert!(balance(&mut env.ctx, env.sender.pubkey()).await < sender_before - AMOUNT);
+    assert_eq!(env.contracts(0).await, vec![Id]);
+
+    let ix = anchor_ix(
+        sol::ID,
+        accounts::GetDetails { htlc: htlc_address },
+        instruction::GetDetails { Id },
+    );
+    let outcome = process(&mut env.ctx, &[ix], &[]).await;
+    outcome.assert_ok();
+    let details = HTLC::deserialize(&mut &outcome.return_data[..]).unwrap();
+    assert_eq!(details.amount, AMOUNT);
+    assert_eq!(details.hop_addresses, vec!["0x1234".to_string()]);
+
+    let ix = anchor_ix(
+        sol::ID,
+        accounts::GetContracts {
+            contracts_page: contracts_page_pda(&env.sender.pubkey(), 0),
+        },
+        instruction::GetContracts {
+            _sender: env.sender.pubkey(),
+            _page: 0,
+        },
+    );
+    let outcome = process(&mut env.ctx, &[ix], &[]).await;
+    outcome.assert_ok();
+    assert_eq!(
+        Vec::<[u8; 32]>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        vec![Id]
+    );
+}
+
+#[tokio::test]
+async fn commit_rejects_a_past_timelock() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(0).await;
+
+    let args = env.commit_args(timelock, AMOUNT).await;
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::NotFutureTimeLock);
+}
+
+#[tokio::test]
+async fn commit_rejects_a_zero_amount() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+
+    let args = env.commit_args(timelock, 0).await;
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::FundsNotSent);
+}
+
+#[tokio::test]
+async fn commit_rejects_an_id_not_derived_from_the_nonce() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+
+    let mut args = env.commit_args(timelock, AMOUNT).await;
+    args.Id = [7u8; 32];
+    args.commit_bump = htlc_pda(&env.sender.pubkey(), &args.Id).1;
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::InvalidCommitId);
+
+    // The Id covers the terms, so it cannot be reused with another amount.
+    let mut args = env.commit_args(timelock, AMOUNT).await;
+    args.amount += 1;
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::InvalidCommitId);
+}
+
+#[tokio::test]
+async fn commit_rejects_bad_hop_routes() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+
+    let mut args = env.commit_args(timelock, AMOUNT).await;
+    args.hopAssets.push("USDC".to_string());
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::HopRouteMismatch);
+
+    let mut args = env.commit_args(timelock, AMOUNT).await;
+    args.hopChains = vec!["A".to_string(); train_htlc_core::MAX_HOPS + 1];
+    args.hopAssets = args.hopChains.clone();
+    args.hopAddresses = args.hopChains.clone();
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::TooManyHops);
+
+    let mut args = env.commit_args(timelock, AMOUNT).await;
+    args.hopAddresses = vec!["a".repeat(train_htlc_core::MAX_HOP_LEN + 1)];
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::HopTooLong);
+}
+
+#[tokio::test]
+async fn commit_rejects_strings_over_their_limits() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let too_long = |max: usize| "a".repeat(max + 1);
+
+    let mut args = env.commit_args(timelock, AMOUNT).await;
+    args.dst_chain = too_long(sol::MAX_CHAIN_LEN);
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::DstChainTooLong);
+
+    let mut args = env.commit_args(timelock, AMOUNT).await;
+    args.dst_asset = too_long(sol::MAX_ASSET_LEN);
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::DstAssetTooLong);
+
+    let mut args = env.commit_args(timelock, AMOUNT).await;
+    args.dst_address = too_long(sol::MAX_ADDRESS_LEN);
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::DstAddressTooLong);
+
+    let mut args = env.commit_args(timelock, AMOUNT).await;
+    args.src_asset = too_long(sol::MAX_ASSET_LEN);
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::SrcAssetTooLong);
+}
+
+#[tokio::test]
+async fn add_lock_then_redeem_pays_the_src_receiver_and_closes_the_htlc() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+    let htlc_address = htlc_pda(&env.sender.pubkey(), &Id).0;
+
+    env.add_lock(Id, hashlock(), timelock + 60)
+        .await
+        .assert_ok();
+    let htlc: HTLC = anchor_account(&mut env.ctx, htlc_address).await.unwrap();
+    assert_eq!(htlc.hashlock, hashlock());
+    assert_eq!(htlc.timelock, timelock + 60);
+    assert_eq!(htlc.status, HTLCStatus::Locked);
+
+    let htlc_lamports = balance(&mut env.ctx, htlc_address).await;
+    let sender_before = balance(&mut env.ctx, env.sender.pubkey()).await;
+    let receiver_before = balance(&mut env.ctx, env.src_receiver.pubkey()).await;
+    env.redeem(Id, SECRET).await.assert_ok();
+
+    assert!(!account_exists(&mut env.ctx, htlc_address).await);
+    assert_eq!(
+        balance(&mut env.ctx, env.src_receiver.pubkey()).await,
+        receiver_before + AMOUNT
+    );
+    assert_eq!(
+        balance(&mut env.ctx, env.sender.pubkey()).await,
+        sender_before + htlc_lamports - AMOUNT
+    );
+    assert!(env.contracts(0).await.is_empty());
+}
+
+#[tokio::test]
+async fn add_lock_rejects_a_second_hashlock_and_a_past_timelock() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+
+    let past = env.in_seconds(0).await;
+    env.add_lock(Id, hashlock(), past)
+        .await
+        .assert_htlc_error(HtlcError::NotFutureTimeLock);
+
+    env.add_lock(Id, hashlock(), timelock).await.assert_ok();
+    env.add_lock(Id, [1u8; 32], timelock)
+        .await
+        .assert_htlc_error(HtlcError::HashlockAlreadySet);
+
+    // An all-zero hashlock locks the HTLC like any other.
+    let Id = env.commit(timelock).await;
+    env.add_lock(Id, [0u8; 32], timelock).await.assert_ok();
+    env.add_lock(Id, hashlock(), timelock)
+        .await
+        .assert_htlc_error(HtlcError::HashlockAlreadySet);
+}
+
+#[tokio::test]
+async fn add_lock_by_another_sender_fails_on_the_seeds() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+    let intruder = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+
+    let ix = anchor_ix(
+        sol::ID,
+        accounts::AddLock {
+            sender: intruder.pubkey(),
+            payer: intruder.pubkey(),
+            htlc: htlc_pda(&env.sender.pubkey(), &Id).0,
+            config: config_pda(),
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::AddLock {
+            Id,
+            hashlock: hashlock(),
+            timelock,
+            hash_algorithm: HashAlgorithm::Sha256,
+        },
+    );
+    process(&mut env.ctx, &[ix], &[&intruder])
+        .await
+        .assert_anchor_error(ErrorCode::ConstraintSeeds);
+}
+
+#[tokio::test]
+async fn add_lock_sig_accepts_the_sender_signature_from_any_payer() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+    let sender = env.sender.insecure_clone();
+
+    let ixs = env
+        .add_lock_sig_ixs(&sender, Id, timelock, HashAlgorithm::Sha256)
+        .await;
+    process(&mut env.ctx, &ixs, &[]).await.assert_ok();
+
+    let htlc: HTLC = anchor_account(&mut env.ctx, htlc_pda(&sender.pubkey(), &Id).0)
+        .await
+        .unwrap();
+    assert_eq!(htlc.hashlock, hashlock());
+    env.redeem(Id, SECRET).await.assert_ok();
+}
+
+#[tokio::test]
+async fn add_lock_sig_rejects_other_signers_and_a_missing_verification() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+    let intruder = Keypair::new();
+
+    let ixs = env
+        .add_lock_sig_ixs(&intruder, Id, timelock, HashAlgorithm::Sha256)
+        .await;
+    process(&mut env.ctx, &ixs, &[])
+        .await
+        .assert_htlc_error(HtlcError::InvalidSignature);
+
+    let sender = env.sender.insecure_clone();
+    let ixs = env
+        .add_lock_sig_ixs(&sender, Id, timelock, HashAlgorithm::Sha256)
+        .await;
+    process(&mut env.ctx, &ixs[1..], &[])
+        .await
+        .assert_htlc_error(HtlcError::InvalidSignature);
+
+    // A signature over another timelock does not cover this one.
+    let mut ixs = env
+        .add_lock_sig_ixs(&sender, Id, timelock, HashAlgorithm::Sha256)
+        .await;
+    ixs[1] = env
+        .add_lock_sig_ixs(&sender, Id, timelock + 1, HashAlgorithm::Sha256)
+        .await
+        .remove(1);
+    process(&mut env.ctx, &ixs, &[])
+        .await
+        .assert_htlc_error(HtlcError::InvalidSignature);
+
+    // Nor over another hash algorithm.
+    let mut ixs = env
+        .add_lock_sig_ixs(&sender, Id, timelock, HashAlgorithm::Sha256)
+        .await;
+    ixs[1] = env
+        .add_lock_sig_ixs(&sender, Id, timelock, HashAlgorithm::Keccak256)
+        .await
+        .remove(1);
+    process(&mut env.ctx, &ixs, &[])
+        .await
+        .assert_htlc_error(HtlcError::InvalidSignature);
+}
+
+#[tokio::test]
+async fn redeem_rejects_a_wrong_secret_and_an_unlocked_commit() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+
+    env.redeem(Id, SECRET)
+        .await
+        .assert_htlc_error(HtlcError::HashlockNotSet);
+
+    env.add_lock(Id, hashlock(), timelock).await.assert_ok();
+    env.redeem(Id, [0u8; 32])
+        .await
+        .assert_htlc_error(HtlcError::HashlockNoMatch);
+}
+
+#[tokio::test]
+async fn redeem_hashes_the_secret_with_the_htlc_hash_algorithm() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+
+    let mut args = env.lock_args([1u8; 32], timelock);
+    args.hashlock = keccak256(&SECRET);
+    args.hash_algorithm = HashAlgorithm::Keccak256;
+    env.lock_with(args).await.assert_ok();
+    let htlc: HTLC = anchor_account(&mut env.ctx, htlc_pda(&env.sender.pubkey(), &[1u8; 32]).0)
+        .await
+        .unwrap();
+    assert_eq!(htlc.hash_algorithm, HashAlgorithm::Keccak256);
+    env.redeem([1u8; 32], SECRET).await.assert_ok();
+
+    // A sha256 hashlock tagged Keccak256 does not match its own secret.
+    let mut args = env.lock_args([2u8; 32], timelock);
+    args.hash_algorithm = HashAlgorithm::Keccak256;
+    env.lock_with(args).await.assert_ok();
+    env.redeem([2u8; 32], SECRET)
+        .await
+        .assert_htlc_error(HtlcError::HashlockNoMatch);
+
+    // add_lock sets the algorithm of a committed HTLC.
+    let Id = env.commit(timelock).await;
+    env.add_lock_with(Id, keccak256(&SECRET), timelock, HashAlgorithm::Keccak256)
+        .await
+        .assert_ok();
+    env.redeem(Id, SECRET).await.assert_ok();
+}
+
+#[tokio::test]
+async fn redeem_twice_fails_on_the_closed_htlc() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+    env.add_lock(Id, hashlock(), timelock).await.assert_ok();
+
+    env.redeem(Id, SECRET).await.assert_ok();
+    next_blockhash(&mut env.ctx).await;
+    env.redeem(Id, SECRET)
+        .await
+        .assert_anchor_error(ErrorCode::AccountNotInitialized);
+}
+
+#[tokio::test]
+async fn redeem_rejects_the_wrong_receiver_payer_and_sender() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+    env.add_lock(Id, hashlock(), timelock).await.assert_ok();
+    let src_receiver = env.src_receiver.insecure_clone();
+    let other = Keypair::new().pubkey();
+
+    let mut accounts = env.redeem_accounts(Id, src_receiver.pubkey()).await;
+    accounts.src_receiver = other;
+    let ix = anchor_ix(
+        sol::ID,
+        accounts,
+        instruction::Redeem { Id, secret: SECRET },
+    );
+    process(&mut env.ctx, &[ix], &[&src_receiver])
+        .await
+        .assert_htlc_error(HtlcError::NotReciever);
+
+    let mut accounts = env.redeem_accounts(Id, src_receiver.pubkey()).await;
+    accounts.payer = other;
+    let ix = anchor_ix(
+        sol::ID,
+        accounts,
+        instruction::Redeem { Id, secret: SECRET },
+    );
+    process(&mut env.ctx, &[ix], &[&src_receiver])
+        .await
+        .assert_htlc_error(HtlcError::NotPayer);
+
+    let mut accounts = env.redeem_accounts(Id, src_receiver.pubkey()).await;
+    accounts.sender = other;
+    let ix = anchor_ix(
+        sol::ID,
+        accounts,
+        instruction::Redeem { Id, secret: SECRET },
+    );
+    process(&mut env.ctx, &[ix], &[&src_receiver])
+        .await
+        .assert_anchor_error(ErrorCode::ConstraintSeeds);
+}
+
+#[tokio::test]
+async fn redeem_batch_redeems_each_item_and_skips_the_failing_ones() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    for Id in [[1u8; 32], [2u8; 32], [3u8; 32]] {
+        let args = env.lock_args(Id, timelock);
+        env.lock_with(args).await.assert_ok();
+    }
+    let committed = env.commit(timelock).await;
+    let receiver_before = balance(&mut env.ctx, env.src_receiver.pubkey()).await;
+
+    let outcome = env
+        .redeem_batch(&[
+            ([1u8; 32], SECRET),
+            ([2u8; 32], [0u8; 32]),
+            ([3u8; 32], SECRET),
+            (committed, SECRET),
+            ([1u8; 32], SECRET),
+        ])
+        .await;
+    outcome.assert_ok();
+    assert_eq!(
+        Vec::<u32>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        [
+            0,
+            HtlcError::HashlockNoMatch.code(),
+            0,
+            HtlcError::HashlockNotSet.code(),
+            u32::from(ErrorCode::AccountNotInitialized),
+        ]
+    );
+
+    assert_eq!(
+        balance(&mut env.ctx, env.src_receiver.pubkey()).await,
+        receiver_before + 2 * AMOUNT
+    );
+    for (Id, open) in [([1u8; 32], false), ([2u8; 32], true), ([3u8; 32], false)] {
+        let htlc_address = htlc_pda(&env.sender.pubkey(), &Id).0;
+        assert_eq!(account_exists(&mut env.ctx, htlc_address).await, open);
+    }
+    assert_eq!(env.contracts(0).await, [committed, [2u8; 32]]);
+}
+
+#[tokio::test]
+async fn redeem_batch_rejects_oversized_batches_and_missing_accounts() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let args = env.lock_args([1u8; 32], timelock);
+    env.lock_with(args).await.assert_ok();
+    let src_receiver = env.src_receiver.insecure_clone();
+
+    env.redeem_batch(&vec![([1u8; 32], SECRET); MAX_BATCH_LEN + 1])
+        .await
+        .assert_htlc_error(HtlcError::BatchTooLarge);
+
+    let mut ix = env
+        .redeem_batch_ix(&[([1u8; 32], SECRET)], src_receiver.pubkey())
+        .await;
+    ix.accounts.pop();
+    process(&mut env.ctx, &[ix], &[&src_receiver])
+        .await
+        .assert_htlc_error(HtlcError::BatchAccountsMismatch);
+
+    let mut ix = env
+        .redeem_batch_ix(&[([1u8; 32], SECRET)], src_receiver.pubkey())
+        .await;
+    ix.accounts[5].pubkey = Keypair::new().pubkey();
+    let outcome = process(&mut env.ctx, &[ix], &[&src_receiver]).await;
+    outcome.assert_ok();
+    assert_eq!(
+        Vec::<u32>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        [HtlcError::NotReciever.code()]
+    );
+}
+
+#[tokio::test]
+async fn lock_rejects_bad_terms() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+
+    let past = env.in_seconds(0).await;
+    let args = env.lock_args([1u8; 32], past);
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::NotFutureTimeLock);
+
+    let mut args = env.lock_args([2u8; 32], timelock);
+    args.amount = 0;
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::FundsNotSent);
+
+    let mut args = env.lock_args([3u8; 32], timelock);
+    args.reward = REWARD;
+    args.reward_timelock = timelock + 1;
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::InvalidRewardTimelock);
+
+    let mut args = env.lock_args([4u8; 32], timelock);
+    args.reward = REWARD;
+    args.reward_timelock = past;
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::InvalidRewardTimelock);
+}
+
+#[tokio::test]
+async fn lock_reward_goes_back_to_the_sender_before_the_reward_timelock() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let mut args = env.lock_args([1u8; 32], timelock);
+    args.reward = REWARD;
+    args.reward_timelock = timelock - 1800;
+    env.lock_with(args).await.assert_ok();
+    let htlc: HTLC = anchor_account(&mut env.ctx, htlc_pda(&env.sender.pubkey(), &[1u8; 32]).0)
+        .await
+        .unwrap();
+    assert_eq!(htlc.status, HTLCStatus::Locked);
+    let htlc_lamports = balance(&mut env.ctx, htlc_pda(&env.sender.pubkey(), &[1u8; 32]).0).await;
+
+    let sender_before = balance(&mut env.ctx, env.sender.pubkey()).await;
+    let receiver_before = balance(&mut env.ctx, env.src_receiver.pubkey()).await;
+    env.redeem([1u8; 32], SECRET).await.assert_ok();
+
+    assert_eq!(
+        balance(&mut env.ctx, env.src_receiver.pubkey()).await,
+        receiver_before + AMOUNT
+    );
+    assert_eq!(
+        balance(&mut env.ctx, env.sender.pubkey()).await,
+        sender_before + htlc_lamports - AMOUNT
+    );
+}
+
+#[tokio::test]
+async fn lock_reward_goes_to_the_redeemer_after_the_reward_timelock() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let reward_timelock = timelock - 1800;
+    for Id in [[1u8; 32], [2u8; 32]] {
+        let mut args = env.lock_args(Id, timelock);
+        args.reward = REWARD;
+        args.reward_timelock = reward_timelock;
+        env.lock_with(args).await.assert_ok();
+    }
+    warp_to(&mut env.ctx, reward_timelock as i64).await;
+
+    let solver = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+    let solver_before = balance(&mut env.ctx, solver.pubkey()).await;
+    let receiver_before = balance(&mut env.ctx, env.src_receiver.pubkey()).await;
+    env.redeem_by([1u8; 32], SECRET, &solver).await.assert_ok();
+    assert_eq!(
+        balance(&mut env.ctx, env.src_receiver.pubkey()).await,
+        receiver_before + AMOUNT
+    );
+    assert_eq!(
+        balance(&mut env.ctx, solver.pubkey()).await,
+        solver_before + REWARD
+    );
+
+    let receiver_before = balance(&mut env.ctx, env.src_receiver.pubkey()).await;
+    env.redeem([2u8; 32], SECRET).await.assert_ok();
+    assert_eq!(
+        balance(&mut env.ctx, env.src_receiver.pubkey()).await,
+        receiver_before + AMOUNT + REWARD
+    );
+}
+
+#[tokio::test]
+async fn refund_waits_for_the_timelock_and_returns_everything() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let mut args = env.lock_args([1u8; 32], timelock);
+    args.reward = REWARD;
+    args.reward_timelock = timelock;
+    env.lock_with(args).await.assert_ok();
+    let Id = [1u8; 32];
+    let htlc_address = htlc_pda(&env.sender.pubkey(), &Id).0;
+
+    env.refund(Id)
+        .await
+        .assert_htlc_error(HtlcError::NotPastTimeLock);
+
+    warp_to(&mut env.ctx, timelock as i64).await;
+    let htlc_lamports = balance(&mut env.ctx, htlc_address).await;
+    let sender_before = balance(&mut env.ctx, env.sender.pubkey()).await;
+    next_blockhash(&mut env.ctx).await;
+    env.refund(Id).await.assert_ok();
+
+    assert!(!account_exists(&mut env.ctx, htlc_address).await);
+    assert_eq!(
+        balance(&mut env.ctx, env.sender.pubkey()).await,
+        sender_before + htlc_lamports
+    );
+    assert!(env.contracts(0).await.is_empty());
+
+    next_blockhash(&mut env.ctx).await;
+    env.refund(Id)
+        .await
+        .assert_anchor_error(ErrorCode::AccountNotInitialized);
+}
+
+#[tokio::test]
+async fn refund_batch_refunds_expired_htlcs_and_skips_the_rest() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let args = env.lock_args([1u8; 32], timelock);
+    env.lock_with(args).await.assert_ok();
+    let args = env.lock_args([2u8; 32], timelock + 3600);
+    env.lock_with(args).await.assert_ok();
+    let committed = env.commit(timelock).await;
+    warp_to(&mut env.ctx, timelock as i64).await;
+
+    let mut refunded_lamports = 0;
+    for Id in [[1u8; 32], committed] {
+        refunded_lamports += balance(&mut env.ctx, htlc_pda(&env.sender.pubkey(), &Id).0).await;
+    }
+    let sender_before = balance(&mut env.ctx, env.sender.pubkey()).await;
+    let outcome = env
+        .refund_batch(&[[1u8; 32], [2u8; 32], committed, [1u8; 32]])
+        .await;
+    outcome.assert_ok();
+    assert_eq!(
+        Vec::<u32>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        [
+            0,
+            HtlcError::NotPastTimeLock.code(),
+            0,
+            u32::from(ErrorCode::AccountNotInitialized),
+        ]
+    );
+
+    assert_eq!(
+        balance(&mut env.ctx, env.sender.pubkey()).await,
+        sender_before + refunded_lamports
+    );
+    assert!(account_exists(&mut env.ctx, htlc_pda(&env.sender.pubkey(), &[2u8; 32]).0).await);
+    assert_eq!(env.contracts(0).await, [[2u8; 32]]);
+
+    let mut ix = env.refund_batch_ix(&[[2u8; 32]], env.sender.pubkey()).await;
+    ix.accounts.pop();
+    let sender = env.sender.insecure_clone();
+    process(&mut env.ctx, &[ix], &[&sender])
+        .await
+        .assert_htlc_error(HtlcError::BatchAccountsMismatch);
+}
+
+#[tokio::test]
+async fn refund_by_a_keeper_returns_the_funds_to_the_sender_and_pays_the_bounty() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let mut args = env.commit_args(timelock, AMOUNT).await;
+    args.keeper_bounty = KEEPER_BOUNTY;
+    let committed = args.Id;
+    env.commit_with(args).await.assert_ok();
+    for Id in [[1u8; 32], [2u8; 32]] {
+        let mut args = env.lock_args(Id, timelock);
+        args.keeper_bounty = KEEPER_BOUNTY;
+        env.lock_with(args).await.assert_ok();
+    }
+    let htlc: HTLC = anchor_account(&mut env.ctx, htlc_pda(&env.sender.pubkey(), &committed).0)
+        .await
+        .unwrap();
+    assert_eq!(htlc.keeper_bounty, KEEPER_BOUNTY);
+
+    let keeper = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+    env.refund_by(committed, &keeper)
+        .await
+        .assert_htlc_error(HtlcError::NotPastTimeLock);
+
+    warp_to(&mut env.ctx, timelock as i64).await;
+    let mut htlc_lamports = 0;
+    for Id in [committed, [1u8; 32], [2u8; 32]] {
+        htlc_lamports += balance(&mut env.ctx, htlc_pda(&env.sender.pubkey(), &Id).0).await;
+    }
+    let sender_before = balance(&mut env.ctx, env.sender.pubkey()).await;
+    let keeper_before = balance(&mut env.ctx, keeper.pubkey()).await;
+    next_blockhash(&mut env.ctx).await;
+    env.refund_by(committed, &keeper).await.assert_ok();
+    let ix = env
+        .refund_batch_ix(&[[1u8; 32], [2u8; 32]], keeper.pubkey())
+        .await;
+    let outcome = process(&mut env.ctx, &[ix], &[&keeper]).await;
+    outcome.assert_ok();
+    assert_eq!(
+        Vec::<u32>::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        [0, 0]
+    );
+
+    assert_eq!(
+        balance(&mut env.ctx, keeper.pubkey()).await,
+        keeper_before + 3 * KEEPER_BOUNTY
+    );
+    assert_eq!(
+        balance(&mut env.ctx, env.sender.pubkey()).await,
+        sender_before + htlc_lamports - 3 * KEEPER_BOUNTY
+    );
+    assert!(env.contracts(0).await.is_empty());
+}
+
+#[tokio::test]
+async fn redeem_returns_the_keeper_bounty_to_the_sender() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let mut args = env.lock_args([1u8; 32], timelock);
+    args.keeper_bounty = KEEPER_BOUNTY;
+    env.lock_with(args).await.assert_ok();
+    let htlc_lamports = balance(&mut env.ctx, htlc_pda(&env.sender.pubkey(), &[1u8; 32]).0).await;
+
+    let sender_before = balance(&mut env.ctx, env.sender.pubkey()).await;
+    let receiver_before = balance(&mut env.ctx, env.src_receiver.pubkey()).await;
+    env.redeem([1u8; 32], SECRET).await.assert_ok();
+
+    assert_eq!(
+        balance(&mut env.ctx, env.src_receiver.pubkey()).await,
+        receiver_before + AMOUNT
+    );
+    assert_eq!(
+        balance(&mut env.ctx, env.sender.pubkey()).await,
+        sender_before + htlc_lamports - AMOUNT
+    );
+}
+
+#[tokio::test]
+async fn refund_rejects_the_wrong_payer() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+    warp_to(&mut env.ctx, timelock as i64).await;
+
+    let mut accounts = env.refund_accounts(Id).await;
+    accounts.payer = Keypair::new().pubkey();
+    let ix = anchor_ix(sol::ID, accounts, instruction::Refund { Id });
+    let sender = env.sender.insecure_clone();
+    process(&mut env.ctx, &[ix], &[&sender])
+        .await
+        .assert_htlc_error(HtlcError::NotPayer);
+}
+
+#[tokio::test]
+async fn redeem_after_refund_fails_on_the_closed_htlc() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+    env.add_lock(Id, hashlock(), timelock).await.assert_ok();
+    warp_to(&mut env.ctx, timelock as i64).await;
+
+    env.refund(Id).await.assert_ok();
+    env.redeem(Id, SECRET)
+        .await
+        .assert_anchor_error(ErrorCode::AccountNotInitialized);
+}
+
+#[tokio::test]
+async fn legacy_htlcs_can_still_be_redeemed() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = [5u8; 32];
+    env.legacy_htlc(Id, timelock, false, false).await;
+
+    env.redeem_legacy(Id, [0u8; 32])
+        .await
+        .assert_htlc_error(HtlcError::HashlockNoMatch);
+
+    let htlc_lamports = balance(&mut env.ctx, legacy_htlc_pda(&Id)).await;
+    let sender_before = balance(&mut env.ctx, env.sender.pubkey()).await;
+    let receiver_before = balance(&mut env.ctx, env.src_receiver.pubkey()).await;
+    env.redeem_legacy(Id, SECRET).await.assert_ok();
+
+    assert!(!account_exists(&mut env.ctx, legacy_htlc_pda(&Id)).await);
+    // The src_receiver signs and pays the fee.
+    assert_eq!(
+        balance(&mut env.ctx, env.src_receiver.pubkey()).await,
+        receiver_before + AMOUNT
+    );
+    assert_eq!(
+        balance(&mut env.ctx, env.sender.pubkey()).await,
+        sender_before + htlc_lamports - AMOUNT
+    );
+}
+
+#[tokio::test]
+async fn legacy_htlcs_can_still_be_refunded() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = [6u8; 32];
+    env.legacy_htlc(Id, timelock, false, false).await;
+    let sender = env.sender.pubkey();
+
+    env.refund_legacy(Id, sender)
+        .await
+        .assert_htlc_error(HtlcError::NotPastTimeLock);
+    warp_to(&mut env.ctx, timelock as i64).await;
+    next_blockhash(&mut env.ctx).await;
+    env.refund_legacy(Id, Keypair::new().pubkey())
+        .await
+        .assert_htlc_error(HtlcError::NotSender);
+
+    let htlc_lamports = balance(&mut env.ctx, legacy_htlc_pda(&Id)).await;
+    let sender_before = balance(&mut env.ctx, sender).await;
+    env.refund_legacy(Id, sender).await.assert_ok();
+    assert_eq!(
+        balance(&mut env.ctx, sender).await,
+        sender_before + htlc_lamports
+    );
+}
+
+#[tokio::test]
+async fn legacy_htlcs_keep_their_redeemed_and_refunded_flags() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    env.legacy_htlc([7u8; 32], timelock, true, false).await;
+    env.legacy_htlc([8u8; 32], timelock, false, true).await;
+
+    env.redeem_legacy([7u8; 32], SECRET)
+        .await
+        .assert_htlc_error(HtlcError::AlreadyRedeemed);
+    env.redeem_legacy([8u8; 32], SECRET)
+        .await
+        .assert_htlc_error(HtlcError::AlreadyRefunded);
+}
+
+#[tokio::test]
+async fn legacy_paths_reject_non_legacy_accounts() {
+    let mut env = setup().await;
+    let Id = [9u8; 32];
+    set_account_data(
+        &mut env.ctx,
+        legacy_htlc_pda(&Id),
+        sol::ID,
+        vec![0u8; 64],
+        0,
+    )
+    .await;
+
+    env.redeem_legacy(Id, SECRET)
+        .await
+        .assert_htlc_error(HtlcError::NotLegacyHTLC);
+    env.redeem_legacy([10u8; 32], SECRET)
+        .await
+        .assert_htlc_error(HtlcError::NotLegacyHTLC);
+}
+
+#[tokio::test]
+async fn initialize_config_makes_the_upgrade_authority_admin_only_once() {
+    let mut env = setup_without_config().await;
+    let admin = env.admin.insecure_clone();
+    let other = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+    env.initialize_config(&other)
+        .await
+        .assert_htlc_error(HtlcError::NotUpgradeAuthority);
+    assert!(!account_exists(&mut env.ctx, config_pda()).await);
+
+    env.initialize_config(&admin).await.assert_ok();
+    let config = env.config().await;
+    assert_eq!(config.admin, env.admin.pubkey());
+    assert_eq!(config.pending_admin, None);
+    assert!(!config.paused);
+
+    // The Config already exists, so the system program refuses to create it
+    // again (AccountAlreadyInUse).
+    next_blockhash(&mut env.ctx).await;
+    assert_eq!(env.initialize_config(&admin).await.error_code(), Some(0));
+    assert_eq!(env.config().await.admin, env.admin.pubkey());
+}
+
+#[tokio::test]
+async fn admin_transfer_takes_effect_once_accepted() {
+    let mut env = setup().await;
+    let admin = env.admin.insecure_clone();
+    let new_admin = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+
+    env.transfer_admin(&new_admin, new_admin.pubkey())
+        .await
+        .assert_htlc_error(HtlcError::NotAdmin);
+    env.accept_admin(&new_admin)
+        .await
+        .assert_htlc_error(HtlcError::NotPendingAdmin);
+
+    env.transfer_admin(&admin, new_admin.pubkey())
+        .await
+        .assert_ok();
+    let config = env.config().await;
+    assert_eq!(config.admin, admin.pubkey());
+    assert_eq!(config.pending_admin, Some(new_admin.pubkey()));
+    env.accept_admin(&admin)
+        .await
+        .assert_htlc_error(HtlcError::NotPendingAdmin);
+
+    next_blockhash(&mut env.ctx).await;
+    env.accept_admin(&new_admin).await.assert_ok();
+    let config = env.config().await;
+    assert_eq!(config.admin, new_admin.pubkey());
+    assert_eq!(config.pending_admin, None);
+
+    env.set_paused(&admin, true)
+        .await
+        .assert_htlc_error(HtlcError::NotAdmin);
+    env.set_paused(&new_admin, true).await.assert_ok();
+    assert!(env.config().await.paused);
+}
+
+#[tokio::test]
+async fn pause_blocks_commit_and_lock_but_not_redeem_or_refund() {
+    let mut env = setup().await;
+    let admin = env.admin.insecure_clone();
+    let timelock = env.in_seconds(3600).await;
+    let refunded = env.commit(timelock).await;
+    let redeemed = env.commit(timelock).await;
+    let args = env.lock_args([1u8; 32], timelock);
+    env.lock_with(args).await.assert_ok();
+
+    env.set_paused(&admin, true).await.assert_ok();
+    let args = env.commit_args(timelock, AMOUNT).await;
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::Paused);
+    let args = env.lock_args([2u8; 32], timelock);
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::Paused);
+
+    env.add_lock(redeemed, hashlock(), timelock)
+        .await
+        .assert_ok();
+    env.redeem(redeemed, SECRET).await.assert_ok();
+    env.redeem([1u8; 32], SECRET).await.assert_ok();
+    warp_to(&mut env.ctx, timelock as i64).await;
+    env.refund(refunded).await.assert_ok();
+
+    env.set_paused(&admin, false).await.assert_ok();
+    let timelock = env.in_seconds(3600).await;
+    env.commit(timelock).await;
+    let args = env.lock_args([2u8; 32], timelock);
+    env.lock_with(args).await.assert_ok();
+}
+
+#[tokio::test]
+async fn redeem_pays_the_fee_into_the_vault_and_the_admin_withdraws_it() {
+    let mut env = setup().await;
+    let admin = env.admin.insecure_clone();
+    let sender = env.sender.insecure_clone();
+    assert_eq!(env.config().await.fee_bps, 0);
+
+    env.set_fee(&sender, 100)
+        .await
+        .assert_htlc_error(HtlcError::NotAdmin);
+    env.set_fee(&admin, MAX_FEE_BPS + 1)
+        .await
+        .assert_htlc_error(HtlcError::FeeTooHigh);
+    env.set_fee(&admin, 100).await.assert_ok();
+    assert_eq!(env.config().await.fee_bps, 100);
+
+    let timelock = env.in_seconds(3600).await;
+    let args = env.lock_args([1u8; 32], timelock);
+    env.lock_with(args).await.assert_ok();
+    let htlc = htlc_pda(&sender.pubkey(), &[1u8; 32]).0;
+    let locked = anchor_account::<HTLC>(&mut env.ctx, htlc).await.unwrap();
+    assert_eq!(locked.fee_bps, 100);
+    // The HTLC keeps the fee it was locked with.
+    env.set_fee(&admin, MAX_FEE_BPS).await.assert_ok();
+    let fee = AMOUNT / 100;
+    let vault_before = balance(&mut env.ctx, fee_vault_pda()).await;
+    let receiver_before = balance(&mut env.ctx, env.src_receiver.pubkey()).await;
+    let outcome = env.redeem([1u8; 32], SECRET).await;
+    outcome.assert_ok();
+    assert_eq!(
+        u64::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        fee
+    );
+    assert_eq!(
+        balance(&mut env.ctx, env.src_receiver.pubkey()).await,
+        receiver_before + AMOUNT - fee
+    );
+    assert_eq!(
+        balance(&mut env.ctx, fee_vault_pda()).await,
+        vault_before + fee
+    );
+
+    let recipient = Keypair::new().pubkey();
+    env.withdraw_fees(&sender, recipient)
+        .await
+        .assert_htlc_error(HtlcError::NotAdmin);
+    let outcome = env.withdraw_fees(&admin, recipient).await;
+    outcome.assert_ok();
+    assert_eq!(
+        u64::deserialize(&mut &outcome.return_data[..]).unwrap(),
+        fee
+    );
+    assert_eq!(balance(&mut env.ctx, recipient).await, fee);
+    assert_eq!(balance(&mut env.ctx, fee_vault_pda()).await, vault_before);
+}
+
+#[tokio::test]
+async fn timelocks_must_fall_within_the_timelock_window() {
+    let mut env = setup().await;
+    let admin = env.admin.insecure_clone();
+    let sender = env.sender.insecure_clone();
+    let config = env.config().await;
+    assert_eq!(
+        (config.min_timelock_window, config.max_timelock_window),
+        (DEFAULT_MIN_TIMELOCK_WINDOW, DEFAULT_MAX_TIMELOCK_WINDOW)
+    );
+
+    let too_short = env.in_seconds(DEFAULT_MIN_TIMELOCK_WINDOW as i64 - 1).await;
+    let too_long = env.in_seconds(DEFAULT_MAX_TIMELOCK_WINDOW as i64 + 1).await;
+    let args = env.commit_args(too_short, AMOUNT).await;
+    env.commit_with(args)
+        .await
+        .assert_htlc_error(HtlcError::TimelockTooShort);
+    let args = env.lock_args([1u8; 32], too_long);
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::TimelockTooLong);
+
+    let timelock = env.in_seconds(DEFAULT_MIN_TIMELOCK_WINDOW as i64).await;
+    let Id = env.commit(timelock).await;
+    env.add_lock(Id, hashlock(), too_short)
+        .await
+        .assert_htlc_error(HtlcError::TimelockTooShort);
+    env.add_lock(Id, hashlock(), too_long)
+        .await
+        .assert_htlc_error(HtlcError::TimelockTooLong);
+
+    env.set_timelock_window(&sender, 60, 600)
+        .await
+        .assert_htlc_error(HtlcError::NotAdmin);
+    env.set_timelock_window(&admin, 601, 600)
+        .await
+        .assert_htlc_error(HtlcError::InvalidTimelockWindow);
+    env.set_timelock_window(&admin, 60, 600).await.assert_ok();
+    let timelock = env.in_seconds(601).await;
+    let args = env.lock_args([1u8; 32], timelock);
+    env.lock_with(args)
+        .await
+        .assert_htlc_error(HtlcError::TimelockTooLong);
+    let timelock = env.in_seconds(60).await;
+    let args = env.lock_args([1u8; 32], timelock);
+    env.lock_with(args).await.assert_ok();
+    let timelock = env.in_seconds(600).await;
+    env.add_lock(Id, hashlock(), timelock).await.assert_ok();
+}
+
+#[tokio::test]
+async fn extend_timelock_only_moves_the_timelock_later_within_the_window() {
+    let mut env = setup().await;
+    let timelock = env.in_seconds(3600).await;
+    let Id = env.commit(timelock).await;
+    let htlc_address = htlc_pda(&env.sender.pubkey(), &Id).0;
+
+    env.extend_timelock(Id, timelock)
+        .await
+        .assert_htlc_error(HtlcError::TimelockNotExtended);
+    env.extend_timelock(Id, timelock - 1)
+        .await
+        .assert_htlc_error(HtlcError::TimelockNotExtended);
+    let too_long = env.in_seconds(DEFAULT_MAX_TIMELOCK_WINDOW as i64 + 1).await;
+    env.extend_timelock(Id, too_long)
+        .await
+        .assert_htlc_error(HtlcError::TimelockTooLong);
+    let intruder = funded_keypair(&mut env.ctx, LAMPORTS_PER_SOL).await;
+    env.extend_timelock_by(Id, timelock + 3600, &intruder)
+        .await
+        .assert_anchor_error(ErrorCode::ConstraintSeeds);
+
+    env.extend_timelock(Id, timelock + 3600).await.assert_ok();
+    let htlc: HTLC = anchor_account(&mut env.ctx, htlc_address).await.unwrap();
+    assert_eq!(htlc.timelock, timelock + 3600);
+    assert_eq!(htlc.status, HTLCStatus::Committed);
+
+    warp_to(&mut env.ctx, timelock as i64).await;
+    env.refund_by(Id, &intruder)
+        .await
+        .assert_htlc_error(HtlcError::NotPastTimeLock);
+    warp_to(&mut env.ctx, (timelock + 3600) as i64).await;
+    env.refund(Id).await.assert_ok();
+}
@@ -5,60 +5,125 @@
 //    |_| |_| \_\/_/   \_\___|_| \_|    |_|   |_| \_\\___/ |_| \___/ \____\___/|_____|
 
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions::{
+    self as instructions_sysvar, load_current_index_checked, load_instruction_at_checked,
+};
+use anchor_lang::solana_program::{
+    bpf_loader_upgradeable, ed25519_program, hash, keccak, program::invoke,
+};
+use anchor_lang::system_program;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{CloseAccount, Mint, Token, TokenAccount, Transfer},
+    token_2022::spl_token_2022::{
+        self,
+        extension::{
+            transfer_fee::{instruction::harvest_withheld_tokens_to_mint, TransferFeeAmount},
+            BaseStateWithExtensions, StateWithExtensions,
+        },
+    },
+    token_interface::{self, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked},
 };
-use sha2::{Digest, Sha256};
 use std::mem::size_of;
+use train_htlc_core::{
+    add_lock_sig_message, check_fee_bps, check_future_timelock, check_reward_timelock,
+    check_timelock_window, check_timelock_window_bounds, derive_commit_id, redeem_payout,
+    validate_batch, validate_hop_route, validate_htlc_strings, HtlcState, HtlcStatus,
+};
+pub use train_htlc_core::{
+    DEFAULT_MAX_TIMELOCK_WINDOW, DEFAULT_MIN_TIMELOCK_WINDOW, MAX_ADDRESS_LEN, MAX_ASSET_LEN,
+    MAX_BATCH_LEN, MAX_CHAIN_LEN, MAX_FEE_BPS, MAX_HOPS, MAX_HOP_LEN,
+};
 declare_id!("3TTb3BF3H273DS8hCJT9w8wuhtchN7fi7tX2sZDZ3p3Q");
-/// @title Pre Hashed Timelock Contracts (PHTLCs) on Solana SPL tokens.
-///
-/// This contract provides a way to lock and keep PHTLCs for SPL tokens.
-///
-/// Protocol:
-///
-///  1) commit(src_receiver, timelock, tokenContract, amount) - a
-///      sender calls this to create a new HTLC on a given token (tokenContract)
-///      for the given amount. A [u8; 32] Id is returned.
-///  2) lock(src_receiver, hashlock, timelock, tokenContract, amount) - a
-///      sender calls this to create a new HTLC on a given token (tokenContract)
-///      for the given amount. A [u8; 32] Id is returned.
-///  3) add_lock(Id, hashlock) - the sender calls this function
-///      to add hashlock to the HTLC.
-///  4) redeem(Id, secret) - once the src_receiver knows the secret of
-///      the hashlock hash they can claim the tokens with this function
-///  5) refund(Id) - after timelock has expired and if the src_receiver did not
-///      redeem the tokens the sender / creator of the HTLC can get their tokens
-///      back with this function.
+
+/// @dev Moves the keeper bounty, in lamports, from the sender into the new HTLC
+/// account, where it stays until redeem returns it or refund pays it out.
+fn deposit_keeper_bounty<'info>(
+    system_program: &Program<'info, System>,
+    sender: &Signer<'info>,
+    htlc: &Account<'info, HTLC>,
+    keeper_bounty: u64,
+) -> Result<()> {
+    if keeper_bounty == 0 {
+        return Ok(());
+    }
+    let transfer_context = CpiContext::new(
+        system_program.to_account_info(),
+        system_program::Transfer {
+            from: sender.to_account_info(),
+            to: htlc.to_account_info(),
+        },
+    );
+    system_program::transfer(transfer_context, keeper_bounty)
+}
+
+/// @dev Checks that allow_mint registered the mint and that src_asset is the
+/// symbol it was registered with. allowed_mint is the mint's
+/// ["allowed_mint", mint] address, which holds no AllowedMint for an unknown
+/// mint.
+fn check_allowed_mint(allowed_mint: &AccountInfo, src_asset: &str) -> Result<()> {
+    require!(
+        allowed_mint.owner == &crate::ID && !allowed_mint.data_is_empty(),
+        HTLCError::MintNotAllowed
+    );
+    let entry = AllowedMint::try_deserialize(&mut &allowed_mint.try_borrow_data()?[..])?;
+    require!(entry.src_asset == src_asset, HTLCError::SrcAssetMismatch);
+    Ok(())
+}
+
+/// @dev Moves the transfer fees a Token-2022 token account withheld to its mint,
+/// as close_account refuses an account still withholding any. The mint is only
+/// written to then, so it has to be passed writable for Token-2022 mints alone.
+fn harvest_withheld_fees<'info>(
+    token_account: AccountInfo<'info>,
+    mint: AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+) -> Result<()> {
+    if token_program.key() != spl_token_2022::ID {
+        return Ok(());
+    }
+    let withheld = {
+        let data = token_account.try_borrow_data()?;
+        let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+        state
+            .get_extension::<TransferFeeAmount>()
+            .map_or(0, |fee_amount| u64::from(fee_amount.withheld_amount))
+    };
+    if withheld == 0 {
+        return Ok(());
+    }
+    require!(mint.is_writable, ErrorCode::ConstraintMut);
+    let harvest =
+        harvest_withheld_tokens_to_mint(token_program.key, mint.key, &[token_account.key])?;
+    invoke(&harvest, &[mint, token_account])?;
+    Ok(())
+}
 
 /// @dev A small utility function that allows us to transfer funds out of the htlc / htlc.
 ///
 /// * `sender` - htlc creator's account
-/// * `Id` - The index of the htlc
 /// * `htlc` - the htlc public key (PDA)
-/// * `htlc_bump` - the htlc public key (PDA) bump
+/// * `htlc_seeds` - the htlc PDA seeds, bump included
 /// * `htlc_token_account` - The htlc Token account
+/// * `token_contract` - the mint of the htlc Token account
 /// * `token_program` - the token program address
 /// * `destination_wallet` - The public key of the destination address (where to send funds)
 /// * `amount` - the amount of token that is sent from `htlc_token_account` to `destination_wallet`
 fn transfer_htlc_out<'info>(
     sender: AccountInfo<'info>,
-    Id: [u8; 32],
     htlc: AccountInfo<'info>,
-    htlc_bump: u8,
-    htlc_token_account: &mut Account<'info, TokenAccount>,
+    htlc_seeds: &[&[u8]],
+    htlc_token_account: &mut InterfaceAccount<'info, TokenAccount>,
+    token_contract: &InterfaceAccount<'info, Mint>,
     token_program: AccountInfo<'info>,
     destination_wallet: AccountInfo<'info>,
     amount: u64,
 ) -> Result<()> {
-    let bump_vector = htlc_bump.to_le_bytes();
-    let inner = vec![Id.as_ref(), bump_vector.as_ref()];
-    let outer = vec![inner.as_slice()];
+    let outer = vec![htlc_seeds];
 
     // Perform the actual transfer
-    let transfer_instruction = Transfer {
+    let transfer_instruction = TransferChecked {
         from: htlc_token_account.to_account_info(),
+        mint: token_contract.to_account_info(),
         to: destination_wallet,
         authority: htlc.to_account_info(),
     };
@@ -67,7 +132,7 @@ fn transfer_htlc_out<'info>(
         transfer_instruction,
         outer.as_slice(),
     );
-    anchor_spl::token::transfer(cpi_ctx, amount)?;
+    token_interface::transfer_checked(cpi_ctx, amount, token_contract.decimals)?;
 
     // Use the `reload()` function on an account to reload it's state. Since we performed the
     // transfer, we are expecting the `amount` field to have changed.
@@ -78,6 +143,11 @@ fn transfer_htlc_out<'info>(
 
     // If token account has no more tokens, it should be wiped out since it has no other use case.
     if should_close {
+        harvest_withheld_fees(
+            htlc_token_account.to_account_info(),
+            token_contract.to_account_info(),
+            &token_program,
+        )?;
         let ca = CloseAccount {
             account: htlc_token_account.to_account_info(),
             destination: sender.to_account_info(),
@@ -85,47 +155,747 @@ fn transfer_htlc_out<'info>(
         };
         let cpi_ctx =
             CpiContext::new_with_signer(token_program.to_account_info(), ca, outer.as_slice());
-        anchor_spl::token::close_account(cpi_ctx)?;
+        token_interface::close_account(cpi_ctx)?;
+    }
+
+    Ok(())
+}
+
+/// @dev Checks that the instruction right before the current one is an Ed25519 program
+/// instruction verifying `signature` by `signer` over the addLockSig message
+/// (program_id || Id || hashlock || timelock as big-endian u64 || hash algorithm tag).
+///
+/// * `ix_sysvar` - the instructions sysvar account
+/// * `signer` - the expected signer, i.e. the HTLC sender
+/// * `Id` - the Id of the HTLC
+/// * `hashlock` - the hashlock to be added
+/// * `timelock` - the timelock to be set
+/// * `hash_algorithm` - the algorithm the hashlock is taken with
+/// * `signature` - the ed25519 signature provided to addLockSig
+fn verify_add_lock_sig(
+    ix_sysvar: &AccountInfo,
+    signer: &Pubkey,
+    Id: [u8; 32],
+    hashlock: [u8; 32],
+    timelock: u64,
+    hash_algorithm: HashAlgorithm,
+    signature: [u8; 64],
+) -> Result<()> {
+    let current_index = load_current_index_checked(ix_sysvar)?;
+    require!(current_index > 0, HTLCError::InvalidSignature);
+    let ix = load_instruction_at_checked((current_index - 1) as usize, ix_sysvar)?;
+    require!(
+        ix.program_id == ed25519_program::ID && ix.accounts.is_empty(),
+        HTLCError::InvalidSignature
+    );
+
+    // Ed25519 instruction data: [num_signatures: u8, padding: u8, offsets: 7 x u16, ...].
+    let data = ix.data;
+    require!(
+        data.len() >= 16 && data[0] == 1,
+        HTLCError::InvalidSignature
+    );
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let signature_offset = read_u16(2) as usize;
+    let signature_ix_index = read_u16(4);
+    let public_key_offset = read_u16(6) as usize;
+    let public_key_ix_index = read_u16(8);
+    let message_offset = read_u16(10) as usize;
+    let message_size = read_u16(12) as usize;
+    let message_ix_index = read_u16(14);
+    // All the data must live inside the Ed25519 instruction itself.
+    require!(
+        signature_ix_index == u16::MAX
+            && public_key_ix_index == u16::MAX
+            && message_ix_index == u16::MAX,
+        HTLCError::InvalidSignature
+    );
+    require!(
+        data.len() >= signature_offset + 64
+            && data.len() >= public_key_offset + 32
+            && data.len() >= message_offset + message_size,
+        HTLCError::InvalidSignature
+    );
+
+    let message = add_lock_sig_message(
+        &crate::ID.to_bytes(),
+        &Id,
+        &hashlock,
+        timelock,
+        hash_algorithm.into(),
+    );
+
+    require!(
+        data[public_key_offset..public_key_offset + 32] == signer.to_bytes(),
+        HTLCError::InvalidSignature
+    );
+    require!(
+        data[signature_offset..signature_offset + 64] == signature,
+        HTLCError::InvalidSignature
+    );
+    require!(
+        data[message_offset..message_offset + message_size] == message[..],
+        HTLCError::InvalidSignature
+    );
+
+    Ok(())
+}
+
+/// @dev Hashes the secret with the HTLC's algorithm through the matching syscall.
+fn hash_secret(hash_algorithm: HashAlgorithm, secret: &[u8; 32]) -> [u8; 32] {
+    match hash_algorithm {
+        HashAlgorithm::Sha256 => hash::hash(secret).to_bytes(),
+        HashAlgorithm::Keccak256 => keccak::hash(secret).to_bytes(),
+    }
+}
+
+/// @dev Reads an HTLC account created at [Id] before the HTLC seeds were namespaced.
+fn load_legacy_htlc(htlc: &AccountInfo) -> Result<LegacyHTLC> {
+    require_keys_eq!(*htlc.owner, crate::ID, HTLCError::NotLegacyHTLC);
+    let data = htlc.try_borrow_data()?;
+    require!(
+        data.len() >= 8 && data[..8] == <HTLC as anchor_lang::Discriminator>::DISCRIMINATOR,
+        HTLCError::NotLegacyHTLC
+    );
+    LegacyHTLC::deserialize(&mut &data[8..]).map_err(|_| error!(HTLCError::NotLegacyHTLC))
+}
+
+/// @dev Closes a legacy HTLC account, sending its remaining lamports to `destination`.
+fn close_legacy_htlc<'info>(
+    htlc: &AccountInfo<'info>,
+    destination: &AccountInfo<'info>,
+) -> Result<()> {
+    let lamports = htlc.lamports();
+    htlc.sub_lamports(lamports)?;
+    destination.add_lamports(lamports)?;
+    htlc.assign(&anchor_lang::system_program::ID);
+    htlc.realloc(0, false)?;
+    Ok(())
+}
+
+/// @dev Borsh size of the hop route stored in the HTLC.
+fn hop_route_space(
+    hop_chains: &[String],
+    hop_assets: &[String],
+    hop_addresses: &[String],
+) -> usize {
+    [hop_chains, hop_assets, hop_addresses]
+        .iter()
+        .map(|hops| 4 + hops.iter().map(|hop| 4 + hop.len()).sum::<usize>())
+        .sum()
+}
+
+/// @dev Appends the Id to the sender's current contracts page and moves the
+/// index on to a fresh page once the current one is full.
+/// Returns the page the Id was stored in.
+fn append_contract(
+    contracts_index: &mut ContractsIndex,
+    contracts_page: &mut ContractsPage,
+    Id: [u8; 32],
+) -> u32 {
+    let page = contracts_index.current_page;
+    contracts_page.ids.push(Id);
+    if contracts_page.ids.len() >= MAX_CONTRACTS_PER_PAGE {
+        contracts_index.current_page += 1;
+    }
+    page
+}
+
+/// @dev Removes the Id from the sender's contracts page once the HTLC is closed.
+fn prune_contract(contracts_page: &mut ContractsPage, Id: [u8; 32]) {
+    if let Some(position) = contracts_page.ids.iter().position(|id| *id == Id) {
+        contracts_page.ids.swap_remove(position);
+    }
+}
+
+/// @dev The result a skipped batch item is reported with: the code of the
+/// Anchor or HTLCError check it failed. Any other error aborts the batch.
+fn batch_error_code(error: Error) -> Result<u32> {
+    match error {
+        Error::AnchorError(error) => Ok(error.error_code_number),
+        error => Err(error),
     }
+}
+
+/// @dev The accounts of a batch item, as checked by load_batch_htlc.
+struct BatchHtlc<'info> {
+    htlc: Account<'info, HTLC>,
+    htlc_bump: u8,
+    contracts_page: Account<'info, ContractsPage>,
+    htlc_token_account: InterfaceAccount<'info, TokenAccount>,
+    token_contract: InterfaceAccount<'info, Mint>,
+}
+
+/// @dev Loads the HTLC, contracts page, HTLC token account and mint of a batch
+/// item, checking them the way the Redeem and Refund accounts do.
+fn load_batch_htlc<'info>(
+    Id: &[u8; 32],
+    htlc: &'info AccountInfo<'info>,
+    contracts_page: &'info AccountInfo<'info>,
+    htlc_token_account: &'info AccountInfo<'info>,
+    token_contract: &'info AccountInfo<'info>,
+    sender: &AccountInfo,
+    payer: &AccountInfo,
+) -> Result<BatchHtlc<'info>> {
+    let (address, htlc_bump) =
+        Pubkey::find_program_address(&[b"htlc", sender.key.as_ref(), Id], &crate::ID);
+    require_keys_eq!(address, htlc.key(), ErrorCode::ConstraintSeeds);
+    let htlc = Account::<HTLC>::try_from(htlc)?;
+    require_keys_eq!(htlc.sender, sender.key(), HTLCError::NotSender);
+    require_keys_eq!(htlc.payer, payer.key(), HTLCError::NotPayer);
+    require_keys_eq!(
+        htlc.token_contract,
+        token_contract.key(),
+        HTLCError::NoToken
+    );
+    require_keys_eq!(
+        htlc.token_wallet,
+        htlc_token_account.key(),
+        ErrorCode::ConstraintSeeds
+    );
+
+    let (address, _) = Pubkey::find_program_address(
+        &[
+            b"contracts_page",
+            sender.key.as_ref(),
+            &htlc.index_page.to_le_bytes(),
+        ],
+        &crate::ID,
+    );
+    require_keys_eq!(address, contracts_page.key(), ErrorCode::ConstraintSeeds);
+    Ok(BatchHtlc {
+        htlc,
+        htlc_bump,
+        contracts_page: Account::try_from(contracts_page)?,
+        htlc_token_account: InterfaceAccount::try_from(htlc_token_account)?,
+        token_contract: InterfaceAccount::try_from(token_contract)?,
+    })
+}
 
+/// @dev Checks that a token account passed to a batch item is of the HTLC mint
+/// and owned by `owner`, failing with `owner_error` otherwise.
+fn check_batch_token_account<'info>(
+    token_account: &'info AccountInfo<'info>,
+    owner: Pubkey,
+    mint: Pubkey,
+    owner_error: HTLCError,
+) -> Result<()> {
+    let token_account = InterfaceAccount::<TokenAccount>::try_from(token_account)?;
+    require_keys_eq!(token_account.owner, owner, owner_error);
+    require_keys_eq!(token_account.mint, mint, HTLCError::NoToken);
     Ok(())
 }
 
+/// @dev Closes an HTLC queued by a batch item, paying its keeper bounty to
+/// `bounty_to` and the rest of its lamports, the rent, to the payer.
+fn close_batch_htlc<'info>(
+    htlc: Account<'info, HTLC>,
+    bounty_to: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+) -> Result<()> {
+    htlc.sub_lamports(htlc.keeper_bounty)?;
+    bounty_to.add_lamports(htlc.keeper_bounty)?;
+    htlc.close(payer.clone())
+}
+
+/// @dev Redeems one item of redeem_batch. Returns 0, or the error code the item
+/// is skipped with if it fails its checks.
+///
+/// The redeemed HTLC is only marked Redeemed, so a repeated item is skipped,
+/// and is queued in `closes`. redeem_batch closes the accounts once every
+/// token transfer is done: moving lamports out of an account ahead of a later
+/// item's CPI would unbalance that CPI under solana-program-test.
+///
+/// * `user_signing` - the redeemer, paid the reward after reward_timelock
+/// * `token_program` - the token program of every mint in the batch
+/// * `item` - Id and secret of the HTLC
+/// * `accounts` - htlc, contracts_page, htlc_token_account, token_contract,
+///   fee_vault, src_receiver_token_account, sender_token_account,
+///   reward_token_account, sender and payer. Like an absent optional account,
+///   fee_vault is this program's id when the HTLC charges no fee, and
+///   reward_token_account when the redeemer is not paid the reward.
+/// * `now` - the current unix timestamp
+/// * `closes` - the HTLCs to close, with the sender their keeper bounty goes
+///   back to and the payer their rent goes back to
+fn redeem_batch_item<'info>(
+    user_signing: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    item: &RedeemBatchItem,
+    accounts: &'info [AccountInfo<'info>],
+    now: i64,
+    closes: &mut Vec<(
+        Account<'info, HTLC>,
+        &'info AccountInfo<'info>,
+        &'info AccountInfo<'info>,
+    )>,
+) -> Result<u32> {
+    let [htlc, contracts_page, htlc_token_account, token_contract, fee_vault, src_receiver_token_account, sender_token_account, reward_token_account, sender, payer] =
+        accounts
+    else {
+        return err!(HTLCError::BatchAccountsMismatch);
+    };
+    let checked = load_batch_htlc(
+        &item.Id,
+        htlc,
+        contracts_page,
+        htlc_token_account,
+        token_contract,
+        sender,
+        payer,
+    )
+    .and_then(|batch_htlc| {
+        let htlc = &batch_htlc.htlc;
+        require_keys_eq!(
+            *token_contract.owner,
+            token_program.key(),
+            ErrorCode::ConstraintMintTokenProgram
+        );
+        require!(
+            token_program.key() != spl_token_2022::ID || token_contract.is_writable,
+            ErrorCode::ConstraintMut
+        );
+        let fee_vault = (fee_vault.key() != crate::ID).then_some(fee_vault);
+        if let Some(fee_vault) = fee_vault {
+            let (address, _) = Pubkey::find_program_address(
+                &[b"fee_vault", htlc.token_contract.as_ref()],
+                &crate::ID,
+            );
+            require_keys_eq!(address, fee_vault.key(), ErrorCode::ConstraintSeeds);
+        }
+        check_batch_token_account(
+            src_receiver_token_account,
+            htlc.src_receiver,
+            htlc.token_contract,
+            HTLCError::NotReciever,
+        )?;
+        check_batch_token_account(
+            sender_token_account,
+            htlc.sender,
+            htlc.token_contract,
+            HTLCError::NotSender,
+        )?;
+        let reward_token_account =
+            (reward_token_account.key() != crate::ID).then_some(reward_token_account);
+        if let Some(reward_token_account) = reward_token_account {
+            check_batch_token_account(
+                reward_token_account,
+                user_signing.key(),
+                htlc.token_contract,
+                HTLCError::UnauthorizedAccess,
+            )?;
+        }
+        let mut state = htlc.state();
+        state
+            .redeem(&hash_secret(htlc.hash_algorithm, &item.secret))
+            .map_err(HTLCError::from)?;
+        let payout = redeem_payout(
+            htlc.amount,
+            htlc.reward,
+            htlc.reward_timelock,
+            now,
+            user_signing.key() == htlc.src_receiver,
+            htlc.fee_bps,
+        );
+        let fee_transfer = if payout.fee != 0 {
+            let fee_vault = fee_vault.ok_or(HTLCError::FeeVaultMissing)?;
+            Some((fee_vault, payout.fee))
+        } else {
+            None
+        };
+        let reward_transfer = if payout.sender != 0 {
+            Some((sender_token_account, payout.sender))
+        } else if payout.redeemer != 0 {
+            let reward_token_account =
+                reward_token_account.ok_or(HTLCError::RewardAccountMissing)?;
+            Some((reward_token_account, payout.redeemer))
+        } else {
+            None
+        };
+        Ok((batch_htlc, state, payout, fee_transfer, reward_transfer))
+    });
+    let (
+        BatchHtlc {
+            mut htlc,
+            htlc_bump,
+            mut contracts_page,
+            mut htlc_token_account,
+            token_contract,
+        },
+        state,
+        payout,
+        fee_transfer,
+        reward_transfer,
+    ) = match checked {
+        Ok(batch_htlc) => batch_htlc,
+        Err(error) => return batch_error_code(error),
+    };
+
+    let bump_vector = htlc_bump.to_le_bytes();
+    let htlc_seeds = [
+        b"htlc".as_ref(),
+        sender.key.as_ref(),
+        item.Id.as_ref(),
+        bump_vector.as_ref(),
+    ];
+    if let Some((fee_vault, fee)) = fee_transfer {
+        transfer_htlc_out(
+            sender.clone(),
+            htlc.to_account_info(),
+            &htlc_seeds,
+            &mut htlc_token_account,
+            &token_contract,
+            token_program.clone(),
+            fee_vault.clone(),
+            fee,
+        )?;
+    }
+    transfer_htlc_out(
+        sender.clone(),
+        htlc.to_account_info(),
+        &htlc_seeds,
+        &mut htlc_token_account,
+        &token_contract,
+        token_program.clone(),
+        src_receiver_token_account.clone(),
+        payout.src_receiver,
+    )?;
+    if let Some((reward_wallet, reward)) = reward_transfer {
+        transfer_htlc_out(
+            sender.clone(),
+            htlc.to_account_info(),
+            &htlc_seeds,
+            &mut htlc_token_account,
+            &token_contract,
+            token_program.clone(),
+            reward_wallet.clone(),
+            reward,
+        )?;
+    }
+
+    prune_contract(&mut contracts_page, item.Id);
+    contracts_page.exit(&crate::ID)?;
+    htlc.set_state(state);
+    htlc.exit(&crate::ID)?;
+
+    emit!(TokenRedeemed {
+        Id: item.Id,
+        redeem_address: user_signing.key(),
+        secret: item.secret,
+        hashlock: htlc.hashlock,
+        fee: payout.fee,
+        status: HTLCStatus::Redeemed,
+    });
+    closes.push((htlc, sender, payer));
+    Ok(0)
+}
+
+/// @dev Refunds one item of refund_batch. Returns 0, or the error code the item
+/// is skipped with if it fails its checks, e.g. NotPastTimeLock. As in
+/// redeem_batch_item, the refunded HTLC is marked Refunded and queued in
+/// `closes`; refund_batch pays its keeper bounty to the caller as it closes it.
+///
+/// * `keeper` - the refunder, paid the keeper bounty
+/// * `sender` - the sender of every HTLC in the batch
+/// * `token_program` - the token program of every mint in the batch
+/// * `Id` - Id of the HTLC
+/// * `accounts` - htlc, contracts_page, htlc_token_account, token_contract,
+///   sender_token_account and payer
+/// * `now` - the current unix timestamp
+/// * `closes` - the HTLCs to close, with the payer their rent goes back to
+fn refund_batch_item<'info>(
+    keeper: &AccountInfo<'info>,
+    sender: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    Id: [u8; 32],
+    accounts: &'info [AccountInfo<'info>],
+    now: i64,
+    closes: &mut Vec<(Account<'info, HTLC>, &'info AccountInfo<'info>)>,
+) -> Result<u32> {
+    let [htlc, contracts_page, htlc_token_account, token_contract, sender_token_account, payer] =
+        accounts
+    else {
+        return err!(HTLCError::BatchAccountsMismatch);
+    };
+    let checked = load_batch_htlc(
+        &Id,
+        htlc,
+        contracts_page,
+        htlc_token_account,
+        token_contract,
+        sender,
+        payer,
+    )
+    .and_then(|batch_htlc| {
+        let htlc = &batch_htlc.htlc;
+        require_keys_eq!(
+            *token_contract.owner,
+            token_program.key(),
+            ErrorCode::ConstraintMintTokenProgram
+        );
+        require!(
+            token_program.key() != spl_token_2022::ID || token_contract.is_writable,
+            ErrorCode::ConstraintMut
+        );
+        check_batch_token_account(
+            sender_token_account,
+            htlc.sender,
+            htlc.token_contract,
+            HTLCError::NotSender,
+        )?;
+        let mut state = htlc.state();
+        state.refund(now).map_err(HTLCError::from)?;
+        Ok((batch_htlc, state))
+    });
+    let (
+        BatchHtlc {
+            mut htlc,
+            htlc_bump,
+            mut contracts_page,
+            mut htlc_token_account,
+            token_contract,
+        },
+        state,
+    ) = match checked {
+        Ok(accounts) => accounts,
+        Err(error) => return batch_error_code(error),
+    };
+
+    let bump_vector = htlc_bump.to_le_bytes();
+    let htlc_seeds = [
+        b"htlc".as_ref(),
+        sender.key.as_ref(),
+        Id.as_ref(),
+        bump_vector.as_ref(),
+    ];
+    transfer_htlc_out(
+        sender.clone(),
+        htlc.to_account_info(),
+        &htlc_seeds,
+        &mut htlc_token_account,
+        &token_contract,
+        token_program.clone(),
+        sender_token_account.clone(),
+        htlc.amount + htlc.reward,
+    )?;
+
+    prune_contract(&mut contracts_page, Id);
+    contracts_page.exit(&crate::ID)?;
+    htlc.set_state(state);
+    htlc.exit(&crate::ID)?;
+
+    emit!(TokenRefunded {
+        Id,
+        keeper: keeper.key(),
+        keeper_bounty: htlc.keeper_bounty,
+        status: HTLCStatus::Refunded,
+    });
+    closes.push((htlc, payer));
+    Ok(0)
+}
+
+/// @title Pre Hashed Timelock Contracts (PHTLCs) on Solana SPL tokens.
+///
+/// This contract provides a way to lock and keep PHTLCs for SPL tokens.
+///
+/// Protocol:
+///
+///  1) commit(src_receiver, timelock, tokenContract, amount) - a
+///      sender calls this to create a new HTLC on a given token (tokenContract)
+///      for the given amount. A [u8; 32] Id is returned.
+///  2) lock(src_receiver, hashlock, timelock, tokenContract, amount) - a
+///      sender calls this to create a new HTLC on a given token (tokenContract)
+///      for the given amount. A [u8; 32] Id is returned.
+///  3) add_lock(Id, hashlock) - the sender calls this function
+///      to add hashlock to the HTLC.
+///  4) redeem(Id, secret) - once the src_receiver knows the secret of
+///      the hashlock hash they can claim the tokens with this function
+///  5) refund(Id) - after timelock has expired and if the src_receiver did not
+///      redeem the tokens the sender / creator of the HTLC can get their tokens
+///      back with this function.
 #[program]
 pub mod anchor_htlc {
 
     use super::*;
-    use anchor_spl::token::Transfer;
+
+    /// @dev Creates the program's Config with the signer as its admin. Only the
+    /// program's upgrade authority can call it, and only once, so it should be
+    /// sent right after the deployment.
+    pub fn initialize_config(ctx: Context<InitializeConfig>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.pending_admin = None;
+        config.paused = false;
+        config.fee_bps = 0;
+        config.min_timelock_window = DEFAULT_MIN_TIMELOCK_WINDOW;
+        config.max_timelock_window = DEFAULT_MAX_TIMELOCK_WINDOW;
+
+        emit!(AdminTransferred {
+            previous_admin: Pubkey::default(),
+            admin: config.admin,
+        });
+        Ok(())
+    }
+
+    /// @dev Called by the admin to hand the admin role over. The new admin
+    /// takes it with accept_admin; until then the current admin keeps it and
+    /// can propose someone else instead.
+    /// @param new_admin the proposed admin.
+    pub fn transfer_admin(ctx: Context<ConfigAdmin>, new_admin: Pubkey) -> Result<()> {
+        ctx.accounts.config.pending_admin = Some(new_admin);
+
+        emit!(AdminTransferStarted {
+            admin: ctx.accounts.admin.key(),
+            pending_admin: new_admin,
+        });
+        Ok(())
+    }
+
+    /// @dev Called by the pending admin to take over the admin role.
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let previous_admin = config.admin;
+        config.admin = ctx.accounts.pending_admin.key();
+        config.pending_admin = None;
+
+        emit!(AdminTransferred {
+            previous_admin,
+            admin: config.admin,
+        });
+        Ok(())
+    }
+
+    /// @dev Called by the admin to pause or resume new deposits. While paused,
+    /// commit and lock fail with Paused; redeem, refund and every other way out
+    /// of an existing HTLC keep working, so funds are never frozen.
+    /// @param paused whether commit and lock are blocked.
+    pub fn set_paused(ctx: Context<ConfigAdmin>, paused: bool) -> Result<()> {
+        ctx.accounts.config.paused = paused;
+
+        emit!(PauseSet { paused });
+        Ok(())
+    }
+
+    /// @dev Called by the admin to set the protocol fee redeem takes from the
+    /// amount of each HTLC. An HTLC keeps the fee it was committed or locked
+    /// with, so the new fee only applies to the HTLCs opened after it.
+    /// @param fee_bps the fee in basis points, at most MAX_FEE_BPS.
+    pub fn set_fee(ctx: Context<ConfigAdmin>, fee_bps: u16) -> Result<()> {
+        check_fee_bps(fee_bps).map_err(HTLCError::from)?;
+        ctx.accounts.config.fee_bps = fee_bps;
+
+        emit!(FeeSet { fee_bps });
+        Ok(())
+    }
+
+    /// @dev Called by the admin to set how far in the future commit, lock and
+    /// add_lock accept a timelock. HTLCs already open keep their timelock.
+    /// @param min_window the shortest timelock window, in seconds.
+    /// @param max_window the longest timelock window, in seconds.
+    pub fn set_timelock_window(
+        ctx: Context<ConfigAdmin>,
+        min_window: u64,
+        max_window: u64,
+    ) -> Result<()> {
+        check_timelock_window_bounds(min_window, max_window).map_err(HTLCError::from)?;
+        let config = &mut ctx.accounts.config;
+        config.min_timelock_window = min_window;
+        config.max_timelock_window = max_window;
+
+        emit!(TimelockWindowSet {
+            min_window,
+            max_window,
+        });
+        Ok(())
+    }
+
+    /// @dev Called by the admin to move the protocol fees collected in one
+    /// mint's fee vault to recipient_token_account. The fee vaults are owned by
+    /// the Config, which signs the transfer.
+    /// @return the tokens withdrawn, in base units.
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>) -> Result<u64> {
+        let amount = ctx.accounts.fee_vault.amount;
+        let config_seeds: &[&[u8]] = &[b"config", &[ctx.bumps.config]];
+        let transfer = TransferChecked {
+            from: ctx.accounts.fee_vault.to_account_info(),
+            mint: ctx.accounts.token_contract.to_account_info(),
+            to: ctx.accounts.recipient_token_account.to_account_info(),
+            authority: ctx.accounts.config.to_account_info(),
+        };
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                transfer,
+                &[config_seeds],
+            ),
+            amount,
+            ctx.accounts.token_contract.decimals,
+        )?;
+
+        emit!(FeesWithdrawn {
+            token_contract: ctx.accounts.token_contract.key(),
+            recipient: ctx.accounts.recipient_token_account.key(),
+            amount,
+        });
+        Ok(amount)
+    }
+
+    /// @dev Called by the admin to accept a mint in commit and lock, or to
+    /// change the symbol of a mint already accepted.
+    /// @param src_asset the mint's canonical symbol, which the src_asset of
+    ///                  every commit and lock of the mint has to equal.
+    pub fn allow_mint(ctx: Context<AllowMint>, src_asset: String) -> Result<()> {
+        require!(src_asset.len() <= MAX_ASSET_LEN, HTLCError::SrcAssetTooLong);
+        ctx.accounts.allowed_mint.src_asset = src_asset.clone();
+
+        emit!(MintAllowed {
+            token_contract: ctx.accounts.token_contract.key(),
+            src_asset,
+        });
+        Ok(())
+    }
+
+    /// @dev Called by the admin to stop accepting a mint in commit and lock.
+    /// HTLCs of the mint already open can still be redeemed and refunded.
+    pub fn remove_mint(ctx: Context<RemoveMint>) -> Result<()> {
+        emit!(MintRemoved {
+            token_contract: ctx.accounts.token_contract.key(),
+        });
+        Ok(())
+    }
 
     /// @dev Called by the Sender to get the commitId from the given parameters.
+    /// The Id is derived from the sender's current nonce, so it is the Id the
+    /// sender's next commit with these terms has to use.
     pub fn get_commit_id(
         ctx: Context<GetCommitId>,
         amount: u64,
         timelock: u64,
     ) -> Result<[u8; 32]> {
-        let sender = &ctx.accounts.sender.to_account_info().key;
-        let receiver = &ctx.accounts.receiver.to_account_info().key;
-        let clock = Clock::get().unwrap();
-        let time: u64 = clock.unix_timestamp.try_into().unwrap();
-
-        let mut hasher = Sha256::new();
-        hasher.update(ctx.program_id);
-        hasher.update(sender);
-        hasher.update(receiver);
-        hasher.update(&amount.to_be_bytes());
-        hasher.update(&time.to_be_bytes());
-        hasher.update(&timelock.to_be_bytes());
+        let sender_nonce = &ctx.accounts.sender_nonce;
+        let nonce = if sender_nonce.data_is_empty() {
+            0
+        } else {
+            SenderNonce::try_deserialize(&mut &sender_nonce.try_borrow_data()?[..])?.nonce
+        };
 
-        let commitId = hasher.finalize();
-        // let commitId = hex::encode(commitId);
-        // msg!("commit id: {}", commitId);
-        Ok(commitId.into())
+        Ok(derive_commit_id(
+            &ctx.program_id.to_bytes(),
+            &ctx.accounts.sender.key.to_bytes(),
+            &ctx.accounts.receiver.key.to_bytes(),
+            amount,
+            timelock,
+            nonce,
+        ))
     }
     /// @dev Sender / Payer sets up a new pre-hash time lock contract depositing the
     /// funds and providing the reciever/src_receiver and terms.
+    /// The Id must be the one get_commit_id derives for the sender's current nonce.
     /// @param src_receiver reciever of the funds.
     /// @param timelock UNIX epoch seconds time that the lock expires at.
     ///                  Refunds can be made after this time.
+    /// @param hash_algorithm hash the hashlock added later is taken with.
+    /// @param keeper_bounty lamports deposited in the HTLC account for whoever
+    ///                  refunds the HTLC once it expires. Zero for none.
     /// @return Id of the new HTLC. This is needed for subsequent calls.
     pub fn commit(
         ctx: Context<Commit>,
@@ -140,56 +910,127 @@ pub mod anchor_htlc {
         src_receiver: Pubkey,
         timelock: u64,
         amount: u64,
+        hash_algorithm: HashAlgorithm,
+        keeper_bounty: u64,
         commit_bump: u8,
     ) -> Result<[u8; 32]> {
         let clock = Clock::get().unwrap();
+        check_future_timelock(timelock, clock.unix_timestamp).map_err(HTLCError::from)?;
+        ctx.accounts
+            .config
+            .check_timelock(timelock, clock.unix_timestamp)?;
+        require!(amount != 0, HTLCError::FundsNotSent);
         require!(
-            timelock > clock.unix_timestamp.try_into().unwrap(),
-            HTLCError::NotFutureTimeLock
+            Id == derive_commit_id(
+                &ctx.program_id.to_bytes(),
+                &ctx.accounts.sender.key.to_bytes(),
+                &src_receiver.to_bytes(),
+                amount,
+                timelock,
+                ctx.accounts.sender_nonce.nonce,
+            ),
+            HTLCError::InvalidCommitId
         );
-        require!(amount != 0, HTLCError::FundsNotSent);
+        ctx.accounts.sender_nonce.nonce += 1;
+        validate_htlc_strings(&dst_chain, &dst_asset, &dst_address, &src_asset)
+            .map_err(HTLCError::from)?;
+        validate_hop_route(&hopChains, &hopAssets, &hopAddress).map_err(HTLCError::from)?;
+        check_allowed_mint(&ctx.accounts.allowed_mint, &src_asset)?;
         let htlc = &mut ctx.accounts.htlc;
         let bump_vector = commit_bump.to_le_bytes();
-        let inner = vec![Id.as_ref(), bump_vector.as_ref()];
+        let inner = vec![
+            b"htlc".as_ref(),
+            ctx.accounts.sender.key.as_ref(),
+            Id.as_ref(),
+            bump_vector.as_ref(),
+        ];
         let outer = vec![inner.as_slice()];
         let transfer_context = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
-            Transfer {
+            TransferChecked {
                 from: ctx.accounts.sender_token_account.to_account_info(),
+                mint: ctx.accounts.token_contract.to_account_info(),
                 to: ctx.accounts.htlc_token_account.to_account_info(),
                 authority: ctx.accounts.sender.to_account_info(),
             },
             outer.as_slice(),
         );
-        anchor_spl::token::transfer(transfer_context, amount)?;
+        token_interface::transfer_checked(
+            transfer_context,
+            amount,
+            ctx.accounts.token_contract.decimals,
+        )?;
+        // The vault is new, so its balance is what actually arrived after any transfer fee.
+        ctx.accounts.htlc_token_account.reload()?;
+        let amount = ctx.accounts.htlc_token_account.amount;
+        require!(amount != 0, HTLCError::FundsNotSent);
 
-        htlc.dst_address = dst_address;
-        htlc.dst_chain = dst_chain;
-        htlc.dst_asset = dst_asset;
-        htlc.src_asset = src_asset;
+        htlc.dst_address = dst_address.clone();
+        htlc.dst_chain = dst_chain.clone();
+        htlc.dst_asset = dst_asset.clone();
+        htlc.src_asset = src_asset.clone();
         htlc.sender = *ctx.accounts.sender.to_account_info().key;
         htlc.src_receiver = src_receiver;
+        htlc.payer = *ctx.accounts.sender.to_account_info().key;
+        htlc.hop_chains = hopChains.clone();
+        htlc.hop_assets = hopAssets.clone();
+        htlc.hop_addresses = hopAddress.clone();
         htlc.hashlock = [0u8; 32];
+        htlc.hash_algorithm = hash_algorithm;
         htlc.secret = [0u8; 32];
         htlc.amount = amount;
         htlc.timelock = timelock;
+        htlc.reward = 0;
+        htlc.reward_timelock = 0;
+        htlc.keeper_bounty = keeper_bounty;
+        htlc.fee_bps = ctx.accounts.config.fee_bps;
         htlc.token_contract = *ctx.accounts.token_contract.to_account_info().key;
         htlc.token_wallet = *ctx.accounts.htlc_token_account.to_account_info().key;
-        htlc.redeemed = false;
-        htlc.refunded = false;
+        htlc.status = HTLCStatus::Committed;
+        htlc.index_page = append_contract(
+            &mut ctx.accounts.contracts_index,
+            &mut ctx.accounts.contracts_page,
+            Id,
+        );
+        deposit_keeper_bounty(
+            &ctx.accounts.system_program,
+            &ctx.accounts.sender,
+            htlc,
+            keeper_bounty,
+        )?;
 
-        // msg!("hop chains: {:?}", hopChains);
-        // msg!("hop assets: {:?}", hopAssets);
-        // msg!("hop addresses: {:?}", hopAddresses);
+        emit!(TokenCommitted {
+            Id,
+            hopChains,
+            hopAssets,
+            hopAddresses: hopAddress,
+            dst_chain,
+            dst_address,
+            dst_asset,
+            sender: htlc.sender,
+            src_receiver,
+            src_asset,
+            amount,
+            timelock,
+            keeper_bounty,
+            token_contract: htlc.token_contract,
+        });
         Ok(Id)
     }
 
     /// @dev Sender / Payer sets up a new hash time lock contract depositing the
     /// funds and providing the reciever and terms.
     /// @param src_receiver receiver of the funds.
-    /// @param hashlock A sha-256 hash hashlock.
+    /// @param hashlock hash of the secret under hash_algorithm.
     /// @param timelock UNIX epoch seconds time that the lock expires at.
     ///                  Refunds can be made after this time.
+    /// @param reward tokens deposited on top of amount for whoever redeems
+    ///                  after reward_timelock.
+    /// @param reward_timelock UNIX epoch seconds time after which the reward
+    ///                  goes to the redeemer instead of back to the sender.
+    /// @param hash_algorithm hash the hashlock is taken with, Sha256 or Keccak256.
+    /// @param keeper_bounty lamports deposited in the HTLC account for whoever
+    ///                  refunds the HTLC once it expires. Zero for none.
     /// @return Id of the new HTLC. This is needed for subsequent calls.
     pub fn lock(
         ctx: Context<Lock>,
@@ -202,45 +1043,106 @@ pub mod anchor_htlc {
         src_asset: String,
         src_receiver: Pubkey,
         amount: u64,
+        reward: u64,
+        reward_timelock: u64,
+        hash_algorithm: HashAlgorithm,
+        keeper_bounty: u64,
         lock_bump: u8,
     ) -> Result<[u8; 32]> {
         let clock = Clock::get().unwrap();
-        require!(
-            timelock > clock.unix_timestamp.try_into().unwrap(),
-            HTLCError::NotFutureTimeLock
-        );
+        check_future_timelock(timelock, clock.unix_timestamp).map_err(HTLCError::from)?;
+        ctx.accounts
+            .config
+            .check_timelock(timelock, clock.unix_timestamp)?;
         require!(amount != 0, HTLCError::FundsNotSent);
+        check_reward_timelock(reward, reward_timelock, timelock, clock.unix_timestamp)
+            .map_err(HTLCError::from)?;
+        validate_htlc_strings(&dst_chain, &dst_asset, &dst_address, &src_asset)
+            .map_err(HTLCError::from)?;
+        check_allowed_mint(&ctx.accounts.allowed_mint, &src_asset)?;
+        let total = amount.checked_add(reward).ok_or(HTLCError::FundsNotSent)?;
         let htlc = &mut ctx.accounts.htlc;
 
         let bump_vector = lock_bump.to_le_bytes();
-        let inner = vec![Id.as_ref(), bump_vector.as_ref()];
+        let inner = vec![
+            b"htlc".as_ref(),
+            ctx.accounts.sender.key.as_ref(),
+            Id.as_ref(),
+            bump_vector.as_ref(),
+        ];
         let outer = vec![inner.as_slice()];
         let transfer_context = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
-            Transfer {
+            TransferChecked {
                 from: ctx.accounts.sender_token_account.to_account_info(),
+                mint: ctx.accounts.token_contract.to_account_info(),
                 to: ctx.accounts.htlc_token_account.to_account_info(),
                 authority: ctx.accounts.sender.to_account_info(),
             },
             outer.as_slice(),
         );
-        anchor_spl::token::transfer(transfer_context, amount)?;
+        token_interface::transfer_checked(
+            transfer_context,
+            total,
+            ctx.accounts.token_contract.decimals,
+        )?;
+        // The vault is new, so its balance is what actually arrived after any transfer fee.
+        ctx.accounts.htlc_token_account.reload()?;
+        let received = ctx.accounts.htlc_token_account.amount;
+        require!(received > reward, HTLCError::FundsNotSent);
+        let amount = received - reward;
 
-        htlc.dst_address = dst_address;
-        htlc.dst_chain = dst_chain;
-        htlc.dst_asset = dst_asset;
-        htlc.src_asset = src_asset;
+        htlc.dst_address = dst_address.clone();
+        htlc.dst_chain = dst_chain.clone();
+        htlc.dst_asset = dst_asset.clone();
+        htlc.src_asset = src_asset.clone();
         htlc.sender = *ctx.accounts.sender.to_account_info().key;
         htlc.src_receiver = src_receiver;
+        htlc.payer = *ctx.accounts.sender.to_account_info().key;
+        htlc.hop_chains = Vec::new();
+        htlc.hop_assets = Vec::new();
+        htlc.hop_addresses = Vec::new();
         htlc.hashlock = hashlock;
+        htlc.hash_algorithm = hash_algorithm;
         htlc.secret = [0u8; 32];
         htlc.amount = amount;
         htlc.timelock = timelock;
+        htlc.reward = reward;
+        htlc.reward_timelock = reward_timelock;
+        htlc.keeper_bounty = keeper_bounty;
+        htlc.fee_bps = ctx.accounts.config.fee_bps;
         htlc.token_contract = *ctx.accounts.token_contract.to_account_info().key;
         htlc.token_wallet = *ctx.accounts.htlc_token_account.to_account_info().key;
-        htlc.redeemed = false;
-        htlc.refunded = false;
+        htlc.status = HTLCStatus::Locked;
+        htlc.index_page = append_contract(
+            &mut ctx.accounts.contracts_index,
+            &mut ctx.accounts.contracts_page,
+            Id,
+        );
+        deposit_keeper_bounty(
+            &ctx.accounts.system_program,
+            &ctx.accounts.sender,
+            htlc,
+            keeper_bounty,
+        )?;
 
+        emit!(TokenLocked {
+            Id,
+            hashlock,
+            hash_algorithm,
+            dst_chain,
+            dst_address,
+            dst_asset,
+            sender: htlc.sender,
+            src_receiver,
+            src_asset,
+            amount,
+            reward,
+            reward_timelock,
+            timelock,
+            keeper_bounty,
+            token_contract: htlc.token_contract,
+        });
         Ok(Id)
     }
 
@@ -253,99 +1155,478 @@ pub mod anchor_htlc {
         Id: [u8; 32],
         hashlock: [u8; 32],
         timelock: u64,
+        hash_algorithm: HashAlgorithm,
     ) -> Result<[u8; 32]> {
-        let clock = Clock::get().unwrap();
-        require!(
-            timelock > clock.unix_timestamp.try_into().unwrap(),
-            HTLCError::NotFutureTimeLock
-        );
+        let now = Clock::get()?.unix_timestamp;
+        let htlc = &mut ctx.accounts.htlc;
+        let mut state = htlc.state();
+        state
+            .add_lock(hashlock, hash_algorithm.into(), timelock, now)
+            .map_err(HTLCError::from)?;
+        ctx.accounts.config.check_timelock(timelock, now)?;
+        htlc.set_state(state);
+
+        emit!(TokenLockAdded {
+            Id,
+            hashlock,
+            timelock,
+            hash_algorithm,
+        });
+        Ok(Id)
+    }
+
+    /// @dev Called by anyone to add hashlock to the HTLC on behalf of the sender.
+    /// The transaction must include an Ed25519 program instruction, right before
+    /// this one, verifying the sender's signature over program_id || Id ||
+    /// hashlock || timelock as big-endian u64 || hash algorithm tag (0 for
+    /// SHA-256, 1 for Keccak-256), 105 bytes in all.
+    ///
+    /// @param Id of the HTLC.
+    /// @param hashlock to be added.
+    /// @param timelock to be set.
+    /// @param hash_algorithm hash the hashlock is taken with.
+    /// @param signature ed25519 signature of the sender.
+    pub fn add_lock_sig(
+        ctx: Context<AddLockSig>,
+        Id: [u8; 32],
+        hashlock: [u8; 32],
+        timelock: u64,
+        hash_algorithm: HashAlgorithm,
+        signature: [u8; 64],
+    ) -> Result<[u8; 32]> {
+        verify_add_lock_sig(
+            &ctx.accounts.ix_sysvar,
+            &ctx.accounts.htlc.sender,
+            Id,
+            hashlock,
+            timelock,
+            hash_algorithm,
+            signature,
+        )?;
 
+        let now = Clock::get()?.unix_timestamp;
         let htlc = &mut ctx.accounts.htlc;
+        let mut state = htlc.state();
+        state
+            .add_lock(hashlock, hash_algorithm.into(), timelock, now)
+            .map_err(HTLCError::from)?;
+        ctx.accounts.config.check_timelock(timelock, now)?;
+        htlc.set_state(state);
 
-        htlc.hashlock = hashlock;
-        htlc.timelock = timelock;
+        emit!(TokenLockAdded {
+            Id,
+            hashlock,
+            timelock,
+            hash_algorithm,
+        });
+        Ok(Id)
+    }
+
+    /// @dev Called by the sender to give the src_receiver more time, for example
+    /// when the destination chain is congested. The timelock of an open HTLC only
+    /// ever moves later, and no further than the Config's maximum timelock window
+    /// from now.
+    ///
+    /// @param Id of the HTLC.
+    /// @param timelock the new timelock, after the current one.
+    pub fn extend_timelock(
+        ctx: Context<ExtendTimelock>,
+        Id: [u8; 32],
+        timelock: u64,
+    ) -> Result<[u8; 32]> {
+        let now = Clock::get()?.unix_timestamp;
+        let htlc = &mut ctx.accounts.htlc;
+        let mut state = htlc.state();
+        state
+            .extend_timelock(timelock, now)
+            .map_err(HTLCError::from)?;
+        ctx.accounts.config.check_max_timelock(timelock, now)?;
+        htlc.set_state(state);
 
+        emit!(TimelockExtended { Id, timelock });
         Ok(Id)
     }
 
     /// @dev Called by the src_receiver once they know the secret of the hashlock.
     /// This will transfer the locked funds to the HTLC's src_receiver's address.
+    /// The reward, if any, goes back to the sender before reward_timelock and to
+    /// the redeemer after it, and the keeper bounty, if any, back to the sender.
+    /// reward_token_account, the redeemer's, is only required when the redeemer
+    /// is paid the reward.
+    /// The protocol fee the HTLC was opened with is taken from the amount into
+    /// the fee vault of the mint, which is only required when the fee is not 0.
+    /// The HTLC account is closed and its rent returned to the payer; the revealed
+    /// secret remains available through the TokenRedeemed event. For a Token-2022
+    /// mint, token_contract has to be writable: the transfer fees withheld in the
+    /// HTLC token account are harvested to it before that account is closed.
     ///
     /// @param Id of the HTLC.
-    /// @param secret sha256(secret) should equal the contract hashlock.
+    /// @param secret hash_algorithm(secret) should equal the contract hashlock.
+    /// @return the protocol fee taken, in token base units.
     pub fn redeem(
         ctx: Context<Redeem>,
         Id: [u8; 32],
         secret: [u8; 32],
         htlc_bump: u8,
-    ) -> Result<bool> {
+    ) -> Result<u64> {
         let htlc = &mut ctx.accounts.htlc;
-        let mut hasher = Sha256::new();
-        hasher.update(secret.clone());
-        let hash = hasher.finalize();
-        require!([0u8; 32] != htlc.hashlock, HTLCError::HashlockNotSet);
-        require!(hash == htlc.hashlock.into(), HTLCError::HashlockNoMatch);
-
-        htlc.redeemed = true;
+        let mut state = htlc.state();
+        state
+            .redeem(&hash_secret(htlc.hash_algorithm, &secret))
+            .map_err(HTLCError::from)?;
+        htlc.set_state(state);
         htlc.secret = secret;
 
+        let bump_vector = htlc_bump.to_le_bytes();
+        let htlc_seeds = [
+            b"htlc".as_ref(),
+            ctx.accounts.sender.key.as_ref(),
+            Id.as_ref(),
+            bump_vector.as_ref(),
+        ];
+        let payout = redeem_payout(
+            htlc.amount,
+            htlc.reward,
+            htlc.reward_timelock,
+            Clock::get()?.unix_timestamp,
+            ctx.accounts.user_signing.key() == htlc.src_receiver,
+            htlc.fee_bps,
+        );
+        let reward_transfer = if payout.sender != 0 {
+            Some((
+                ctx.accounts.sender_token_account.to_account_info(),
+                payout.sender,
+            ))
+        } else if payout.redeemer != 0 {
+            let reward_token_account = ctx
+                .accounts
+                .reward_token_account
+                .as_ref()
+                .ok_or(HTLCError::RewardAccountMissing)?;
+            Some((reward_token_account.to_account_info(), payout.redeemer))
+        } else {
+            None
+        };
+
+        if payout.fee != 0 {
+            let fee_vault = ctx
+                .accounts
+                .fee_vault
+                .as_ref()
+                .ok_or(HTLCError::FeeVaultMissing)?;
+            transfer_htlc_out(
+                ctx.accounts.sender.to_account_info(),
+                htlc.to_account_info(),
+                &htlc_seeds,
+                &mut ctx.accounts.htlc_token_account,
+                &ctx.accounts.token_contract,
+                ctx.accounts.token_program.to_account_info(),
+                fee_vault.to_account_info(),
+                payout.fee,
+            )?;
+        }
         transfer_htlc_out(
             ctx.accounts.sender.to_account_info(),
-            Id,
             htlc.to_account_info(),
-            htlc_bump,
+            &htlc_seeds,
             &mut ctx.accounts.htlc_token_account,
+            &ctx.accounts.token_contract,
             ctx.accounts.token_program.to_account_info(),
             ctx.accounts.src_receiver_token_account.to_account_info(),
-            ctx.accounts.htlc.amount,
+            payout.src_receiver,
         )?;
+        if let Some((reward_wallet, reward)) = reward_transfer {
+            transfer_htlc_out(
+                ctx.accounts.sender.to_account_info(),
+                ctx.accounts.htlc.to_account_info(),
+                &htlc_seeds,
+                &mut ctx.accounts.htlc_token_account,
+                &ctx.accounts.token_contract,
+                ctx.accounts.token_program.to_account_info(),
+                reward_wallet,
+                reward,
+            )?;
+        }
 
-        Ok(true)
+        let keeper_bounty = ctx.accounts.htlc.keeper_bounty;
+        ctx.accounts.htlc.sub_lamports(keeper_bounty)?;
+        ctx.accounts.sender.add_lamports(keeper_bounty)?;
+        prune_contract(&mut ctx.accounts.contracts_page, Id);
+
+        emit!(TokenRedeemed {
+            Id,
+            redeem_address: ctx.accounts.user_signing.key(),
+            secret,
+            hashlock: ctx.accounts.htlc.hashlock,
+            fee: payout.fee,
+            status: HTLCStatus::Redeemed,
+        });
+        Ok(payout.fee)
     }
 
-    /// @dev Called by the sender if there was no redeem AND the time lock has
-    /// expired. This will refund the contract amount.
+    /// @dev Called by anyone if there was no redeem AND the time lock has
+    /// expired, e.g. a keeper refunding on behalf of a sender who is offline.
+    /// This will refund the contract amount to the sender and pay the keeper
+    /// bounty, if any, to the caller.
+    /// The HTLC account is closed and its rent returned to the payer. As in
+    /// `redeem`, token_contract has to be writable for a Token-2022 mint.
     ///
     /// @param Id of the HTLC to refund from.
     pub fn refund(ctx: Context<Refund>, Id: [u8; 32], htlc_bump: u8) -> Result<bool> {
         let htlc = &mut ctx.accounts.htlc;
+        let mut state = htlc.state();
+        state
+            .refund(Clock::get()?.unix_timestamp)
+            .map_err(HTLCError::from)?;
+        htlc.set_state(state);
 
-        htlc.refunded = true;
-
+        let bump_vector = htlc_bump.to_le_bytes();
+        let htlc_seeds = [
+            b"htlc".as_ref(),
+            ctx.accounts.sender.key.as_ref(),
+            Id.as_ref(),
+            bump_vector.as_ref(),
+        ];
         transfer_htlc_out(
             ctx.accounts.sender.to_account_info(),
-            Id,
             htlc.to_account_info(),
-            htlc_bump,
+            &htlc_seeds,
             &mut ctx.accounts.htlc_token_account,
+            &ctx.accounts.token_contract,
             ctx.accounts.token_program.to_account_info(),
             ctx.accounts.sender_token_account.to_account_info(),
-            ctx.accounts.htlc.amount,
+            ctx.accounts.htlc.amount + ctx.accounts.htlc.reward,
         )?;
 
-        Ok(true)
+        let keeper_bounty = ctx.accounts.htlc.keeper_bounty;
+        ctx.accounts.htlc.sub_lamports(keeper_bounty)?;
+        ctx.accounts.user_signing.add_lamports(keeper_bounty)?;
+        prune_contract(&mut ctx.accounts.contracts_page, Id);
+
+        emit!(TokenRefunded {
+            Id,
+            keeper: ctx.accounts.user_signing.key(),
+            keeper_bounty,
+            status: HTLCStatus::Refunded,
+        });
+        Ok(true)
+    }
+
+    /// @dev Redeems several HTLCs in one transaction, e.g. for a solver holding
+    /// the secrets of many. Each item is checked and paid out like `redeem`; an
+    /// item that fails its checks is skipped and the others still go through.
+    /// The accounts of each item follow in remaining_accounts, in item order:
+    /// htlc, contracts_page, htlc_token_account, token_contract, fee_vault,
+    /// src_receiver_token_account, sender_token_account, reward_token_account,
+    /// sender and payer, all but token_contract writable; token_contract too for
+    /// a Token-2022 mint, which its withheld transfer fees are harvested to
+    /// before the HTLC token account is closed. Unlike `redeem`, the
+    /// src_receiver token accounts have to exist already. An item's fee_vault
+    /// and reward_token_account are this program's id when, as in `redeem`,
+    /// they are not required.
+    /// An item takes up to about 60k compute units (two token transfers and
+    /// the close of the HTLC token account), so a full batch of MAX_BATCH_LEN
+    /// items needs a ComputeBudget limit of about 600k; simulate the
+    /// transaction for the exact figure. Without an address lookup table only
+    /// two or three items fit in a transaction.
+    ///
+    /// @param items Id and secret of each HTLC, at most MAX_BATCH_LEN.
+    /// @return for each item, 0 if it was redeemed or the code of the error it
+    /// was skipped with.
+    pub fn redeem_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RedeemBatch<'info>>,
+        items: Vec<RedeemBatchItem>,
+    ) -> Result<Vec<u32>> {
+        validate_batch(
+            items.len(),
+            ctx.remaining_accounts.len(),
+            REDEEM_BATCH_ACCOUNTS,
+        )
+        .map_err(HTLCError::from)?;
+        let now = Clock::get()?.unix_timestamp;
+        let user_signing = ctx.accounts.user_signing.to_account_info();
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let mut closes = Vec::with_capacity(items.len());
+        let results = items
+            .iter()
+            .zip(ctx.remaining_accounts.chunks(REDEEM_BATCH_ACCOUNTS))
+            .map(|(item, accounts)| {
+                redeem_batch_item(
+                    &user_signing,
+                    &token_program,
+                    item,
+                    accounts,
+                    now,
+                    &mut closes,
+                )
+            })
+            .collect::<Result<Vec<u32>>>()?;
+        for (htlc, sender, payer) in closes {
+            close_batch_htlc(htlc, sender, payer)?;
+        }
+        Ok(results)
+    }
+
+    /// @dev Refunds several expired HTLCs of one sender in one transaction. Each
+    /// item is checked and refunded like `refund`; an item that is not
+    /// refundable yet, or fails any other check, is skipped and the others
+    /// still go through. The accounts of each item follow in remaining_accounts,
+    /// in item order: htlc, contracts_page, htlc_token_account, token_contract,
+    /// sender_token_account and payer, all but token_contract writable;
+    /// token_contract too for a Token-2022 mint, as in `redeem_batch`.
+    /// An item takes up to about 45k compute units (a token transfer and the
+    /// close of the HTLC token account), so a full batch of MAX_BATCH_LEN items
+    /// needs a ComputeBudget limit of about 450k; simulate the transaction for
+    /// the exact figure.
+    ///
+    /// @param Ids of the HTLCs, at most MAX_BATCH_LEN.
+    /// @return for each item, 0 if it was refunded or the code of the error it
+    /// was skipped with.
+    pub fn refund_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RefundBatch<'info>>,
+        Ids: Vec<[u8; 32]>,
+    ) -> Result<Vec<u32>> {
+        validate_batch(
+            Ids.len(),
+            ctx.remaining_accounts.len(),
+            REFUND_BATCH_ACCOUNTS,
+        )
+        .map_err(HTLCError::from)?;
+        let now = Clock::get()?.unix_timestamp;
+        let user_signing = ctx.accounts.user_signing.to_account_info();
+        let sender = ctx.accounts.sender.to_account_info();
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let mut closes = Vec::with_capacity(Ids.len());
+        let results = Ids
+            .iter()
+            .zip(ctx.remaining_accounts.chunks(REFUND_BATCH_ACCOUNTS))
+            .map(|(Id, accounts)| {
+                refund_batch_item(
+                    &user_signing,
+                    &sender,
+                    &token_program,
+                    *Id,
+                    accounts,
+                    now,
+                    &mut closes,
+                )
+            })
+            .collect::<Result<Vec<u32>>>()?;
+        for (htlc, payer) in closes {
+            close_batch_htlc(htlc, &user_signing, payer)?;
+        }
+        Ok(results)
+    }
+
+    /// @dev Redeems an HTLC created at [Id], before the HTLC seeds were namespaced
+    /// by sender. The locked tokens go to the src_receiver and the rent of both
+    /// accounts back to the sender. As in `redeem`, token_contract has to be
+    /// writable for a Token-2022 mint.
+    ///
+    /// @param Id of the HTLC.
+    /// @param secret sha256(secret) should equal the contract hashlock.
+    pub fn redeem_legacy(
+        ctx: Context<RedeemLegacy>,
+        Id: [u8; 32],
+        secret: [u8; 32],
+    ) -> Result<bool> {
+        let htlc = load_legacy_htlc(&ctx.accounts.htlc)?;
+        require_keys_eq!(htlc.sender, ctx.accounts.sender.key(), HTLCError::NotSender);
+        require_keys_eq!(
+            htlc.src_receiver,
+            ctx.accounts.src_receiver.key(),
+            HTLCError::NotReciever
+        );
+        require_keys_eq!(
+            htlc.token_contract,
+            ctx.accounts.token_contract.key(),
+            HTLCError::NoToken
+        );
+        htlc.state()
+            .redeem(&hash_secret(HashAlgorithm::Sha256, &secret))
+            .map_err(HTLCError::from)?;
+
+        let bump_vector = ctx.bumps.htlc.to_le_bytes();
+        let htlc_seeds = [Id.as_ref(), bump_vector.as_ref()];
+        transfer_htlc_out(
+            ctx.accounts.sender.to_account_info(),
+            ctx.accounts.htlc.to_account_info(),
+            &htlc_seeds,
+            &mut ctx.accounts.htlc_token_account,
+            &ctx.accounts.token_contract,
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.src_receiver_token_account.to_account_info(),
+            htlc.amount,
+        )?;
+        close_legacy_htlc(&ctx.accounts.htlc, &ctx.accounts.sender)?;
+
+        emit!(TokenRedeemed {
+            Id,
+            redeem_address: ctx.accounts.user_signing.key(),
+            secret,
+            hashlock: htlc.hashlock,
+            fee: 0,
+            status: HTLCStatus::Redeemed,
+        });
+        Ok(true)
+    }
+
+    /// @dev Refunds an expired HTLC created at [Id], before the HTLC seeds were
+    /// namespaced by sender. As in `redeem`, token_contract has to be writable
+    /// for a Token-2022 mint.
+    ///
+    /// @param Id of the HTLC to refund from.
+    pub fn refund_legacy(ctx: Context<RefundLegacy>, Id: [u8; 32]) -> Result<bool> {
+        let htlc = load_legacy_htlc(&ctx.accounts.htlc)?;
+        require_keys_eq!(htlc.sender, ctx.accounts.sender.key(), HTLCError::NotSender);
+        require_keys_eq!(
+            htlc.token_contract,
+            ctx.accounts.token_contract.key(),
+            HTLCError::NoToken
+        );
+        htlc.state()
+            .refund(Clock::get()?.unix_timestamp)
+            .map_err(HTLCError::from)?;
+
+        let bump_vector = ctx.bumps.htlc.to_le_bytes();
+        let htlc_seeds = [Id.as_ref(), bump_vector.as_ref()];
+        transfer_htlc_out(
+            ctx.accounts.sender.to_account_info(),
+            ctx.accounts.htlc.to_account_info(),
+            &htlc_seeds,
+            &mut ctx.accounts.htlc_token_account,
+            &ctx.accounts.token_contract,
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.sender_token_account.to_account_info(),
+            htlc.amount,
+        )?;
+        close_legacy_htlc(&ctx.accounts.htlc, &ctx.accounts.sender)?;
+
+        emit!(TokenRefunded {
+            Id,
+            keeper: ctx.accounts.user_signing.key(),
+            keeper_bounty: 0,
+            status: HTLCStatus::Refunded,
+        });
+        Ok(true)
+    }
+
+    /// @dev Get the Ids of the HTLCs created by the sender, one page at a time.
+    /// Pages run from 0 up to the current_page of the sender's ContractsIndex.
+    /// @param sender creator of the HTLCs.
+    /// @param page of the sender's index.
+    pub fn get_contracts(
+        ctx: Context<GetContracts>,
+        _sender: Pubkey,
+        _page: u32,
+    ) -> Result<Vec<[u8; 32]>> {
+        Ok(ctx.accounts.contracts_page.ids.clone())
     }
 
     /// @dev Get HTLC details.
     /// @param Id of the HTLC.
     pub fn getDetails(ctx: Context<GetDetails>, Id: [u8; 32]) -> Result<HTLC> {
         let htlc = &ctx.accounts.htlc;
-
-        msg!("dst_address: {:?}", htlc.dst_address.clone());
-        msg!("dst_chain: {:?}", htlc.dst_chain.clone());
-        msg!("dst_asset: {:?}", htlc.dst_asset.clone());
-        msg!("src_asset: {:?}", htlc.src_asset);
-        msg!("sender: {:?}", htlc.sender);
-        msg!("src_receiver: {:?}", htlc.src_receiver);
-        msg!("hashlock: {:?}", hex::encode(htlc.hashlock));
-        msg!("secret: {:?}", hex::encode(htlc.secret.clone()));
-        msg!("amount: {:?}", htlc.amount);
-        msg!("timelock: {:?}", htlc.timelock);
-        msg!("token_contract: {:?}", htlc.token_contract);
-        msg!("token_wallet: {:?}", htlc.token_wallet);
-        msg!("redeemed: {:?}", htlc.redeemed);
-        msg!("refunded: {:?}", htlc.refunded);
-
         Ok(HTLC {
             dst_address: htlc.dst_address.clone(),
             dst_chain: htlc.dst_chain.clone(),
@@ -353,14 +1634,23 @@ pub mod anchor_htlc {
             src_asset: htlc.src_asset.clone(),
             sender: htlc.sender,
             src_receiver: htlc.src_receiver,
+            payer: htlc.payer,
+            hop_chains: htlc.hop_chains.clone(),
+            hop_assets: htlc.hop_assets.clone(),
+            hop_addresses: htlc.hop_addresses.clone(),
             hashlock: htlc.hashlock,
+            hash_algorithm: htlc.hash_algorithm,
             secret: htlc.secret.clone(),
             amount: htlc.amount,
             timelock: htlc.timelock,
+            reward: htlc.reward,
+            reward_timelock: htlc.reward_timelock,
+            keeper_bounty: htlc.keeper_bounty,
+            fee_bps: htlc.fee_bps,
             token_contract: htlc.token_contract,
             token_wallet: htlc.token_wallet,
-            redeemed: htlc.redeemed,
-            refunded: htlc.refunded,
+            index_page: htlc.index_page,
+            status: htlc.status,
         })
     }
 }
@@ -374,95 +1664,516 @@ pub struct HTLC {
     pub src_asset: String,
     pub sender: Pubkey,
     pub src_receiver: Pubkey,
+    pub payer: Pubkey,
+    pub hop_chains: Vec<String>,
+    pub hop_assets: Vec<String>,
+    pub hop_addresses: Vec<String>,
     pub hashlock: [u8; 32],
+    pub hash_algorithm: HashAlgorithm,
     pub secret: [u8; 32],
     pub amount: u64,   //TODO: check if this should be u256, though the spl uses u64
     pub timelock: u64, //TODO: check if this should be u256
+    pub reward: u64,
+    pub reward_timelock: u64,
+    /// Lamports paid to whoever refunds the HTLC, on top of the rent.
+    pub keeper_bounty: u64,
+    /// Protocol fee redeem takes from amount, in basis points: the Config's fee
+    /// when the HTLC was committed or locked.
+    pub fee_bps: u16,
+    pub token_contract: Pubkey,
+    pub token_wallet: Pubkey,
+    pub index_page: u32,
+    /// Committed until the hashlock is added and Locked from then on. Redeem and
+    /// refund close the account, so Redeemed and Refunded are only reported by
+    /// the TokenRedeemed and TokenRefunded events.
+    pub status: HTLCStatus,
+}
+
+impl HTLC {
+    /// Borsh size of an HTLC with empty strings and no hop route, discriminator included.
+    pub const BASE_LEN: usize = 8 // discriminator
+        + 4 * 4 // dst_address, dst_chain, dst_asset, src_asset length prefixes
+        + 32 * 3 // sender, src_receiver, payer
+        + 32 * 2 // hashlock, secret
+        + 1 // hash_algorithm
+        + 8 * 5 // amount, timelock, reward, reward_timelock, keeper_bounty
+        + 2 // fee_bps
+        + 32 * 2 // token_contract, token_wallet
+        + 4 // index_page
+        + 1; // status
+
+    /// Borsh size of an HTLC holding the given strings, without the hop route.
+    pub fn space(dst_chain: &str, dst_asset: &str, dst_address: &str, src_asset: &str) -> usize {
+        Self::BASE_LEN + dst_chain.len() + dst_asset.len() + dst_address.len() + src_asset.len()
+    }
+
+    /// The lifecycle fields train_htlc_core checks the HTLC's transitions against.
+    pub fn state(&self) -> HtlcState {
+        HtlcState {
+            hashlock: self.hashlock,
+            hash_algorithm: self.hash_algorithm.into(),
+            timelock: self.timelock,
+            status: self.status.into(),
+        }
+    }
+
+    pub fn set_state(&mut self, state: HtlcState) {
+        self.hashlock = state.hashlock;
+        self.hash_algorithm = state.hash_algorithm.into();
+        self.timelock = state.timelock;
+        self.status = state.status.into();
+    }
+}
+
+/// Layout of the HTLC accounts created at [Id], before the HTLC seeds were
+/// namespaced by sender. Only redeem_legacy and refund_legacy read it.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyHTLC {
+    pub dst_address: String,
+    pub dst_chain: String,
+    pub dst_asset: String,
+    pub src_asset: String,
+    pub sender: Pubkey,
+    pub src_receiver: Pubkey,
+    pub hashlock: [u8; 32],
+    pub secret: [u8; 32],
+    pub amount: u64,
+    pub timelock: u64,
     pub token_contract: Pubkey,
     pub token_wallet: Pubkey,
     pub redeemed: bool,
     pub refunded: bool,
 }
+
+impl LegacyHTLC {
+    pub fn state(&self) -> HtlcState {
+        HtlcState {
+            hashlock: self.hashlock,
+            hash_algorithm: train_htlc_core::HashAlgorithm::Sha256,
+            timelock: self.timelock,
+            status: HtlcStatus::from_flags(&self.hashlock, self.redeemed, self.refunded),
+        }
+    }
+}
+
+/// Ids of every HTLC in a ContractsPage fit in the 1024-byte return data of get_contracts.
+pub const MAX_CONTRACTS_PER_PAGE: usize = 31;
+
+/// Accounts each redeem_batch item takes from remaining_accounts.
+pub const REDEEM_BATCH_ACCOUNTS: usize = 10;
+
+/// Accounts each refund_batch item takes from remaining_accounts.
+pub const REFUND_BATCH_ACCOUNTS: usize = 6;
+
+#[account]
+#[derive(Default)]
+pub struct ContractsIndex {
+    pub current_page: u32,
+}
+
+#[account]
+#[derive(Default)]
+pub struct ContractsPage {
+    pub ids: Vec<[u8; 32]>,
+}
+
+#[account]
+#[derive(Default)]
+pub struct SenderNonce {
+    pub nonce: u64,
+}
+
+/// Program-wide settings at ["config"], created once by initialize_config.
+#[account]
+#[derive(Default)]
+pub struct Config {
+    pub admin: Pubkey,
+    /// The admin proposed by transfer_admin, until they accept_admin.
+    pub pending_admin: Option<Pubkey>,
+    /// While set, commit and lock fail with Paused.
+    pub paused: bool,
+    /// The protocol fee, in basis points of the amount, new HTLCs are opened
+    /// with and redeem takes.
+    pub fee_bps: u16,
+    /// The shortest time, in seconds, a new timelock may be ahead of the clock.
+    pub min_timelock_window: u64,
+    /// The longest time, in seconds, a new timelock may be ahead of the clock.
+    pub max_timelock_window: u64,
+}
+
+impl Config {
+    /// Borsh size of the Config, discriminator included.
+    pub const LEN: usize = 8 // discriminator
+        + 32 // admin
+        + 1 + 32 // pending_admin
+        + 1 // paused
+        + 2 // fee_bps
+        + 8 // min_timelock_window
+        + 8; // max_timelock_window
+
+    /// Fails unless the timelock is within the timelock window from `now`.
+    pub fn check_timelock(&self, timelock: u64, now: i64) -> Result<()> {
+        check_timelock_window(
+            timelock,
+            now,
+            self.min_timelock_window,
+            self.max_timelock_window,
+        )
+        .map_err(HTLCError::from)?;
+        Ok(())
+    }
+
+    /// Fails if the timelock is further than the maximum timelock window from
+    /// `now`.
+    pub fn check_max_timelock(&self, timelock: u64, now: i64) -> Result<()> {
+        check_timelock_window(timelock, now, 0, self.max_timelock_window)
+            .map_err(HTLCError::from)?;
+        Ok(())
+    }
+}
+
+/// A mint accepted by commit and lock, at ["allowed_mint", mint], created by
+/// allow_mint and closed by remove_mint.
+#[account]
+#[derive(Default)]
+pub struct AllowedMint {
+    /// The mint's canonical symbol, the src_asset its HTLCs have to use.
+    pub src_asset: String,
+}
+
+impl AllowedMint {
+    /// Borsh size of an AllowedMint with the longest src_asset, discriminator
+    /// included.
+    pub const LEN: usize = 8 // discriminator
+        + 4 + MAX_ASSET_LEN; // src_asset
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    /// The ProgramData of this program, whose upgrade authority has to be the admin.
+    #[account(
+        seeds = [crate::ID.as_ref()],
+        bump,
+        seeds::program = bpf_loader_upgradeable::ID,
+        constraint = program_data.upgrade_authority_address == Some(admin.key()) @HTLCError::NotUpgradeAuthority,
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    #[account(
+        init,
+        payer = admin,
+        space = Config::LEN,
+        seeds = [b"config".as_ref()],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigAdmin<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config".as_ref()],
+        bump,
+        has_one = admin @HTLCError::NotAdmin,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    pub pending_admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config".as_ref()],
+        bump,
+        constraint = config.pending_admin == Some(pending_admin.key()) @HTLCError::NotPendingAdmin,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct AllowMint<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"config".as_ref()],
+        bump,
+        has_one = admin @HTLCError::NotAdmin,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = AllowedMint::LEN,
+        seeds = [
+            b"allowed_mint".as_ref(),
+            token_contract.key().as_ref()
+        ],
+        bump,
+    )]
+    pub allowed_mint: Account<'info, AllowedMint>,
+    pub token_contract: InterfaceAccount<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveMint<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"config".as_ref()],
+        bump,
+        has_one = admin @HTLCError::NotAdmin,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        close = admin,
+        seeds = [
+            b"allowed_mint".as_ref(),
+            token_contract.key().as_ref()
+        ],
+        bump,
+    )]
+    pub allowed_mint: Account<'info, AllowedMint>,
+    ///CHECK: The mint, only used for the allowed_mint seeds
+    pub token_contract: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"config".as_ref()],
+        bump,
+        has_one = admin @HTLCError::NotAdmin,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [
+            b"fee_vault".as_ref(),
+            token_contract.key().as_ref()
+        ],
+        bump,
+    )]
+    pub fee_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = recipient_token_account.mint == token_contract.key() @HTLCError::NoToken,
+    )]
+    pub recipient_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_contract: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
 #[derive(Accounts)]
-#[instruction(Id: [u8;32], commit_bump: u8)]
+#[instruction(Id: [u8;32], hopChains: Vec<String>, hopAssets: Vec<String>, hopAddress: Vec<String>, dst_chain: String, dst_asset: String, dst_address: String, src_asset: String)]
 pub struct Commit<'info> {
     #[account(mut)]
     pub sender: Signer<'info>,
+    #[account(
+        seeds = [b"config".as_ref()],
+        bump,
+        constraint = !config.paused @HTLCError::Paused,
+    )]
+    pub config: Box<Account<'info, Config>>,
 
     #[account(
         init,
         payer = sender,
-        space = size_of::<HTLC>() + 28,
+        space = HTLC::space(&dst_chain, &dst_asset, &dst_address, &src_asset)
+            + hop_route_space(&hopChains, &hopAssets, &hopAddress),
         seeds = [
+            b"htlc".as_ref(),
+            sender.key().as_ref(),
             Id.as_ref()
         ],
         bump,
     )]
     pub htlc: Box<Account<'info, HTLC>>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = 8 + size_of::<ContractsIndex>(),
+        seeds = [
+            b"contracts_index".as_ref(),
+            sender.key().as_ref()
+        ],
+        bump,
+    )]
+    pub contracts_index: Box<Account<'info, ContractsIndex>>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = 8 + 4 + 32 * MAX_CONTRACTS_PER_PAGE,
+        seeds = [
+            b"contracts_page".as_ref(),
+            sender.key().as_ref(),
+            contracts_index.current_page.to_le_bytes().as_ref()
+        ],
+        bump,
+    )]
+    pub contracts_page: Box<Account<'info, ContractsPage>>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = 8 + size_of::<SenderNonce>(),
+        seeds = [
+            b"nonce".as_ref(),
+            sender.key().as_ref()
+        ],
+        bump,
+    )]
+    pub sender_nonce: Box<Account<'info, SenderNonce>>,
     #[account(
         init,
         payer = sender,
         seeds = [
             b"htlc_token_account".as_ref(),
+            sender.key().as_ref(),
             Id.as_ref()
         ],
         bump,
         token::mint=token_contract,
         token::authority=htlc,
+        token::token_program=token_program,
+    )]
+    pub htlc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        seeds = [
+            b"fee_vault".as_ref(),
+            token_contract.key().as_ref()
+        ],
+        bump,
+        token::mint=token_contract,
+        token::authority=config,
+        token::token_program=token_program,
+    )]
+    pub fee_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_contract: InterfaceAccount<'info, Mint>,
+    ///CHECK: The mint's AllowedMint, which does not exist for a mint allow_mint
+    /// has not accepted; checked by check_allowed_mint.
+    #[account(
+        seeds = [
+            b"allowed_mint".as_ref(),
+            token_contract.key().as_ref()
+        ],
+        bump,
     )]
-    pub htlc_token_account: Box<Account<'info, TokenAccount>>,
-    pub token_contract: Account<'info, Mint>,
+    pub allowed_mint: UncheckedAccount<'info>,
     #[account(
         mut,
         constraint=sender_token_account.owner == sender.key() @HTLCError::NotSender,
         constraint=sender_token_account.mint == token_contract.key() @HTLCError::NoToken,
     )]
-    pub sender_token_account: Account<'info, TokenAccount>,
+    pub sender_token_account: InterfaceAccount<'info, TokenAccount>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-#[instruction(Id: [u8; 32], lock_bump: u8)]
+#[instruction(Id: [u8; 32], hashlock: [u8; 32], timelock: u64, dst_chain: String, dst_address: String, dst_asset: String, src_asset: String)]
 pub struct Lock<'info> {
     #[account(mut)]
     pub sender: Signer<'info>,
+    #[account(
+        seeds = [b"config".as_ref()],
+        bump,
+        constraint = !config.paused @HTLCError::Paused,
+    )]
+    pub config: Box<Account<'info, Config>>,
 
     #[account(
         init,
         payer = sender,
-        space = size_of::<HTLC>() + 28,
-        // space = 256,
+        space = HTLC::space(&dst_chain, &dst_asset, &dst_address, &src_asset)
+            + hop_route_space(&[], &[], &[]),
         seeds = [
+            b"htlc".as_ref(),
+            sender.key().as_ref(),
             Id.as_ref()
         ],
         bump,
     )]
     pub htlc: Box<Account<'info, HTLC>>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = 8 + size_of::<ContractsIndex>(),
+        seeds = [
+            b"contracts_index".as_ref(),
+            sender.key().as_ref()
+        ],
+        bump,
+    )]
+    pub contracts_index: Box<Account<'info, ContractsIndex>>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = 8 + 4 + 32 * MAX_CONTRACTS_PER_PAGE,
+        seeds = [
+            b"contracts_page".as_ref(),
+            sender.key().as_ref(),
+            contracts_index.current_page.to_le_bytes().as_ref()
+        ],
+        bump,
+    )]
+    pub contracts_page: Box<Account<'info, ContractsPage>>,
     #[account(
         init,
         payer = sender,
         seeds = [
             b"htlc_token_account".as_ref(),
+            sender.key().as_ref(),
             Id.as_ref()
         ],
         bump,
         token::mint=token_contract,
         token::authority=htlc,
+        token::token_program=token_program,
+    )]
+    pub htlc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        seeds = [
+            b"fee_vault".as_ref(),
+            token_contract.key().as_ref()
+        ],
+        bump,
+        token::mint=token_contract,
+        token::authority=config,
+        token::token_program=token_program,
     )]
-    pub htlc_token_account: Box<Account<'info, TokenAccount>>,
+    pub fee_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_contract: Account<'info, Mint>,
+    pub token_contract: InterfaceAccount<'info, Mint>,
+    ///CHECK: The mint's AllowedMint, which does not exist for a mint allow_mint
+    /// has not accepted; checked by check_allowed_mint.
+    #[account(
+        seeds = [
+            b"allowed_mint".as_ref(),
+            token_contract.key().as_ref()
+        ],
+        bump,
+    )]
+    pub allowed_mint: UncheckedAccount<'info>,
     #[account(
         mut,
         constraint=sender_token_account.owner == sender.key() @HTLCError::NotSender,
         constraint=sender_token_account.mint == token_contract.key() @ HTLCError::NoToken,
     )]
-    pub sender_token_account: Box<Account<'info, TokenAccount>>,
+    pub sender_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
@@ -476,42 +2187,80 @@ pub struct Redeem<'info> {
     #[account(
         mut,
         seeds = [
+            b"htlc".as_ref(),
+            sender.key().as_ref(),
             Id.as_ref()
         ],
         bump,
         has_one = sender @HTLCError::NotSender,
         has_one = src_receiver @HTLCError::NotReciever,
         has_one = token_contract @HTLCError::NoToken,
-        constraint = !htlc.redeemed @ HTLCError::AlreadyRedeemed,
-        constraint = !htlc.refunded @ HTLCError::AlreadyRefunded,
+        has_one = payer @HTLCError::NotPayer,
+        close = payer,
     )]
     pub htlc: Box<Account<'info, HTLC>>,
+    #[account(
+        mut,
+        seeds = [
+            b"contracts_page".as_ref(),
+            htlc.sender.as_ref(),
+            htlc.index_page.to_le_bytes().as_ref()
+        ],
+        bump,
+    )]
+    pub contracts_page: Box<Account<'info, ContractsPage>>,
     #[account(
         mut,
         seeds = [
             b"htlc_token_account".as_ref(),
+            sender.key().as_ref(),
             Id.as_ref()
         ],
         bump,
     )]
-    pub htlc_token_account: Box<Account<'info, TokenAccount>>,
+    pub htlc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            b"fee_vault".as_ref(),
+            token_contract.key().as_ref()
+        ],
+        bump,
+    )]
+    pub fee_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
     #[account(
         init_if_needed,
         payer = user_signing,
         associated_token::mint = token_contract,
         associated_token::authority = src_receiver,
+        associated_token::token_program = token_program,
+    )]
+    pub src_receiver_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint=sender_token_account.owner == htlc.sender @HTLCError::NotSender,
+        constraint=sender_token_account.mint == token_contract.key() @HTLCError::NoToken,
+    )]
+    pub sender_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint=reward_token_account.owner == user_signing.key() @HTLCError::UnauthorizedAccess,
+        constraint=reward_token_account.mint == token_contract.key() @HTLCError::NoToken,
     )]
-    pub src_receiver_token_account: Account<'info, TokenAccount>,
+    pub reward_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
     ///CHECK: The sender
     #[account(mut)]
     sender: UncheckedAccount<'info>,
     ///CHECK: The reciever
     pub src_receiver: UncheckedAccount<'info>,
-    token_contract: Account<'info, Mint>,
+    ///CHECK: The payer of the HTLC account rent
+    #[account(mut)]
+    payer: UncheckedAccount<'info>,
+    token_contract: InterfaceAccount<'info, Mint>,
 
     system_program: Program<'info, System>,
-    token_program: Program<'info, Token>,
+    token_program: Interface<'info, TokenInterface>,
     associated_token_program: Program<'info, AssociatedToken>,
     rent: Sysvar<'info, Rent>,
 }
@@ -524,43 +2273,166 @@ pub struct Refund<'info> {
 
     #[account(mut,
     seeds = [
-        //b"htlc",
+        b"htlc".as_ref(),
+        sender.key().as_ref(),
         Id.as_ref()
     ],
     bump = htlc_bump,
     has_one = sender @HTLCError::NotSender,
     has_one = token_contract @HTLCError::NoToken,
-    constraint = !htlc.refunded @ HTLCError::AlreadyRefunded,
-    constraint = !htlc.redeemed @ HTLCError::AlreadyRedeemed,
-    constraint = Clock::get().unwrap().unix_timestamp >= htlc.timelock.try_into().unwrap() @ HTLCError::NotPastTimeLock,
+    has_one = payer @HTLCError::NotPayer,
+    close = payer,
     )]
     pub htlc: Box<Account<'info, HTLC>>,
+    #[account(
+        mut,
+        seeds = [
+            b"contracts_page".as_ref(),
+            htlc.sender.as_ref(),
+            htlc.index_page.to_le_bytes().as_ref()
+        ],
+        bump,
+    )]
+    pub contracts_page: Box<Account<'info, ContractsPage>>,
     #[account(
         mut,
         seeds = [
             b"htlc_token_account".as_ref(),
+            sender.key().as_ref(),
             Id.as_ref()
         ],
         bump,
     )]
-    pub htlc_token_account: Box<Account<'info, TokenAccount>>,
+    pub htlc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     ///CHECK: The sender
     #[account(mut)]
     sender: UncheckedAccount<'info>,
-    token_contract: Account<'info, Mint>,
+    ///CHECK: The payer of the HTLC account rent
+    #[account(mut)]
+    payer: UncheckedAccount<'info>,
+    token_contract: InterfaceAccount<'info, Mint>,
 
     #[account(
         mut,
         constraint=htlc.sender.key() == sender_token_account.owner @HTLCError::NotSender,
         constraint=sender_token_account.mint == token_contract.key() @HTLCError::NoToken,)]
-    pub sender_token_account: Account<'info, TokenAccount>,
+    pub sender_token_account: InterfaceAccount<'info, TokenAccount>,
 
     system_program: Program<'info, System>,
-    token_program: Program<'info, Token>,
+    token_program: Interface<'info, TokenInterface>,
     rent: Sysvar<'info, Rent>,
 }
 
+/// One HTLC of a redeem_batch call.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct RedeemBatchItem {
+    pub Id: [u8; 32],
+    pub secret: [u8; 32],
+}
+
+#[derive(Accounts)]
+pub struct RedeemBatch<'info> {
+    #[account(mut)]
+    user_signing: Signer<'info>,
+    token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct RefundBatch<'info> {
+    #[account(mut)]
+    user_signing: Signer<'info>,
+    ///CHECK: The sender of every HTLC in the batch
+    #[account(mut)]
+    sender: UncheckedAccount<'info>,
+    token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(Id: [u8;32])]
+pub struct RedeemLegacy<'info> {
+    #[account(mut)]
+    user_signing: Signer<'info>,
+
+    ///CHECK: The legacy HTLC, checked by load_legacy_htlc
+    #[account(
+        mut,
+        seeds = [
+            Id.as_ref()
+        ],
+        bump,
+    )]
+    pub htlc: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"htlc_token_account".as_ref(),
+            Id.as_ref()
+        ],
+        bump,
+    )]
+    pub htlc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user_signing,
+        associated_token::mint = token_contract,
+        associated_token::authority = src_receiver,
+        associated_token::token_program = token_program,
+    )]
+    pub src_receiver_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    ///CHECK: The sender
+    #[account(mut)]
+    sender: UncheckedAccount<'info>,
+    ///CHECK: The reciever
+    pub src_receiver: UncheckedAccount<'info>,
+    token_contract: Box<InterfaceAccount<'info, Mint>>,
+
+    system_program: Program<'info, System>,
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+#[instruction(Id: [u8;32])]
+pub struct RefundLegacy<'info> {
+    #[account(mut)]
+    user_signing: Signer<'info>,
+
+    ///CHECK: The legacy HTLC, checked by load_legacy_htlc
+    #[account(
+        mut,
+        seeds = [
+            Id.as_ref()
+        ],
+        bump,
+    )]
+    pub htlc: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"htlc_token_account".as_ref(),
+            Id.as_ref()
+        ],
+        bump,
+    )]
+    pub htlc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    ///CHECK: The sender
+    #[account(mut)]
+    sender: UncheckedAccount<'info>,
+    token_contract: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint=sender_token_account.owner == sender.key() @HTLCError::NotSender,
+        constraint=sender_token_account.mint == token_contract.key() @HTLCError::NoToken,)]
+    pub sender_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    system_program: Program<'info, System>,
+    token_program: Interface<'info, TokenInterface>,
+}
+
 #[derive(Accounts)]
 #[instruction(Id: [u8;32])]
 pub struct AddLock<'info> {
@@ -569,31 +2441,96 @@ pub struct AddLock<'info> {
 
     #[account(mut,
     seeds = [
+        b"htlc".as_ref(),
+        sender.key().as_ref(),
         Id.as_ref()
     ],
     bump,
-    constraint = !htlc.redeemed @ HTLCError::AlreadyRedeemed,
-    constraint = !htlc.refunded @ HTLCError::AlreadyRefunded,
     constraint = htlc.sender == sender.key() @ HTLCError::UnauthorizedAccess,
-    constraint = htlc.hashlock == [0u8;32] @ HTLCError::HashlockAlreadySet,
     )]
     pub htlc: Box<Account<'info, HTLC>>,
+    #[account(
+        seeds = [b"config".as_ref()],
+        bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
 
     system_program: Program<'info, System>,
     rent: Sysvar<'info, Rent>,
 }
 
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32])]
+pub struct AddLockSig<'info> {
+    payer: Signer<'info>,
+    #[account(mut,
+    seeds = [
+        b"htlc".as_ref(),
+        htlc.sender.as_ref(),
+        Id.as_ref()
+    ],
+    bump,
+    )]
+    pub htlc: Box<Account<'info, HTLC>>,
+    #[account(
+        seeds = [b"config".as_ref()],
+        bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    ///CHECK: The instructions sysvar
+    #[account(address = instructions_sysvar::ID)]
+    ix_sysvar: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32])]
+pub struct ExtendTimelock<'info> {
+    sender: Signer<'info>,
+
+    #[account(mut,
+    seeds = [
+        b"htlc".as_ref(),
+        sender.key().as_ref(),
+        Id.as_ref()
+    ],
+    bump,
+    constraint = htlc.sender == sender.key() @ HTLCError::UnauthorizedAccess,
+    )]
+    pub htlc: Box<Account<'info, HTLC>>,
+    #[account(
+        seeds = [b"config".as_ref()],
+        bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+}
+
 #[derive(Accounts)]
 #[instruction(Id: [u8;32])]
 pub struct GetDetails<'info> {
     #[account(
         seeds = [
+            b"htlc".as_ref(),
+            htlc.sender.as_ref(),
             Id.as_ref()
         ],
         bump,
     )]
     pub htlc: Box<Account<'info, HTLC>>,
 }
+
+#[derive(Accounts)]
+#[instruction(sender: Pubkey, page: u32)]
+pub struct GetContracts<'info> {
+    #[account(
+        seeds = [
+            b"contracts_page".as_ref(),
+            sender.as_ref(),
+            page.to_le_bytes().as_ref()
+        ],
+        bump,
+    )]
+    pub contracts_page: Box<Account<'info, ContractsPage>>,
+}
 #[derive(Accounts)]
 
 pub struct GetCommitId<'info> {
@@ -601,56 +2538,242 @@ pub struct GetCommitId<'info> {
     pub sender: UncheckedAccount<'info>,
     ///CHECK: The reciever
     pub receiver: UncheckedAccount<'info>,
+    ///CHECK: The sender's nonce, which does not exist before the first commit
+    #[account(
+        seeds = [
+            b"nonce".as_ref(),
+            sender.key().as_ref()
+        ],
+        bump,
+    )]
+    pub sender_nonce: UncheckedAccount<'info>,
+}
+#[event]
+pub struct TokenCommitted {
+    pub Id: [u8; 32],
+    pub hopChains: Vec<String>,
+    pub hopAssets: Vec<String>,
+    pub hopAddresses: Vec<String>,
+    pub dst_chain: String,
+    pub dst_address: String,
+    pub dst_asset: String,
+    pub sender: Pubkey,
+    pub src_receiver: Pubkey,
+    pub src_asset: String,
+    pub amount: u64,
+    pub timelock: u64,
+    pub keeper_bounty: u64,
+    pub token_contract: Pubkey,
+}
+
+#[event]
+pub struct TokenLocked {
+    pub Id: [u8; 32],
+    pub hashlock: [u8; 32],
+    pub hash_algorithm: HashAlgorithm,
+    pub dst_chain: String,
+    pub dst_address: String,
+    pub dst_asset: String,
+    pub sender: Pubkey,
+    pub src_receiver: Pubkey,
+    pub src_asset: String,
+    pub amount: u64,
+    pub reward: u64,
+    pub reward_timelock: u64,
+    pub timelock: u64,
+    pub keeper_bounty: u64,
+    pub token_contract: Pubkey,
+}
+
+#[event]
+pub struct TokenLockAdded {
+    pub Id: [u8; 32],
+    pub hashlock: [u8; 32],
+    pub timelock: u64,
+    pub hash_algorithm: HashAlgorithm,
+}
+
+#[event]
+pub struct TimelockExtended {
+    pub Id: [u8; 32],
+    pub timelock: u64,
+}
+
+#[event]
+pub struct TokenRedeemed {
+    pub Id: [u8; 32],
+    pub redeem_address: Pubkey,
+    pub secret: [u8; 32],
+    pub hashlock: [u8; 32],
+    pub fee: u64,
+    pub status: HTLCStatus,
+}
+
+#[event]
+pub struct TokenRefunded {
+    pub Id: [u8; 32],
+    pub keeper: Pubkey,
+    pub keeper_bounty: u64,
+    pub status: HTLCStatus,
+}
+
+#[event]
+pub struct AdminTransferStarted {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+}
+
+#[event]
+pub struct AdminTransferred {
+    pub previous_admin: Pubkey,
+    pub admin: Pubkey,
+}
+
+#[event]
+pub struct PauseSet {
+    pub paused: bool,
 }
-// #[event]
-// pub struct TokenCommitted {
-//     pub commitId: [u8; 32],
-//     pub hopChains: Vec<String>,
-//     pub hopAssets: Vec<String>,
-//     pub hopAddress: Vec<String>,
-//     pub dst_chain: String,
-//     pub dst_address: String,
-//     pub dst_asset: String,
-//     pub sender: Pubkey,
-//     pub src_receiver: Pubkey,
-//     pub src_asset: String,
-//     pub amount: u64,
-//     pub timelock: u64,
-//     pub token_contract: Pubkey,
-// }
-
-// #[event]
-// pub struct TokenRedeemed {
-//     #[index]
-//     Id: [u8; 32],
-//     redeem_address: Pubkey,
-// }
-#[error_code]
-pub enum HTLCError {
-    #[msg("Not Future TimeLock.")]
-    NotFutureTimeLock,
-    #[msg("Not Past TimeLock.")]
-    NotPastTimeLock,
-    #[msg("Hashlock Is Not Set.")]
-    HashlockNotSet,
-    #[msg("Does Not Match the Hashlock.")]
-    HashlockNoMatch,
-    #[msg("Hashlock Already Set.")]
-    HashlockAlreadySet,
-    #[msg("Funds Are Alredy Redeemed.")]
-    AlreadyRedeemed,
-    #[msg("Funds Are Alredy Refunded.")]
-    AlreadyRefunded,
-    #[msg("Funds Can Not Be Zero.")]
-    FundsNotSent,
-    #[msg("Unauthorized Access.")]
-    UnauthorizedAccess,
-    #[msg("Not The Owner.")]
-    NotOwner,
-    #[msg("Not The Sender.")]
-    NotSender,
-    #[msg("Not The Reciever.")]
-    NotReciever,
-    #[msg("Wrong Token.")]
-    NoToken,
+
+#[event]
+pub struct FeeSet {
+    pub fee_bps: u16,
+}
+
+#[event]
+pub struct TimelockWindowSet {
+    pub min_window: u64,
+    pub max_window: u64,
+}
+
+#[event]
+pub struct FeesWithdrawn {
+    pub token_contract: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct MintAllowed {
+    pub token_contract: Pubkey,
+    pub src_asset: String,
+}
+
+#[event]
+pub struct MintRemoved {
+    pub token_contract: Pubkey,
+}
+
+train_htlc_core::declare_program_error!();
+train_htlc_core::declare_program_status!();
+train_htlc_core::declare_program_hash_algorithm!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(len: usize) -> String {
+        "a".repeat(len)
+    }
+
+    #[test]
+    fn space_matches_serialized_htlc_at_max_lengths() {
+        let htlc = HTLC {
+            dst_address: text(MAX_ADDRESS_LEN),
+            dst_chain: text(MAX_CHAIN_LEN),
+            dst_asset: text(MAX_ASSET_LEN),
+            src_asset: text(MAX_ASSET_LEN),
+            hop_chains: vec![text(MAX_HOP_LEN); MAX_HOPS],
+            hop_assets: vec![text(MAX_HOP_LEN); MAX_HOPS],
+            hop_addresses: vec![text(MAX_HOP_LEN); MAX_HOPS],
+            ..Default::default()
+        };
+        let space = HTLC::space(
+            &htlc.dst_chain,
+            &htlc.dst_asset,
+            &htlc.dst_address,
+            &htlc.src_asset,
+        ) + hop_route_space(&htlc.hop_chains, &htlc.hop_assets, &htlc.hop_addresses);
+
+        assert_eq!(space, 8 + htlc.try_to_vec().unwrap().len());
+    }
+
+    #[test]
+    fn space_matches_serialized_empty_htlc() {
+        let space = HTLC::space("", "", "", "") + hop_route_space(&[], &[], &[]);
+
+        assert_eq!(space, 8 + HTLC::default().try_to_vec().unwrap().len());
+    }
+
+    #[test]
+    fn config_len_matches_serialized_config() {
+        let config = Config {
+            pending_admin: Some(Pubkey::default()),
+            ..Config::default()
+        };
+
+        assert_eq!(Config::LEN, 8 + config.try_to_vec().unwrap().len());
+    }
+
+    #[test]
+    fn allowed_mint_len_matches_the_longest_src_asset() {
+        let allowed_mint = AllowedMint {
+            src_asset: text(MAX_ASSET_LEN),
+        };
+
+        assert_eq!(
+            AllowedMint::LEN,
+            8 + allowed_mint.try_to_vec().unwrap().len()
+        );
+    }
+
+    #[test]
+    fn statuses_serialize_in_core_order() {
+        let statuses = [
+            HtlcStatus::Committed,
+            HtlcStatus::Locked,
+            HtlcStatus::Redeemed,
+            HtlcStatus::Refunded,
+        ];
+        for (index, status) in statuses.into_iter().enumerate() {
+            let program_status = HTLCStatus::from(status);
+
+            assert_eq!(program_status.try_to_vec().unwrap(), vec![index as u8]);
+            assert_eq!(HtlcStatus::from(program_status), status);
+        }
+    }
+
+    #[test]
+    fn hash_algorithms_match_core() {
+        let secret = [7u8; 32];
+        for algorithm in [
+            train_htlc_core::HashAlgorithm::Sha256,
+            train_htlc_core::HashAlgorithm::Keccak256,
+        ] {
+            let program_algorithm = HashAlgorithm::from(algorithm);
+
+            assert_eq!(
+                program_algorithm.try_to_vec().unwrap(),
+                vec![algorithm.tag()]
+            );
+            assert_eq!(
+                train_htlc_core::HashAlgorithm::from(program_algorithm),
+                algorithm
+            );
+            assert_eq!(
+                hash_secret(program_algorithm, &secret),
+                algorithm.hash(&secret)
+            );
+        }
+    }
+
+    #[test]
+    fn error_codes_match_core() {
+        for error in train_htlc_core::HtlcError::ALL {
+            let program_error = HTLCError::from(*error);
+
+            assert_eq!(u32::from(program_error), error.code());
+            assert_eq!(program_error.name(), error.name());
+            assert_eq!(program_error.to_string(), error.msg());
+        }
+    }
 }
@@ -0,0 +1,158 @@
+/// Anchor numbers a program's custom errors from this code up, in declaration order.
+pub const ERROR_CODE_OFFSET: u32 = 6000;
+
+/// Expands `$callback! { Name => "Message.", ... }` with every HTLC error, in code
+/// order. This is the one list both [`HtlcError`] and the programs' `HTLCError`
+/// are declared from, so new errors go at the end to keep the existing codes.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __htlc_errors {
+    ($($callback:tt)+) => {
+        $($callback)+! {
+            NotFutureTimeLock => "Not Future TimeLock.",
+            NotPastTimeLock => "Not Past TimeLock.",
+            HashlockNotSet => "Hashlock Is Not Set.",
+            HashlockNoMatch => "Does Not Match the Hashlock.",
+            HashlockAlreadySet => "Hashlock Already Set.",
+            AlreadyRedeemed => "Funds Are Alredy Redeemed.",
+            AlreadyRefunded => "Funds Are Alredy Refunded.",
+            FundsNotSent => "Funds Can Not Be Zero.",
+            UnauthorizedAccess => "Unauthorized Access.",
+            NotOwner => "Not The Owner.",
+            NotSender => "Not The Sender.",
+            NotReciever => "Not The Reciever.",
+            NoToken => "Wrong Token.",
+            InvalidSignature => "Invalid Signature.",
+            InvalidRewardTimelock => "Invalid Reward TimeLock.",
+            NotPayer => "Not The Payer.",
+            HopRouteMismatch => "Hop Chains, Assets And Addresses Differ In Length.",
+            TooManyHops => "Too Many Hops.",
+            HopTooLong => "Hop Is Too Long.",
+            InvalidCommitId => "Invalid Commit Id.",
+            NotLegacyHTLC => "Not A Legacy HTLC.",
+            DstChainTooLong => "Destination Chain Is Too Long.",
+            DstAssetTooLong => "Destination Asset Is Too Long.",
+            DstAddressTooLong => "Destination Address Is Too Long.",
+            SrcAssetTooLong => "Source Asset Is Too Long.",
+            BatchTooLarge => "Batch Is Too Large.",
+            BatchAccountsMismatch => "Batch Accounts Do Not Match The Items.",
+            Paused => "Deposits Are Paused.",
+            NotAdmin => "Not The Admin.",
+            NotPendingAdmin => "Not The Pending Admin.",
+            FeeTooHigh => "Fee Too High.",
+            MintNotAllowed => "Mint Is Not Allowed.",
+            SrcAssetMismatch => "Source Asset Does Not Match The Mint.",
+            TimelockTooShort => "TimeLock Is Too Short.",
+            TimelockTooLong => "TimeLock Is Too Long.",
+            InvalidTimelockWindow => "Invalid TimeLock Window.",
+            TimelockNotExtended => "TimeLock Is Not Extended.",
+            RewardAccountMissing => "Reward Token Account Is Missing.",
+            FeeVaultMissing => "Fee Vault Is Missing.",
+            NotUpgradeAuthority => "Not The Upgrade Authority.",
+        }
+    };
+}
+
+/// Declares the program's `#[error_code] pub enum HTLCError` from the shared error
+/// list, together with `From<HtlcError>`, so both programs report the same codes
+/// and the errors stay in each program's IDL.
+#[macro_export]
+macro_rules! declare_program_error {
+    () => {
+        $crate::__htlc_errors!($crate::__program_error);
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __program_error {
+    ($($name:ident => $msg:tt,)*) => {
+        #[error_code]
+        pub enum HTLCError {
+            $(
+                #[msg($msg)]
+                $name,
+            )*
+        }
+
+        impl From<$crate::HtlcError> for HTLCError {
+            fn from(error: $crate::HtlcError) -> Self {
+                match error {
+                    $($crate::HtlcError::$name => HTLCError::$name,)*
+                }
+            }
+        }
+    };
+}
+
+macro_rules! core_error {
+    ($($name:ident => $msg:literal,)*) => {
+        /// Errors of the HTLC rules, numbered like the programs' `HTLCError`.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        #[repr(u32)]
+        pub enum HtlcError {
+            $($name,)*
+        }
+
+        impl HtlcError {
+            /// Every error, in code order.
+            pub const ALL: &'static [HtlcError] = &[$(HtlcError::$name,)*];
+
+            /// The code the programs fail with for this error.
+            pub fn code(self) -> u32 {
+                ERROR_CODE_OFFSET + self as u32
+            }
+
+            /// The error for a program error code, if it is one of ours.
+            pub fn from_code(code: u32) -> Option<Self> {
+                let index = code.checked_sub(ERROR_CODE_OFFSET)?;
+                Self::ALL.get(index as usize).copied()
+            }
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(HtlcError::$name => stringify!($name),)*
+                }
+            }
+
+            pub fn msg(self) -> &'static str {
+                match self {
+                    $(HtlcError::$name => $msg,)*
+                }
+            }
+        }
+    };
+}
+
+__htlc_errors!(core_error);
+
+impl core::fmt::Display for HtlcError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.msg())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_follow_declaration_order() {
+        assert_eq!(HtlcError::NotFutureTimeLock.code(), 6000);
+        assert_eq!(HtlcError::NoToken.code(), 6012);
+        for (index, error) in HtlcError::ALL.iter().enumerate() {
+            assert_eq!(error.code(), ERROR_CODE_OFFSET + index as u32);
+            assert_eq!(HtlcError::from_code(error.code()), Some(*error));
+        }
+    }
+
+    #[test]
+    fn unknown_codes_are_not_htlc_errors() {
+        assert_eq!(HtlcError::from_code(0), None);
+        assert_eq!(HtlcError::from_code(5999), None);
+        assert_eq!(
+            HtlcError::from_code(ERROR_CODE_OFFSET + HtlcError::ALL.len() as u32),
+            None
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use sha2::{Digest, Sha256};
+use sha3::Keccak256;
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// The hash an HTLC's hashlock is taken with, recorded on the HTLC so a swap
+/// can use whichever hash the counterpart chain supports natively.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Keccak256,
+}
+
+impl HashAlgorithm {
+    /// The byte the programs serialize and the addLockSig message carries.
+    pub fn tag(self) -> u8 {
+        self as u8
+    }
+
+    /// Hashes on the host. The programs hash with the matching syscall instead.
+    pub fn hash(self, data: &[u8]) -> [u8; 32] {
+        match self {
+            HashAlgorithm::Sha256 => sha256(data),
+            HashAlgorithm::Keccak256 => keccak256(data),
+        }
+    }
+}
+
+/// Declares the program's `HashAlgorithm`, the Anchor-serializable twin of
+/// [`HashAlgorithm`], with conversions both ways.
+#[macro_export]
+macro_rules! declare_program_hash_algorithm {
+    () => {
+        $crate::__program_enum! { HashAlgorithm: Sha256, Keccak256 }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_matches_known_digest() {
+        // sha256("abc"), FIPS 180-2 appendix B.1.
+        assert_eq!(
+            sha256(b"abc"),
+            [
+                0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d, 0xae,
+                0x22, 0x23, 0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10, 0xff, 0x61,
+                0xf2, 0x00, 0x15, 0xad,
+            ]
+        );
+    }
+
+    #[test]
+    fn keccak256_matches_known_digest() {
+        // keccak256(""), the Ethereum empty-input hash.
+        assert_eq!(
+            keccak256(b""),
+            [
+                0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7,
+                0x03, 0xc0, 0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04,
+                0x5d, 0x85, 0xa4, 0x70,
+            ]
+        );
+    }
+
+    #[test]
+    fn algorithms_hash_and_tag_distinctly() {
+        assert_eq!(HashAlgorithm::Sha256.hash(b"abc"), sha256(b"abc"));
+        assert_eq!(HashAlgorithm::Keccak256.hash(b"abc"), keccak256(b"abc"));
+        assert_eq!(HashAlgorithm::Sha256.tag(), 0);
+        assert_eq!(HashAlgorithm::Keccak256.tag(), 1);
+    }
+}
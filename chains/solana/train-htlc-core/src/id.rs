@@ -0,0 +1,92 @@
+use sha2::{Digest, Sha256};
+
+use crate::HashAlgorithm;
+
+/// Length of the message the sender signs for addLockSig.
+pub const ADD_LOCK_SIG_MESSAGE_LEN: usize = 32 + 32 + 32 + 8 + 1;
+
+/// Derives the commit Id from the commit terms and the sender's nonce:
+/// sha256(program_id || sender || src_receiver || amount || timelock || nonce),
+/// with the integers as big-endian u64.
+pub fn derive_commit_id(
+    program_id: &[u8; 32],
+    sender: &[u8; 32],
+    src_receiver: &[u8; 32],
+    amount: u64,
+    timelock: u64,
+    nonce: u64,
+) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(program_id);
+    hasher.update(sender);
+    hasher.update(src_receiver);
+    hasher.update(amount.to_be_bytes());
+    hasher.update(timelock.to_be_bytes());
+    hasher.update(nonce.to_be_bytes());
+    hasher.finalize().into()
+}
+
+/// The message the sender signs to let anyone call addLockSig:
+/// program_id || Id || hashlock || timelock as big-endian u64 || hash algorithm tag.
+#[allow(non_snake_case)]
+pub fn add_lock_sig_message(
+    program_id: &[u8; 32],
+    Id: &[u8; 32],
+    hashlock: &[u8; 32],
+    timelock: u64,
+    hash_algorithm: HashAlgorithm,
+) -> [u8; ADD_LOCK_SIG_MESSAGE_LEN] {
+    let mut message = [0u8; ADD_LOCK_SIG_MESSAGE_LEN];
+    message[..32].copy_from_slice(program_id);
+    message[32..64].copy_from_slice(Id);
+    message[64..96].copy_from_slice(hashlock);
+    message[96..104].copy_from_slice(&timelock.to_be_bytes());
+    message[104] = hash_algorithm.tag();
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256;
+
+    #[test]
+    fn commit_id_hashes_the_terms_in_order() {
+        let mut preimage = [0u8; 32 * 3 + 8 * 3];
+        preimage[..32].copy_from_slice(&[1u8; 32]);
+        preimage[32..64].copy_from_slice(&[2u8; 32]);
+        preimage[64..96].copy_from_slice(&[3u8; 32]);
+        preimage[96..104].copy_from_slice(&10u64.to_be_bytes());
+        preimage[104..112].copy_from_slice(&20u64.to_be_bytes());
+        preimage[112..].copy_from_slice(&30u64.to_be_bytes());
+
+        assert_eq!(
+            derive_commit_id(&[1u8; 32], &[2u8; 32], &[3u8; 32], 10, 20, 30),
+            sha256(&preimage)
+        );
+    }
+
+    #[test]
+    fn commit_id_changes_with_the_nonce() {
+        let id = |nonce| derive_commit_id(&[1u8; 32], &[2u8; 32], &[3u8; 32], 10, 20, nonce);
+
+        assert_ne!(id(0), id(1));
+    }
+
+    #[test]
+    fn add_lock_sig_message_layout() {
+        let message = add_lock_sig_message(
+            &[1u8; 32],
+            &[2u8; 32],
+            &[3u8; 32],
+            0x0102,
+            HashAlgorithm::Keccak256,
+        );
+
+        assert_eq!(message[..32], [1u8; 32]);
+        assert_eq!(message[32..64], [2u8; 32]);
+        assert_eq!(message[64..96], [3u8; 32]);
+        assert_eq!(message[96..104], [0, 0, 0, 0, 0, 0, 1, 2]);
+        assert_eq!(message[104], 1);
+    }
+}
@@ -0,0 +1,65 @@
+//! HTLC state machine, hashing, Id derivation and error codes shared by the
+//! Train Solana programs (native_htlc and anchor_htlc).
+//!
+//! The crate is `no_std` and knows nothing about Solana accounts: keys are plain
+//! 32-byte arrays and clock readings plain integers, so every rule the programs
+//! enforce can be unit tested on the host.
+#![no_std]
+
+#[cfg(test)]
+extern crate std;
+
+mod error;
+mod hash;
+mod id;
+mod state;
+mod validate;
+
+pub use error::{HtlcError, ERROR_CODE_OFFSET};
+pub use hash::{keccak256, sha256, HashAlgorithm};
+pub use id::{add_lock_sig_message, derive_commit_id, ADD_LOCK_SIG_MESSAGE_LEN};
+pub use state::{
+    check_fee_bps, check_future_timelock, check_reward_timelock, check_timelock_window,
+    check_timelock_window_bounds, protocol_fee, redeem_payout, HtlcState, HtlcStatus, Payout,
+    DEFAULT_MAX_TIMELOCK_WINDOW, DEFAULT_MIN_TIMELOCK_WINDOW, EMPTY_HASHLOCK, MAX_FEE_BPS,
+};
+pub use validate::{
+    validate_batch, validate_hop_route, validate_htlc_strings, MAX_ADDRESS_LEN, MAX_ASSET_LEN,
+    MAX_BATCH_LEN, MAX_CHAIN_LEN, MAX_HOPS, MAX_HOP_LEN,
+};
+
+/// Declares a program enum mirroring one of the core enums, deriving the
+/// Anchor traits so it can live in accounts and the IDL, with `From` both ways.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __program_enum {
+    ($program:ident => $core:ident: $first:ident, $($name:ident),*) => {
+        #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+        pub enum $program {
+            #[default]
+            $first,
+            $($name,)*
+        }
+
+        impl From<$crate::$core> for $program {
+            fn from(value: $crate::$core) -> Self {
+                match value {
+                    $crate::$core::$first => $program::$first,
+                    $($crate::$core::$name => $program::$name,)*
+                }
+            }
+        }
+
+        impl From<$program> for $crate::$core {
+            fn from(value: $program) -> Self {
+                match value {
+                    $program::$first => $crate::$core::$first,
+                    $($program::$name => $crate::$core::$name,)*
+                }
+            }
+        }
+    };
+    ($name:ident: $($variants:tt)*) => {
+        $crate::__program_enum! { $name => $name: $($variants)* }
+    };
+}
@@ -0,0 +1,543 @@
+use crate::{HashAlgorithm, HtlcError};
+
+/// Hashlock of a committed HTLC that has not been locked yet.
+pub const EMPTY_HASHLOCK: [u8; 32] = [0u8; 32];
+
+/// Where an HTLC is in its lifecycle.
+///
+/// An HTLC starts out committed (by `commit`, without a hashlock) or locked (by
+/// `lock`). A committed HTLC becomes locked once its hashlock is added, and an
+/// open one ends up either redeemed (with the secret) or refunded (after the
+/// timelock). Redeemed and refunded HTLCs accept no further transition. The
+/// programs close an HTLC's account as it is redeemed or refunded, so these
+/// two final states are only reported by the redeem and refund events, and a
+/// closed account means the HTLC is finished.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HtlcStatus {
+    #[default]
+    Committed,
+    Locked,
+    Redeemed,
+    Refunded,
+}
+
+impl HtlcStatus {
+    /// The status of an account that predates the status field and tracks its
+    /// lifecycle with the redeemed / refunded flags and an empty hashlock.
+    pub fn from_flags(hashlock: &[u8; 32], redeemed: bool, refunded: bool) -> Self {
+        if redeemed {
+            HtlcStatus::Redeemed
+        } else if refunded {
+            HtlcStatus::Refunded
+        } else if *hashlock == EMPTY_HASHLOCK {
+            HtlcStatus::Committed
+        } else {
+            HtlcStatus::Locked
+        }
+    }
+
+    /// Whether the HTLC still holds its funds.
+    pub fn is_open(self) -> bool {
+        matches!(self, HtlcStatus::Committed | HtlcStatus::Locked)
+    }
+}
+
+/// Declares the program's `HTLCStatus`, the Anchor-serializable twin of
+/// [`HtlcStatus`], with conversions both ways, so the HTLC account and
+/// `getDetails` carry the status and it shows up in each program's IDL.
+#[macro_export]
+macro_rules! declare_program_status {
+    () => {
+        $crate::__program_enum! { HTLCStatus => HtlcStatus: Committed, Locked, Redeemed, Refunded }
+    };
+}
+
+/// The part of an HTLC its lifecycle depends on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HtlcState {
+    pub hashlock: [u8; 32],
+    pub hash_algorithm: HashAlgorithm,
+    pub timelock: u64,
+    pub status: HtlcStatus,
+}
+
+impl HtlcState {
+    /// Fails if the HTLC has already been redeemed or refunded.
+    pub fn check_open(&self) -> Result<(), HtlcError> {
+        match self.status {
+            HtlcStatus::Redeemed => Err(HtlcError::AlreadyRedeemed),
+            HtlcStatus::Refunded => Err(HtlcError::AlreadyRefunded),
+            HtlcStatus::Committed | HtlcStatus::Locked => Ok(()),
+        }
+    }
+
+    /// Locks a committed HTLC with the hashlock, the algorithm it was taken
+    /// with and a new timelock.
+    pub fn add_lock(
+        &mut self,
+        hashlock: [u8; 32],
+        hash_algorithm: HashAlgorithm,
+        timelock: u64,
+        now: i64,
+    ) -> Result<(), HtlcError> {
+        self.check_open()?;
+        if self.status == HtlcStatus::Locked {
+            return Err(HtlcError::HashlockAlreadySet);
+        }
+        check_future_timelock(timelock, now)?;
+        self.hashlock = hashlock;
+        self.hash_algorithm = hash_algorithm;
+        self.timelock = timelock;
+        self.status = HtlcStatus::Locked;
+        Ok(())
+    }
+
+    /// Moves the timelock of an open HTLC later; it is never shortened.
+    pub fn extend_timelock(&mut self, timelock: u64, now: i64) -> Result<(), HtlcError> {
+        self.check_open()?;
+        if timelock <= self.timelock {
+            return Err(HtlcError::TimelockNotExtended);
+        }
+        check_future_timelock(timelock, now)?;
+        self.timelock = timelock;
+        Ok(())
+    }
+
+    /// Redeems a locked HTLC given its secret hashed with `hash_algorithm`,
+    /// which has to equal the hashlock.
+    pub fn redeem(&mut self, secret_hash: &[u8; 32]) -> Result<(), HtlcError> {
+        self.check_open()?;
+        if self.status == HtlcStatus::Committed {
+            return Err(HtlcError::HashlockNotSet);
+        }
+        if *secret_hash != self.hashlock {
+            return Err(HtlcError::HashlockNoMatch);
+        }
+        self.status = HtlcStatus::Redeemed;
+        Ok(())
+    }
+
+    /// Refunds an HTLC whose timelock has passed.
+    pub fn refund(&mut self, now: i64) -> Result<(), HtlcError> {
+        self.check_open()?;
+        if i128::from(now) < i128::from(self.timelock) {
+            return Err(HtlcError::NotPastTimeLock);
+        }
+        self.status = HtlcStatus::Refunded;
+        Ok(())
+    }
+}
+
+/// Fails unless the timelock is after `now`.
+pub fn check_future_timelock(timelock: u64, now: i64) -> Result<(), HtlcError> {
+    if i128::from(timelock) <= i128::from(now) {
+        return Err(HtlcError::NotFutureTimeLock);
+    }
+    Ok(())
+}
+
+/// The shortest timelock window a new Config starts with, in seconds: the
+/// 1800 seconds EVM `Train.lock` requires.
+pub const DEFAULT_MIN_TIMELOCK_WINDOW: u64 = 1_800;
+
+/// The longest timelock window a new Config starts with, in seconds: 30 days.
+pub const DEFAULT_MAX_TIMELOCK_WINDOW: u64 = 30 * 24 * 60 * 60;
+
+/// Fails unless the timelock is at least `min_window` and at most `max_window`
+/// seconds after `now`.
+pub fn check_timelock_window(
+    timelock: u64,
+    now: i64,
+    min_window: u64,
+    max_window: u64,
+) -> Result<(), HtlcError> {
+    let window = i128::from(timelock) - i128::from(now);
+    if window < i128::from(min_window) {
+        return Err(HtlcError::TimelockTooShort);
+    }
+    if window > i128::from(max_window) {
+        return Err(HtlcError::TimelockTooLong);
+    }
+    Ok(())
+}
+
+/// Fails unless the minimum timelock window is no longer than the maximum.
+pub fn check_timelock_window_bounds(min_window: u64, max_window: u64) -> Result<(), HtlcError> {
+    if min_window > max_window {
+        return Err(HtlcError::InvalidTimelockWindow);
+    }
+    Ok(())
+}
+
+/// Fails unless a non-zero reward unlocks after `now` and no later than the timelock.
+pub fn check_reward_timelock(
+    reward: u64,
+    reward_timelock: u64,
+    timelock: u64,
+    now: i64,
+) -> Result<(), HtlcError> {
+    if reward != 0 && (reward_timelock > timelock || i128::from(reward_timelock) <= i128::from(now))
+    {
+        return Err(HtlcError::InvalidRewardTimelock);
+    }
+    Ok(())
+}
+
+/// The highest protocol fee, 10% of the amount.
+pub const MAX_FEE_BPS: u16 = 1_000;
+
+/// Checks a protocol fee in basis points against MAX_FEE_BPS.
+pub fn check_fee_bps(fee_bps: u16) -> Result<(), HtlcError> {
+    if fee_bps > MAX_FEE_BPS {
+        return Err(HtlcError::FeeTooHigh);
+    }
+    Ok(())
+}
+
+/// The protocol fee on `amount`, rounded down.
+pub fn protocol_fee(amount: u64, fee_bps: u16) -> u64 {
+    (u128::from(amount) * u128::from(fee_bps) / 10_000) as u64
+}
+
+/// How the amount and reward of a redeemed HTLC are paid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Payout {
+    pub src_receiver: u64,
+    pub sender: u64,
+    pub redeemer: u64,
+    /// The protocol fee, for the fee vault.
+    pub fee: u64,
+}
+
+/// Splits a redeem: the amount always goes to the src_receiver, less the
+/// protocol fee of `fee_bps` on it. The reward goes back to the sender before
+/// reward_timelock and to the redeemer from then on, and is never charged.
+pub fn redeem_payout(
+    amount: u64,
+    reward: u64,
+    reward_timelock: u64,
+    now: i64,
+    redeemer_is_src_receiver: bool,
+    fee_bps: u16,
+) -> Payout {
+    let fee = protocol_fee(amount, fee_bps);
+    let amount = amount - fee;
+    if reward == 0 {
+        Payout {
+            src_receiver: amount,
+            fee,
+            ..Payout::default()
+        }
+    } else if i128::from(reward_timelock) > i128::from(now) {
+        Payout {
+            src_receiver: amount,
+            sender: reward,
+            fee,
+            ..Payout::default()
+        }
+    } else if redeemer_is_src_receiver {
+        Payout {
+            src_receiver: amount + reward,
+            fee,
+            ..Payout::default()
+        }
+    } else {
+        Payout {
+            src_receiver: amount,
+            redeemer: reward,
+            fee,
+            ..Payout::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256;
+
+    const SECRET: [u8; 32] = [9u8; 32];
+
+    fn secret_hash() -> [u8; 32] {
+        sha256(&SECRET)
+    }
+
+    fn committed() -> HtlcState {
+        HtlcState {
+            timelock: 100,
+            ..HtlcState::default()
+        }
+    }
+
+    fn locked() -> HtlcState {
+        HtlcState {
+            hashlock: secret_hash(),
+            timelock: 100,
+            status: HtlcStatus::Locked,
+            ..HtlcState::default()
+        }
+    }
+
+    #[test]
+    fn add_lock_sets_hashlock_and_timelock_once() {
+        let mut state = committed();
+        state
+            .add_lock([1u8; 32], HashAlgorithm::Keccak256, 200, 50)
+            .unwrap();
+
+        assert_eq!(state.hashlock, [1u8; 32]);
+        assert_eq!(state.hash_algorithm, HashAlgorithm::Keccak256);
+        assert_eq!(state.timelock, 200);
+        assert_eq!(state.status, HtlcStatus::Locked);
+        assert_eq!(
+            state.add_lock([2u8; 32], HashAlgorithm::Sha256, 300, 50),
+            Err(HtlcError::HashlockAlreadySet)
+        );
+    }
+
+    #[test]
+    fn add_lock_needs_a_future_timelock() {
+        assert_eq!(
+            committed().add_lock([1u8; 32], HashAlgorithm::Sha256, 50, 50),
+            Err(HtlcError::NotFutureTimeLock)
+        );
+    }
+
+    #[test]
+    fn extend_timelock_only_moves_the_timelock_later() {
+        let mut state = locked();
+        state.extend_timelock(200, 50).unwrap();
+        assert_eq!(state.timelock, 200);
+        assert_eq!(state.status, HtlcStatus::Locked);
+
+        assert_eq!(
+            state.extend_timelock(200, 50),
+            Err(HtlcError::TimelockNotExtended)
+        );
+        assert_eq!(
+            state.extend_timelock(150, 50),
+            Err(HtlcError::TimelockNotExtended)
+        );
+        assert_eq!(
+            state.extend_timelock(250, 250),
+            Err(HtlcError::NotFutureTimeLock)
+        );
+        assert_eq!(committed().extend_timelock(200, 150), Ok(()));
+    }
+
+    #[test]
+    fn redeem_needs_the_secret() {
+        let mut state = locked();
+
+        assert_eq!(state.redeem(&[0u8; 32]), Err(HtlcError::HashlockNoMatch));
+        assert_eq!(
+            committed().redeem(&secret_hash()),
+            Err(HtlcError::HashlockNotSet)
+        );
+        state.redeem(&secret_hash()).unwrap();
+        assert_eq!(state.status, HtlcStatus::Redeemed);
+    }
+
+    #[test]
+    fn refund_waits_for_the_timelock() {
+        let mut state = locked();
+
+        assert_eq!(state.refund(99), Err(HtlcError::NotPastTimeLock));
+        state.refund(100).unwrap();
+        assert_eq!(state.status, HtlcStatus::Refunded);
+    }
+
+    #[test]
+    fn an_all_zero_hashlock_is_a_real_hashlock() {
+        let mut state = committed();
+        state
+            .add_lock(EMPTY_HASHLOCK, HashAlgorithm::Sha256, 200, 50)
+            .unwrap();
+
+        assert_eq!(
+            state.add_lock([1u8; 32], HashAlgorithm::Sha256, 300, 50),
+            Err(HtlcError::HashlockAlreadySet)
+        );
+        assert_eq!(
+            state.redeem(&secret_hash()),
+            Err(HtlcError::HashlockNoMatch)
+        );
+    }
+
+    #[test]
+    fn committed_htlcs_refund_but_do_not_redeem() {
+        let mut state = committed();
+        state.refund(100).unwrap();
+        assert_eq!(state.status, HtlcStatus::Refunded);
+    }
+
+    #[test]
+    fn flags_map_to_a_status() {
+        let hashlock = [1u8; 32];
+        assert_eq!(
+            HtlcStatus::from_flags(&EMPTY_HASHLOCK, false, false),
+            HtlcStatus::Committed
+        );
+        assert_eq!(
+            HtlcStatus::from_flags(&hashlock, false, false),
+            HtlcStatus::Locked
+        );
+        assert_eq!(
+            HtlcStatus::from_flags(&hashlock, true, false),
+            HtlcStatus::Redeemed
+        );
+        assert_eq!(
+            HtlcStatus::from_flags(&EMPTY_HASHLOCK, false, true),
+            HtlcStatus::Refunded
+        );
+        assert!(HtlcStatus::Locked.is_open());
+        assert!(!HtlcStatus::Redeemed.is_open());
+    }
+
+    #[test]
+    fn closed_htlcs_accept_no_transition() {
+        let mut redeemed = locked();
+        redeemed.redeem(&secret_hash()).unwrap();
+        let mut refunded = locked();
+        refunded.refund(100).unwrap();
+
+        assert_eq!(redeemed.refund(100), Err(HtlcError::AlreadyRedeemed));
+        assert_eq!(
+            redeemed.redeem(&secret_hash()),
+            Err(HtlcError::AlreadyRedeemed)
+        );
+        assert_eq!(
+            refunded.redeem(&secret_hash()),
+            Err(HtlcError::AlreadyRefunded)
+        );
+        assert_eq!(
+            refunded.add_lock([1u8; 32], HashAlgorithm::Sha256, 200, 50),
+            Err(HtlcError::AlreadyRefunded)
+        );
+        assert_eq!(
+            redeemed.extend_timelock(200, 50),
+            Err(HtlcError::AlreadyRedeemed)
+        );
+    }
+
+    #[test]
+    fn timelocks_compare_against_negative_clocks() {
+        assert_eq!(check_future_timelock(0, -1), Ok(()));
+        assert_eq!(locked().refund(-1), Err(HtlcError::NotPastTimeLock));
+    }
+
+    #[test]
+    fn timelocks_must_fall_within_the_window() {
+        assert_eq!(check_timelock_window(1_800, 0, 1_800, 3_600), Ok(()));
+        assert_eq!(check_timelock_window(3_600, 0, 1_800, 3_600), Ok(()));
+        assert_eq!(
+            check_timelock_window(1_799, 0, 1_800, 3_600),
+            Err(HtlcError::TimelockTooShort)
+        );
+        assert_eq!(
+            check_timelock_window(3_601, 0, 1_800, 3_600),
+            Err(HtlcError::TimelockTooLong)
+        );
+        assert_eq!(
+            check_timelock_window(0, 10, 0, 3_600),
+            Err(HtlcError::TimelockTooShort)
+        );
+        assert_eq!(check_timelock_window(u64::MAX, 0, 0, u64::MAX), Ok(()));
+    }
+
+    #[test]
+    fn timelock_window_bounds_must_be_ordered() {
+        assert_eq!(check_timelock_window_bounds(0, 0), Ok(()));
+        assert_eq!(
+            check_timelock_window_bounds(DEFAULT_MIN_TIMELOCK_WINDOW, DEFAULT_MAX_TIMELOCK_WINDOW),
+            Ok(())
+        );
+        assert_eq!(
+            check_timelock_window_bounds(2, 1),
+            Err(HtlcError::InvalidTimelockWindow)
+        );
+    }
+
+    #[test]
+    fn reward_timelock_must_fall_between_now_and_timelock() {
+        assert_eq!(check_reward_timelock(0, 0, 100, 50), Ok(()));
+        assert_eq!(check_reward_timelock(1, 100, 100, 50), Ok(()));
+        assert_eq!(
+            check_reward_timelock(1, 101, 100, 50),
+            Err(HtlcError::InvalidRewardTimelock)
+        );
+        assert_eq!(
+            check_reward_timelock(1, 50, 100, 50),
+            Err(HtlcError::InvalidRewardTimelock)
+        );
+    }
+
+    #[test]
+    fn reward_goes_to_sender_then_redeemer() {
+        let payout = |now, redeemer_is_src_receiver| {
+            redeem_payout(10, 3, 60, now, redeemer_is_src_receiver, 0)
+        };
+
+        assert_eq!(
+            redeem_payout(10, 0, 0, 70, false, 0),
+            Payout {
+                src_receiver: 10,
+                ..Payout::default()
+            }
+        );
+        assert_eq!(
+            payout(59, false),
+            Payout {
+                src_receiver: 10,
+                sender: 3,
+                redeemer: 0,
+                fee: 0
+            }
+        );
+        assert_eq!(
+            payout(60, false),
+            Payout {
+                src_receiver: 10,
+                sender: 0,
+                redeemer: 3,
+                fee: 0
+            }
+        );
+        assert_eq!(
+            payout(60, true),
+            Payout {
+                src_receiver: 13,
+                ..Payout::default()
+            }
+        );
+    }
+
+    #[test]
+    fn fee_comes_out_of_the_amount_only() {
+        assert_eq!(
+            redeem_payout(10_000, 3, 60, 60, false, 25),
+            Payout {
+                src_receiver: 9_975,
+                redeemer: 3,
+                fee: 25,
+                ..Payout::default()
+            }
+        );
+        assert_eq!(
+            redeem_payout(10_000, 3, 60, 60, true, 25),
+            Payout {
+                src_receiver: 9_978,
+                fee: 25,
+                ..Payout::default()
+            }
+        );
+        assert_eq!(protocol_fee(399, 25), 0);
+        assert_eq!(protocol_fee(u64::MAX, MAX_FEE_BPS), u64::MAX / 10);
+    }
+
+    #[test]
+    fn fee_is_capped() {
+        assert_eq!(check_fee_bps(MAX_FEE_BPS), Ok(()));
+        assert_eq!(check_fee_bps(MAX_FEE_BPS + 1), Err(HtlcError::FeeTooHigh));
+    }
+}
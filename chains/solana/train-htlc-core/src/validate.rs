@@ -0,0 +1,172 @@
+use crate::HtlcError;
+
+/// Maximum length of dst_chain.
+pub const MAX_CHAIN_LEN: usize = 64;
+/// Maximum length of dst_asset and src_asset.
+pub const MAX_ASSET_LEN: usize = 64;
+/// Maximum length of dst_address.
+pub const MAX_ADDRESS_LEN: usize = 128;
+/// Maximum number of hops in the route passed to commit.
+pub const MAX_HOPS: usize = 5;
+/// Maximum length of each hop chain, asset and address.
+pub const MAX_HOP_LEN: usize = 128;
+/// Maximum number of HTLCs handled by one batch instruction. At 64 bytes of
+/// data per item, a larger batch would not fit a 1232-byte transaction even
+/// with its accounts in an address lookup table.
+pub const MAX_BATCH_LEN: usize = 10;
+
+/// Checks that the HTLC strings stay within their maximum lengths.
+pub fn validate_htlc_strings(
+    dst_chain: &str,
+    dst_asset: &str,
+    dst_address: &str,
+    src_asset: &str,
+) -> Result<(), HtlcError> {
+    if dst_chain.len() > MAX_CHAIN_LEN {
+        return Err(HtlcError::DstChainTooLong);
+    }
+    if dst_asset.len() > MAX_ASSET_LEN {
+        return Err(HtlcError::DstAssetTooLong);
+    }
+    if dst_address.len() > MAX_ADDRESS_LEN {
+        return Err(HtlcError::DstAddressTooLong);
+    }
+    if src_asset.len() > MAX_ASSET_LEN {
+        return Err(HtlcError::SrcAssetTooLong);
+    }
+    Ok(())
+}
+
+/// Checks that the hop route has one chain, asset and address per hop and stays
+/// within MAX_HOPS / MAX_HOP_LEN.
+pub fn validate_hop_route<S: AsRef<str>>(
+    hop_chains: &[S],
+    hop_assets: &[S],
+    hop_addresses: &[S],
+) -> Result<(), HtlcError> {
+    if hop_chains.len() != hop_assets.len() || hop_chains.len() != hop_addresses.len() {
+        return Err(HtlcError::HopRouteMismatch);
+    }
+    if hop_chains.len() > MAX_HOPS {
+        return Err(HtlcError::TooManyHops);
+    }
+    if hop_chains
+        .iter()
+        .chain(hop_assets)
+        .chain(hop_addresses)
+        .any(|hop| hop.as_ref().len() > MAX_HOP_LEN)
+    {
+        return Err(HtlcError::HopTooLong);
+    }
+    Ok(())
+}
+
+/// Checks that a batch stays within MAX_BATCH_LEN items and comes with exactly
+/// `accounts_per_item` accounts for each item.
+pub fn validate_batch(
+    items: usize,
+    accounts: usize,
+    accounts_per_item: usize,
+) -> Result<(), HtlcError> {
+    if items > MAX_BATCH_LEN {
+        return Err(HtlcError::BatchTooLarge);
+    }
+    if accounts != items * accounts_per_item {
+        return Err(HtlcError::BatchAccountsMismatch);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::String;
+    use std::vec;
+
+    fn text(len: usize) -> String {
+        "a".repeat(len)
+    }
+
+    #[test]
+    fn strings_at_max_length_are_accepted() {
+        assert_eq!(
+            validate_htlc_strings(
+                &text(MAX_CHAIN_LEN),
+                &text(MAX_ASSET_LEN),
+                &text(MAX_ADDRESS_LEN),
+                &text(MAX_ASSET_LEN),
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn strings_over_max_length_are_rejected() {
+        let chain = text(MAX_CHAIN_LEN);
+        let asset = text(MAX_ASSET_LEN);
+        let address = text(MAX_ADDRESS_LEN);
+
+        assert_eq!(
+            validate_htlc_strings(&text(MAX_CHAIN_LEN + 1), &asset, &address, &asset),
+            Err(HtlcError::DstChainTooLong)
+        );
+        assert_eq!(
+            validate_htlc_strings(&chain, &text(MAX_ASSET_LEN + 1), &address, &asset),
+            Err(HtlcError::DstAssetTooLong)
+        );
+        assert_eq!(
+            validate_htlc_strings(&chain, &asset, &text(MAX_ADDRESS_LEN + 1), &asset),
+            Err(HtlcError::DstAddressTooLong)
+        );
+        assert_eq!(
+            validate_htlc_strings(&chain, &asset, &address, &text(MAX_ASSET_LEN + 1)),
+            Err(HtlcError::SrcAssetTooLong)
+        );
+    }
+
+    #[test]
+    fn hop_route_at_max_size_is_accepted() {
+        let hops = vec![text(MAX_HOP_LEN); MAX_HOPS];
+
+        assert_eq!(validate_hop_route(&hops, &hops, &hops), Ok(()));
+        assert_eq!(validate_hop_route::<String>(&[], &[], &[]), Ok(()));
+    }
+
+    #[test]
+    fn hop_route_out_of_bounds_is_rejected() {
+        let hops = vec![text(1); 2];
+        let too_many = vec![text(1); MAX_HOPS + 1];
+        let too_long = vec![text(1), text(MAX_HOP_LEN + 1)];
+
+        assert_eq!(
+            validate_hop_route(&hops, &hops, &hops[..1]),
+            Err(HtlcError::HopRouteMismatch)
+        );
+        assert_eq!(
+            validate_hop_route(&too_many, &too_many, &too_many),
+            Err(HtlcError::TooManyHops)
+        );
+        assert_eq!(
+            validate_hop_route(&hops, &too_long, &hops),
+            Err(HtlcError::HopTooLong)
+        );
+    }
+
+    #[test]
+    fn batches_need_one_group_of_accounts_per_item() {
+        assert_eq!(validate_batch(0, 0, 5), Ok(()));
+        assert_eq!(validate_batch(MAX_BATCH_LEN, MAX_BATCH_LEN * 5, 5), Ok(()));
+        assert_eq!(
+            validate_batch(MAX_BATCH_LEN + 1, (MAX_BATCH_LEN + 1) * 5, 5),
+            Err(HtlcError::BatchTooLarge)
+        );
+        assert_eq!(
+            validate_batch(2, 9, 5),
+            Err(HtlcError::BatchAccountsMismatch)
+        );
+        assert_eq!(
+            validate_batch(2, 11, 5),
+            Err(HtlcError::BatchAccountsMismatch)
+        );
+    }
+}